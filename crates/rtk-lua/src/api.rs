@@ -1,8 +1,12 @@
+use std::collections::HashMap;
+
 use anyhow::Context;
 use mlua::{Either, FromLua, IntoLua, Lua};
+use rtk_lua_macros::RtkMeta;
 
 use crate::{
-    ext::TableSetFnExt, impl_enum_into_lua, impl_into_lua, versioning::RtkRustcDriverVersion,
+    ext::TableSetFnExt, impl_enum_into_lua, impl_from_lua, impl_into_lua, typescript,
+    versioning::RtkRustcDriverVersion,
 };
 
 pub trait RtkLuaScriptExecutor: Send + Sync + Clone + 'static {
@@ -14,17 +18,127 @@ pub trait RtkLuaScriptExecutor: Send + Sync + Clone + 'static {
         self.intake_version(version);
     }
 
+    /// The name of the crate being analyzed, as it appears in the `crate_name` of its locations
+    fn primary_crate_name(&self) -> String;
+    /// The version of the crate being analyzed, if cargo gave one
+    fn primary_crate_version(&self) -> Option<String>;
+
+    /// Query every call matching `query`, in the order they appear in the source: by file, then by
+    /// where the call starts in it
     fn query_method_calls(&self, query: MethodCallQuery) -> Vec<MethodCall>;
+    /// Run many method call queries at once, returning the results in the same order as the
+    /// queries. This lets the driver answer all of them in a single pass over the crate
+    fn query_method_calls_batch(&self, queries: Vec<MethodCallQuery>) -> Vec<Vec<MethodCall>>;
     fn query_trait_impls(&self, query: Location) -> Vec<TraitImpl>;
+    /// Like `query_trait_impls`, but also finding impls written in dependencies of the crate, e.g.
+    /// a library's impls of its own trait for std types
+    fn query_all_trait_impls(&self, query: Location) -> Vec<TraitImpl>;
+    /// The reverse of `query_trait_impls`, returning the location of every trait implemented for
+    /// the type at `query`
+    fn query_impls_for_type(&self, query: Location) -> Vec<Location>;
+    /// Query the declaration of the trait at `query` itself, rather than any of its impls
+    fn query_trait_defs(&self, query: Location) -> Vec<TraitDef>;
+    /// Query the function at the location. The location may point into a dependency of the crate
+    /// as well as the crate itself. Without an `impl_block_number`, functions of the same name
+    /// across a type's impl blocks come back as one, with the rest in its `overloads`
     fn query_functions(&self, query: Location) -> Vec<FunctionTypeValue>;
+    /// Query every function defined in the module at `module`, including those in its submodules
+    fn query_functions_in_module(&self, module: Location) -> Vec<FunctionTypeValue>;
+    /// Every call to the function at `query`. Associated functions like `Vec::new()` are located
+    /// by the module of their impl block rather than the type, e.g. `alloc::vec::new{impl#1}`, so
+    /// leaving out `impl_block_number` matches them in any impl block of that module. Trait
+    /// functions called through a type, like `String::from("x")`, resolve to the trait's
+    /// declaration (`core::convert::From::from`) rather than the impl
     fn query_function_calls(&self, query: Location) -> Vec<FunctionCall>;
+    /// Every struct expression constructing the struct (or enum struct variant) at `query`
+    fn query_struct_literals(&self, query: Location) -> Vec<StructLiteralSite>;
+    /// Every invocation of the bang macro called `name`, like `define_route!(...)`. Macros are
+    /// expanded by the time the crate is queried, so this is best-effort: invocations are only
+    /// found through the code they expand to, and ones expanding to nothing are missed
+    fn query_macro_calls(&self, name: String) -> Vec<MacroCall>;
+    /// The fields of the struct at `query` that serde serializes, under the names it serializes
+    /// them with. See [`SerdeField::of_struct`] for which of serde's attributes are followed
+    fn query_serde_fields(&self, query: Location) -> Vec<SerdeField>;
+    /// Every `const` item in the crate whose type matches `type_filter`, as decided by
+    /// [`TypeValue::matches_type_filter`]
+    fn query_constants_by_type(&self, type_filter: TypeValue) -> Vec<ConstantValue>;
+    /// Like `query_function_calls`, but only looking within the body of the function at
+    /// `outer_location`
+    fn query_function_calls_in_function(
+        &self,
+        outer_location: Location,
+        call_location: Location,
+    ) -> Vec<FunctionCall>;
+    /// Every closure within the body of the function at `within_location` that captures a variable
+    /// called `capture_name`, whether by reference or by value
+    fn query_closures_with_capture(
+        &self,
+        within_location: Location,
+        capture_name: String,
+    ) -> Vec<ClosureTypeValue>;
+    /// Every function call made from the items beneath the module at `module`, grouped by the
+    /// function called wherever it is defined. Each group's calls are in source order
+    fn query_function_calls_grouped(
+        &self,
+        module: Location,
+    ) -> HashMap<Location, Vec<FunctionCall>>;
+    /// The location of every module in the crate, starting with the crate root
+    fn query_modules(&self) -> Vec<Location>;
+
+    /// Count the functions `query_functions` would return. Executors backed by a crate should
+    /// override this to skip elevating the functions entirely
+    fn count_functions(&self, query: Location) -> usize {
+        self.query_functions(query).len()
+    }
+    /// Count the method calls `query_method_calls` would return. Executors backed by a crate
+    /// should override this to skip elevating the calls entirely
+    fn count_method_calls(&self, query: MethodCallQuery) -> usize {
+        self.query_method_calls(query).len()
+    }
 
     fn log_note(&self, msg: String);
     fn log_warn(&self, msg: String);
     fn log_error(&self, msg: String);
     fn log_fatal_error(&self, msg: String) -> !;
+    /// Abort like `log_fatal_error` if `log_error` has been called at any point during this run
+    fn abort_if_errors(&self);
+
+    /// How queries that match nothing are reported. Nothing is reported unless the script opts in
+    /// with `rtk.set_empty_query_behavior`
+    fn query_empty_behavior(&self) -> QueryEmptyBehavior {
+        QueryEmptyBehavior::Ignore
+    }
+    /// Intake the empty query behavior set by the script. Executors that never report empty
+    /// queries can ignore it
+    fn intake_query_empty_behavior(&self, _behavior: QueryEmptyBehavior) {}
+    /// Intake whether the script wants queries traced, set with `rtk.trace_enable()` and
+    /// `rtk.trace_disable()`. Executors with nothing worth timing can ignore it
+    fn intake_trace_enabled(&self, _enabled: bool) {}
+    /// Intake whether the script wants `#[doc(hidden)]` and non-public items included in queries,
+    /// set with `rtk.set_include_hidden`. Executors that can't tell hidden items apart can ignore
+    /// it
+    fn intake_include_hidden(&self, _include_hidden: bool) {}
+    /// Report that the `query_name` query for `query` matched nothing, as `query_empty_behavior`
+    /// asks
+    fn report_empty_query(&self, query_name: &str, query: &Location) {
+        let msg = format!(
+            "`{query_name}` matched nothing for `{query}`, this is usually a mistyped location"
+        );
+
+        match self.query_empty_behavior() {
+            QueryEmptyBehavior::Ignore => {}
+            QueryEmptyBehavior::WarnOnEmpty => self.log_warn(msg),
+            QueryEmptyBehavior::ErrorOnEmpty => self.log_error(msg),
+        }
+    }
 
     fn emit(&self, text: String);
+    /// Emit text with an explicit priority. Lower priorities are flushed to the output first, and
+    /// emits of equal priority keep the order they were made in. `emit` uses
+    /// [`crate::DEFAULT_EMIT_PRIORITY`]
+    fn emit_with_priority(&self, priority: i64, text: String);
+    /// Emit text like `emit`, unless the exact same text has already been emitted this run
+    fn emit_unique(&self, text: String);
 }
 
 /// Injects the full API into the table
@@ -84,6 +198,64 @@ pub fn inject(
         })
         .context("failed to set fatal_error function")?;
 
+    let set_empty_query_behavior_exec = exec.clone();
+    table
+        .set_rtk_api_fn(
+            lua,
+            "set_empty_query_behavior",
+            move |behavior: QueryEmptyBehavior| {
+                set_empty_query_behavior_exec.intake_query_empty_behavior(behavior);
+                mlua::Nil
+            },
+        )
+        .context("failed to set set_empty_query_behavior function")?;
+
+    let trace_enable_exec = exec.clone();
+    table
+        .set_rtk_api_fn(lua, "trace_enable", move |()| {
+            trace_enable_exec.intake_trace_enabled(true);
+            mlua::Nil
+        })
+        .context("failed to set trace_enable function")?;
+
+    let trace_disable_exec = exec.clone();
+    table
+        .set_rtk_api_fn(lua, "trace_disable", move |()| {
+            trace_disable_exec.intake_trace_enabled(false);
+            mlua::Nil
+        })
+        .context("failed to set trace_disable function")?;
+
+    let set_include_hidden_exec = exec.clone();
+    table
+        .set_rtk_api_fn(lua, "set_include_hidden", move |include_hidden: bool| {
+            set_include_hidden_exec.intake_include_hidden(include_hidden);
+            mlua::Nil
+        })
+        .context("failed to set set_include_hidden function")?;
+
+    let abort_if_errors_exec = exec.clone();
+    table
+        .set_rtk_api_fn(lua, "abort_if_errors", move |()| {
+            abort_if_errors_exec.abort_if_errors();
+            mlua::Nil
+        })
+        .context("failed to set abort_if_errors function")?;
+
+    let crate_name_exec = exec.clone();
+    table
+        .set_rtk_api_fn(lua, "crate_name", move |()| {
+            crate_name_exec.primary_crate_name()
+        })
+        .context("failed to set crate_name function")?;
+
+    let crate_version_exec = exec.clone();
+    table
+        .set_rtk_api_fn(lua, "crate_version", move |()| {
+            crate_version_exec.primary_crate_version()
+        })
+        .context("failed to set crate_version function")?;
+
     let query_method_calls_exec = exec.clone();
     table
         .set_rtk_api_fn(lua, "query_method_calls", move |query: MethodCallQuery| {
@@ -91,13 +263,77 @@ pub fn inject(
         })
         .context("failed to set query_method_calls function")?;
 
+    let query_method_calls_batch_exec = exec.clone();
+    table
+        .set_rtk_api_fn_with_lua(
+            lua,
+            "query_method_calls_batch",
+            move |lua, queries: mlua::Table| {
+                // keep hold of the keys so the results can be handed back under the same ones
+                let (keys, queries): (Vec<mlua::Value>, Vec<MethodCallQuery>) = queries
+                    .pairs::<mlua::Value, MethodCallQuery>()
+                    .collect::<mlua::Result<Vec<_>>>()?
+                    .into_iter()
+                    .unzip();
+
+                let results = lua.create_table()?;
+                for (key, calls) in keys
+                    .into_iter()
+                    .zip(query_method_calls_batch_exec.query_method_calls_batch(queries))
+                {
+                    results.set(key, calls)?;
+                }
+
+                Ok(results)
+            },
+        )
+        .context("failed to set query_method_calls_batch function")?;
+
     let query_trait_impls_exec = exec.clone();
     table
-        .set_rtk_api_fn(lua, "query_trait_impls", move |query: Location| {
-            query_trait_impls_exec.query_trait_impls(query)
-        })
+        .set_rtk_api_fn(
+            lua,
+            "query_trait_impls",
+            // the generic args are optional, and when given only impls whose trait generic args
+            // each match the filter in the same position are kept
+            move |(query, trait_generic_args): (Location, Option<Vec<TypeValue>>)| {
+                let mut impls = query_trait_impls_exec.query_trait_impls(query);
+                if let Some(trait_generic_args) = trait_generic_args {
+                    impls.retain(|ti| {
+                        ti.trait_generic_args.len() == trait_generic_args.len()
+                            && ti
+                                .trait_generic_args
+                                .iter()
+                                .zip(&trait_generic_args)
+                                .all(|(arg, filter)| arg.matches_type_filter(filter))
+                    });
+                }
+                impls
+            },
+        )
         .context("failed to set query_trait_impls function")?;
 
+    let query_all_trait_impls_exec = exec.clone();
+    table
+        .set_rtk_api_fn(lua, "query_all_trait_impls", move |query: Location| {
+            query_all_trait_impls_exec.query_all_trait_impls(query)
+        })
+        .context("failed to set query_all_trait_impls function")?;
+
+    let query_impls_for_type_exec = exec.clone();
+    table
+        .set_rtk_api_fn(lua, "query_impls_for_type", move |query: Location| {
+            query_impls_for_type_exec.query_impls_for_type(query)
+        })
+        .context("failed to set query_impls_for_type function")?;
+
+    let query_trait_defs_exec = exec.clone();
+    table
+        .set_rtk_api_fn(lua, "query_trait_defs", move |query: Location| {
+            query_trait_defs_exec.query_trait_defs(query)
+        })
+        .context("failed to set query_trait_defs function")?;
+
     let query_functions_exec = exec.clone();
     table
         .set_rtk_api_fn(lua, "query_functions", move |query: Location| {
@@ -105,6 +341,13 @@ pub fn inject(
         })
         .context("failed to set query_functions function")?;
 
+    let query_functions_in_module_exec = exec.clone();
+    table
+        .set_rtk_api_fn(lua, "query_functions_in_module", move |module: Location| {
+            query_functions_in_module_exec.query_functions_in_module(module)
+        })
+        .context("failed to set query_functions_in_module function")?;
+
     let query_function_calls_exec = exec.clone();
     table
         .set_rtk_api_fn(lua, "query_function_calls", move |query: Location| {
@@ -112,6 +355,99 @@ pub fn inject(
         })
         .context("failed to set query_function_calls function")?;
 
+    let query_struct_literals_exec = exec.clone();
+    table
+        .set_rtk_api_fn(lua, "query_struct_literals", move |query: Location| {
+            query_struct_literals_exec.query_struct_literals(query)
+        })
+        .context("failed to set query_struct_literals function")?;
+
+    let query_macro_calls_exec = exec.clone();
+    table
+        .set_rtk_api_fn(lua, "query_macro_calls", move |name: String| {
+            query_macro_calls_exec.query_macro_calls(name)
+        })
+        .context("failed to set query_macro_calls function")?;
+
+    let query_serde_fields_exec = exec.clone();
+    table
+        .set_rtk_api_fn(lua, "query_serde_fields", move |query: Location| {
+            query_serde_fields_exec.query_serde_fields(query)
+        })
+        .context("failed to set query_serde_fields function")?;
+
+    let query_constants_by_type_exec = exec.clone();
+    table
+        .set_rtk_api_fn(
+            lua,
+            "query_constants_by_type",
+            move |type_filter: TypeValue| {
+                query_constants_by_type_exec.query_constants_by_type(type_filter)
+            },
+        )
+        .context("failed to set query_constants_by_type function")?;
+
+    let query_function_calls_in_function_exec = exec.clone();
+    table
+        .set_rtk_api_fn(
+            lua,
+            "query_function_calls_in_function",
+            move |(outer_location, call_location): (Location, Location)| {
+                query_function_calls_in_function_exec
+                    .query_function_calls_in_function(outer_location, call_location)
+            },
+        )
+        .context("failed to set query_function_calls_in_function function")?;
+
+    let query_closures_with_capture_exec = exec.clone();
+    table
+        .set_rtk_api_fn(
+            lua,
+            "query_closures_with_capture",
+            move |(within_location, capture_name): (Location, String)| {
+                query_closures_with_capture_exec
+                    .query_closures_with_capture(within_location, capture_name)
+            },
+        )
+        .context("failed to set query_closures_with_capture function")?;
+
+    let query_function_calls_grouped_exec = exec.clone();
+    table
+        .set_rtk_api_fn(
+            lua,
+            "query_function_calls_grouped",
+            move |module: Location| {
+                // tables can't be keyed by value in Lua, so groups are keyed by the formatted location
+                query_function_calls_grouped_exec
+                    .query_function_calls_grouped(module)
+                    .into_iter()
+                    .map(|(location, calls)| (location.to_string(), calls))
+                    .collect::<HashMap<_, _>>()
+            },
+        )
+        .context("failed to set query_function_calls_grouped function")?;
+
+    let query_modules_exec = exec.clone();
+    table
+        .set_rtk_api_fn(lua, "query_modules", move |()| {
+            query_modules_exec.query_modules()
+        })
+        .context("failed to set query_modules function")?;
+
+    let count_functions_exec = exec.clone();
+    table
+        .set_rtk_api_fn(lua, "count_functions", move |query: Location| {
+            count_functions_exec.count_functions(query)
+        })
+        .context("failed to set count_functions function")?;
+
+    let count_method_calls_exec = exec.clone();
+    table
+        .set_rtk_api_fn(lua, "count_method_calls", move |query: MethodCallQuery| {
+            count_method_calls_exec.count_method_calls(query)
+        })
+        .context("failed to set count_method_calls function")?;
+
     let emit_exec = exec.clone();
     table
         .set_rtk_api_fn(lua, "emit", move |text: String| {
@@ -120,14 +456,277 @@ pub fn inject(
         })
         .context("failed to set emit function")?;
 
+    let emit_with_priority_exec = exec.clone();
+    table
+        .set_rtk_api_fn(
+            lua,
+            "emit_with_priority",
+            move |(priority, text): (i64, String)| {
+                emit_with_priority_exec.emit_with_priority(priority, text);
+                mlua::Nil
+            },
+        )
+        .context("failed to set emit_with_priority function")?;
+
+    let emit_unique_exec = exec.clone();
+    table
+        .set_rtk_api_fn(lua, "emit_unique", move |text: String| {
+            emit_unique_exec.emit_unique(text);
+            mlua::Nil
+        })
+        .context("failed to set emit_unique function")?;
+
+    table
+        .set_rtk_api_fn(lua, "fmt_location", move |loc: Location| loc.to_string())
+        .context("failed to set fmt_location function")?;
+
+    table
+        .set_rtk_api_fn(lua, "join", move |(sep, values): (String, Vec<String>)| {
+            values.join(&sep)
+        })
+        .context("failed to set join function")?;
+
+    table
+        .set_rtk_api_fn(
+            lua,
+            "indent",
+            move |(n, text, unit): (usize, String, Option<String>)| {
+                indent_text(n, &text, unit.as_deref().unwrap_or(DEFAULT_INDENT_UNIT))
+            },
+        )
+        .context("failed to set indent function")?;
+
+    table
+        .set_rtk_api_fn(lua, "dedent", move |text: String| dedent_text(&text))
+        .context("failed to set dedent function")?;
+
+    table
+        .set_rtk_api_fn(lua, "is_primitive", move |tv: TypeValue| tv.is_primitive())
+        .context("failed to set is_primitive function")?;
+
+    table
+        .set_rtk_api_fn(lua, "unwrap_inner", move |tv: TypeValue| tv.unwrap_inner())
+        .context("failed to set unwrap_inner function")?;
+
+    table
+        .set_rtk_api_fn(
+            lua,
+            "find_attribute",
+            move |(attributes, name): (Vec<Attribute>, String)| {
+                Attribute::find(&attributes, &name).cloned()
+            },
+        )
+        .context("failed to set find_attribute function")?;
+
+    table
+        .set_rtk_api_fn(
+            lua,
+            "generate_typescript_interface",
+            move |s: StructTypeValue| typescript::struct_to_ts_interface(&s),
+        )
+        .context("failed to set generate_typescript_interface function")?;
+
+    table
+        .set_rtk_api_fn(
+            lua,
+            "generate_typescript_type",
+            move |(e, options): (EnumTypeValue, Option<typescript::TsEnumOptions>)| {
+                typescript::enum_to_ts_type(&e, &options.unwrap_or_default())
+            },
+        )
+        .context("failed to set generate_typescript_type function")?;
+
+    table
+        .set_rtk_api_fallible_fn(
+            lua,
+            "render",
+            move |(template, values): (String, mlua::Table)| render_template(&template, &values),
+        )
+        .context("failed to set render function")?;
+
+    table
+        .set_rtk_api_fallible_fn(
+            lua,
+            "map",
+            move |(values, f): (mlua::Table, mlua::Function)| {
+                map_sequence(&values, |value, index| f.call((value, index)))
+            },
+        )
+        .context("failed to set map function")?;
+
+    table
+        .set_rtk_api_fallible_fn(
+            lua,
+            "filter",
+            move |(values, predicate): (mlua::Table, mlua::Function)| {
+                let mut kept = vec![];
+                for (i, value) in values.sequence_values::<mlua::Value>().enumerate() {
+                    let value = value?;
+                    if predicate.call::<bool>((value.clone(), i + 1))? {
+                        kept.push(value);
+                    }
+                }
+
+                Ok(kept)
+            },
+        )
+        .context("failed to set filter function")?;
+
+    table
+        .set_rtk_api_fallible_fn(
+            lua,
+            "flat_map",
+            move |(values, f): (mlua::Table, mlua::Function)| {
+                let mapped = map_sequence(&values, |value, index| {
+                    f.call::<Vec<mlua::Value>>((value, index))
+                })?;
+
+                Ok(mapped.into_iter().flatten().collect::<Vec<_>>())
+            },
+        )
+        .context("failed to set flat_map function")?;
+
     Ok(())
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, Default)]
+/// Calls `f` with each element of the array part of `values` along with its 1-based index, the
+/// same arguments `ipairs` would hand out
+fn map_sequence<T>(
+    values: &mlua::Table,
+    mut f: impl FnMut(mlua::Value, usize) -> mlua::Result<T>,
+) -> mlua::Result<Vec<T>> {
+    values
+        .sequence_values::<mlua::Value>()
+        .enumerate()
+        .map(|(i, value)| f(value?, i + 1))
+        .collect()
+}
+
+/// Renders `template`, substituting every `{{ key }}` with the matching value from `values`.
+/// Dotted keys such as `{{ user.name }}` walk into nested tables, and numeric segments index into
+/// arrays. A missing key is an error rather than being left in place, since a typo in a key would
+/// otherwise end up silently written into the output
+fn render_template(template: &str, values: &mlua::Table) -> mlua::Result<String> {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(open) = rest.find("{{") {
+        rendered.push_str(&rest[..open]);
+
+        let after_open = &rest[open + 2..];
+        let close = after_open.find("}}").ok_or_else(|| {
+            mlua::Error::runtime(format!(
+                "unterminated `{{{{` in template starting at `{}`",
+                &rest[open..]
+            ))
+        })?;
+
+        let key = after_open[..close].trim();
+        rendered.push_str(&template_value_for_key(values, key)?);
+
+        rest = &after_open[close + 2..];
+    }
+
+    rendered.push_str(rest);
+
+    Ok(rendered)
+}
+
+fn template_value_for_key(values: &mlua::Table, key: &str) -> mlua::Result<String> {
+    let mut value = mlua::Value::Table(values.clone());
+
+    for segment in key.split('.') {
+        let table = match value {
+            mlua::Value::Table(table) => table,
+            other => {
+                return Err(mlua::Error::runtime(format!(
+                    "template key `{key}` indexes into a {} value",
+                    other.type_name()
+                )));
+            }
+        };
+
+        value = match segment.parse::<i64>() {
+            Ok(index) => table.get(index)?,
+            Err(_) => table.get(segment)?,
+        };
+    }
+
+    match value {
+        mlua::Value::String(s) => Ok(s.to_str()?.to_string()),
+        mlua::Value::Integer(i) => Ok(i.to_string()),
+        mlua::Value::Number(n) => Ok(n.to_string()),
+        mlua::Value::Boolean(b) => Ok(b.to_string()),
+        mlua::Value::Nil => Err(mlua::Error::runtime(format!(
+            "template key `{key}` is missing"
+        ))),
+        other => Err(mlua::Error::runtime(format!(
+            "template key `{key}` is a {} which cannot be rendered",
+            other.type_name()
+        ))),
+    }
+}
+
+/// What `rtk.indent` repeats for each level when the script doesn't pass its own unit
+const DEFAULT_INDENT_UNIT: &str = "    ";
+
+/// Prefixes every line of `text` with `unit` repeated `n` times. Blank lines are left alone so no
+/// trailing whitespace ends up in the output
+fn indent_text(n: usize, text: &str, unit: &str) -> String {
+    let indentation = unit.repeat(n);
+
+    text.split('\n')
+        .map(|line| {
+            if line.trim().is_empty() {
+                line.to_string()
+            } else {
+                format!("{indentation}{line}")
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Strips the leading whitespace every non-blank line of `text` has in common. Blank lines are
+/// emptied since whatever whitespace they had is meaningless. Indentation is compared character by
+/// character, so a tab and a space are never taken as the same
+fn dedent_text(text: &str) -> String {
+    let common = text
+        .split('\n')
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| &line[..line.len() - line.trim_start().len()])
+        .reduce(|common, indent| {
+            let len = common
+                .chars()
+                .zip(indent.chars())
+                .take_while(|(a, b)| a == b)
+                .map(|(c, _)| c.len_utf8())
+                .sum();
+            &common[..len]
+        })
+        .unwrap_or("");
+
+    text.split('\n')
+        .map(|line| {
+            if line.trim().is_empty() {
+                ""
+            } else {
+                line.strip_prefix(common).unwrap_or(line)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
 pub struct Location {
     pub crate_name: String,
     pub path: Vec<String>,
     pub impl_block_number: Option<usize>,
+    /// Whether the item is defined in the crate being compiled rather than one of its
+    /// dependencies. Locations built from Lua are queries rather than declarations, so this is
+    /// false for them unless set explicitly
+    pub is_local: bool,
 }
 
 impl FromLua for Location {
@@ -143,20 +742,101 @@ impl FromLua for Location {
         let crate_name: String = table.get("crate_name")?;
         let path: Vec<String> = table.get("path")?;
         let impl_block_number: Option<usize> = table.get("impl_block_number")?;
+        let is_local: Option<bool> = table.get("is_local")?;
 
         Ok(Location {
             crate_name,
             path,
             impl_block_number,
+            is_local: is_local.unwrap_or(false),
         })
     }
 }
 
+impl Location {
+    /// Whether both locations point at the same item. Unlike `==` this leaves out `is_local`, so a
+    /// query location built in Lua is the same item as the location the driver produces for it
+    pub fn same_item(&self, other: &Location) -> bool {
+        self.crate_name == other.crate_name
+            && self.path == other.path
+            && self.impl_block_number == other.impl_block_number
+    }
+
+    /// Whether this location sits somewhere beneath `module`, e.g. `crate::a::b::f` is within
+    /// `crate::a`
+    pub fn is_within(&self, module: &Location) -> bool {
+        self.crate_name == module.crate_name
+            && self.path.len() > module.path.len()
+            && self.path.starts_with(&module.path)
+    }
+
+    /// Whether this location is the one a query for `query` is after. A query that leaves out
+    /// `impl_block_number` matches the item in any impl block, since the number of the block
+    /// holding an associated function like `Router::new` is rarely known up front
+    pub fn matches_query(&self, query: &Location) -> bool {
+        self.crate_name == query.crate_name
+            && self.path == query.path
+            && (query.impl_block_number.is_none()
+                || self.impl_block_number == query.impl_block_number)
+    }
+}
+
+/// Formats the location the same way the driver does in its own diagnostics, i.e.
+/// `crate::path::to::item{impl#N}`
+impl std::fmt::Display for Location {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.crate_name)?;
+        for segment in &self.path {
+            write!(f, "::{segment}")?;
+        }
+
+        if let Some(impl_block_number) = self.impl_block_number {
+            write!(f, "{{impl#{impl_block_number}}}")?;
+        }
+
+        Ok(())
+    }
+}
+
 impl_into_lua! {
     Location {
         crate_name,
         path,
         impl_block_number,
+        is_local,
+    }
+}
+
+/// What to do when a query matches nothing, set from scripts with
+/// `rtk.set_empty_query_behavior("ignore" | "warn" | "error")`.
+///
+/// Only queries that look up a location are reported, since an empty result there is usually a
+/// mistyped path. Queries that search the whole crate, like `query_constants_by_type` or
+/// `query_macro_calls`, can rightly match nothing and are never reported
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, RtkMeta)]
+pub enum QueryEmptyBehavior {
+    /// Empty results are returned without a word, the default
+    #[default]
+    #[rtk_meta(override = string)]
+    Ignore,
+    /// Warn about each query that matches nothing
+    #[rtk_meta(override = string)]
+    WarnOnEmpty,
+    /// Error on each query that matches nothing, failing the build once the script finishes
+    #[rtk_meta(override = string)]
+    ErrorOnEmpty,
+}
+
+impl FromLua for QueryEmptyBehavior {
+    fn from_lua(value: mlua::Value, _: &mlua::Lua) -> mlua::Result<Self> {
+        match value.to_string()?.as_str() {
+            "ignore" => Ok(QueryEmptyBehavior::Ignore),
+            "warn" => Ok(QueryEmptyBehavior::WarnOnEmpty),
+            "error" => Ok(QueryEmptyBehavior::ErrorOnEmpty),
+            unknown => Err(mlua::Error::external(format!(
+                "Invalid empty query behavior: {unknown}. Expected one of ignore, warn or error",
+            ))),
+        }
     }
 }
 
@@ -174,12 +854,24 @@ pub struct MethodCallQuery {
     pub parent: Option<Box<MethodCallQuery>>,
     /// The path to the module this method call sits in.
     pub location: Location,
+    /// If specified, only calls whose receiver (with references peeled) is the type at this
+    /// location are matched. This disambiguates same-named methods on different types
+    pub receiver_type_filter: Option<Location>,
+}
+
+impl MethodCallQuery {
+    /// How many `parent` links lead from this query to the start of its chain, so a query with
+    /// no parent is at depth 0
+    pub fn chain_depth(&self) -> usize {
+        std::iter::successors(self.parent.as_deref(), |parent| parent.parent.as_deref()).count()
+    }
 }
 
 impl_into_lua! {
     MethodCallQuery {
         parent => parent.map(|b| *b),
         location,
+        receiver_type_filter,
     }
 }
 
@@ -207,7 +899,13 @@ impl FromLua for MethodCallQuery {
                     message: Some("expected a Location".to_string()),
                 })?;
 
-        Ok(MethodCallQuery { parent, location })
+        let receiver_type_filter: Option<Location> = table.get("receiver_type_filter")?;
+
+        Ok(MethodCallQuery {
+            parent,
+            location,
+            receiver_type_filter,
+        })
     }
 }
 
@@ -219,6 +917,12 @@ pub struct MethodCall {
     pub origin: MethodCallQuery,
     pub args: Vec<Value>,
     pub in_item_id: String,
+    /// Whether the call sits in code only compiled for tests, i.e. beneath a `#[cfg(test)]` module
+    pub is_in_test_module: bool,
+    /// How many method calls this call is chained onto in the source, e.g. 2 for `d` in
+    /// `a.b().c().d()`. This is counted from the call itself, so it can be deeper than the
+    /// [`MethodCallQuery::chain_depth`] of a query that only names the end of the chain
+    pub chain_depth: usize,
 }
 
 impl_into_lua! {
@@ -226,6 +930,8 @@ impl_into_lua! {
         origin,
         args,
         in_item_id,
+        is_in_test_module,
+        chain_depth,
     }
 }
 
@@ -238,6 +944,24 @@ pub enum Value {
     FunctionCall(FunctionCall),
     MethodCall(MethodCall),
 
+    /// A struct expression like `Config { timeout: 30 }`, with the fields in the order written.
+    /// Fields whose values can't be elevated are left out
+    StructLiteral {
+        location: Location,
+        fields: Vec<(String, Value)>,
+    },
+    /// An array expression like `[handler_a, handler_b]`. Elements that can't be elevated are left
+    /// out
+    ArrayLiteral(Vec<Value>),
+    /// An enum variant like `Status::Inactive` or `Status::Active(42)`. `location` is the enum's,
+    /// and `inner` is the argument of a variant with a single field, if it can be elevated.
+    /// Variants with more than one field have no `inner`
+    EnumVariantLiteral {
+        location: Location,
+        variant: String,
+        inner: Option<Box<Value>>,
+    },
+
     Type(TypeValue),
 }
 
@@ -250,11 +974,70 @@ impl_enum_into_lua! {
         FunctionCall(f) => f,
         MethodCall(m) => m,
 
+        StructLiteral { location, fields } => StructLiteralData {
+            location,
+            fields: fields
+                .into_iter()
+                .map(|(name, value)| StructLiteralField { name, value })
+                .collect(),
+        },
+        ArrayLiteral(elements) => elements,
+        EnumVariantLiteral {
+            location,
+            variant,
+            inner,
+        } => EnumVariantLiteralData {
+            location,
+            variant,
+            inner: inner.map(|b| *b),
+        },
+
         Type(t) => t,
     }
 }
 
-#[derive(Clone, Debug)]
+/// The Lua form of [`Value::EnumVariantLiteral`]
+struct EnumVariantLiteralData {
+    location: Location,
+    variant: String,
+    inner: Option<Value>,
+}
+
+impl_into_lua! {
+    EnumVariantLiteralData {
+        location,
+        variant,
+        inner,
+    }
+}
+
+/// The Lua form of [`Value::StructLiteral`]. Fields are a list rather than keyed by name so that
+/// their order survives
+struct StructLiteralData {
+    location: Location,
+    fields: Vec<StructLiteralField>,
+}
+
+impl_into_lua! {
+    StructLiteralData {
+        location,
+        fields,
+    }
+}
+
+struct StructLiteralField {
+    name: String,
+    value: Value,
+}
+
+impl_into_lua! {
+    StructLiteralField {
+        name,
+        value,
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
 pub enum TypeValue {
     String,
 
@@ -272,13 +1055,25 @@ pub enum TypeValue {
     I128,
     Isize,
 
+    /// Only available on nightly toolchains
+    F16,
     F32,
     F64,
+    /// Only available on nightly toolchains
+    F128,
 
     Bool,
 
-    HashMap(Box<TypeValue>, Box<TypeValue>),
+    /// A map from `key` to `value`, a `BTreeMap` when `is_ordered` and a `HashMap` otherwise. In
+    /// Lua `variant_data` is a table with all three under their own names
+    Map {
+        key: Box<TypeValue>,
+        value: Box<TypeValue>,
+        is_ordered: bool,
+    },
     Vec(Box<TypeValue>),
+    /// A `Result` of the first type or the second. In Lua `variant_data` is a table with the two
+    /// under `ok` and `err`
     Result(Box<TypeValue>, Box<TypeValue>),
 
     Struct(StructTypeValue),
@@ -288,10 +1083,32 @@ pub enum TypeValue {
     Function(FunctionTypeValue),
 
     Option(Box<TypeValue>),
+    /// One of the `core::num::NonZero*` integers, wrapping the primitive it is a non-zero form of
+    NonZero(Box<TypeValue>),
 
+    /// A tuple type. In Lua `variant_data` is an array of the element types, in order
     Tuple(Vec<TypeValue>),
+    /// A raw pointer, `*mut T` when `mutable` and `*const T` otherwise. In Lua `variant_data` is a
+    /// table with the two under `mutable` and `inner`
+    RawPointer {
+        mutable: bool,
+        inner: Box<TypeValue>,
+    },
 
     RecursiveRef(Location),
+    /// A generic type parameter left uninstantiated, e.g. the `T` in `impl<T> Trait for Wrapper<T>`
+    Generic(String),
+    /// An `impl Trait` argument, like the `x` in `fn f(x: impl Into<String>)`, with the location of
+    /// the trait it is bounded by. In Lua `variant_data` is a table with it under `bound`
+    ImplTrait {
+        bound: Location,
+    },
+    /// A type that couldn't be elevated, like a function pointer, with the type as rustc prints it. Only
+    /// struct fields and trait generic args are elevated to this, so neither loses an entry. In Lua
+    /// `variant_data` is a table with it under `rust_type`
+    Unsupported {
+        rust_type: String,
+    },
 }
 
 impl_enum_into_lua! {
@@ -309,15 +1126,26 @@ impl_enum_into_lua! {
         I64,
         I128,
         Isize,
+        F16,
         F32,
         F64,
+        F128,
         Bool,
 
-        // HashMap(k, v) => (*k, *v),
-        HashMap(_, _) => mlua::Nil,
+        Map {
+            key,
+            value,
+            is_ordered,
+        } => MapData {
+            key: *key,
+            value: *value,
+            is_ordered,
+        },
         Vec(t) => *t,
-        // Result(ok, err) => (*ok, *err),
-        Result(_, _) => mlua::Nil,
+        Result(ok, err) => ResultData {
+            ok: *ok,
+            err: *err,
+        },
 
         Struct(s) => s,
         Enum(e) => e,
@@ -327,132 +1155,812 @@ impl_enum_into_lua! {
         Function(f) => f,
 
         Option(t) => *t,
+        NonZero(t) => *t,
 
         Tuple(elements) => elements,
+        RawPointer { mutable, inner } => RawPointerData {
+            mutable,
+            inner: *inner,
+        },
 
         RecursiveRef(location) => location,
+        Generic(name) => name,
+        ImplTrait { bound } => ImplTraitData { bound },
+        Unsupported { rust_type } => UnsupportedData { rust_type },
     }
 }
 
-#[derive(Clone, Debug)]
-pub struct StructTypeValue {
-    pub location: Location,
-    pub fields: Vec<StructTypeValueField>,
-    pub doc_comment: Option<String>,
-    pub attributes: Vec<Attribute>,
+/// The Lua form of [`TypeValue::Map`]
+struct MapData {
+    key: TypeValue,
+    value: TypeValue,
+    is_ordered: bool,
 }
 
 impl_into_lua! {
-    StructTypeValue {
-        location,
-        fields,
-        doc_comment,
-        attributes,
+    MapData {
+        key,
+        value,
+        is_ordered,
     }
 }
 
-#[derive(Clone, Debug)]
-pub struct StructTypeValueField {
-    pub name: Either<usize, String>,
-    pub doc_comment: Option<String>,
-    pub attributes: Vec<Attribute>,
-    pub value: TypeValue,
-}
-
-impl_into_lua! {
-    StructTypeValueField {
-        name,
-        doc_comment,
-        attributes,
+impl_from_lua! {
+    MapData {
+        key,
         value,
+        is_ordered,
     }
 }
 
-#[derive(Clone, Debug)]
-pub struct EnumTypeValue {
-    pub location: Location,
-    pub variants: Vec<EnumTypeValueVariant>,
-    pub doc_comment: Option<String>,
-    pub attributes: Vec<Attribute>,
+/// The Lua form of [`TypeValue::Result`]
+struct ResultData {
+    ok: TypeValue,
+    err: TypeValue,
 }
 
 impl_into_lua! {
-    EnumTypeValue {
-        location,
-        variants,
-        doc_comment,
-        attributes,
+    ResultData {
+        ok,
+        err,
     }
 }
 
-#[derive(Clone, Debug)]
-pub struct EnumTypeValueVariant {
-    pub name: String,
-    /// If this variant has a value, this will be the type of that value otherwise its just a unit
-    /// variant
-    pub value: Option<TypeValue>,
-    pub doc_comment: Option<String>,
-    pub attributes: Vec<Attribute>,
+impl_from_lua! {
+    ResultData {
+        ok,
+        err,
+    }
+}
+
+/// The Lua form of [`TypeValue::RawPointer`]
+struct RawPointerData {
+    mutable: bool,
+    inner: TypeValue,
 }
 
 impl_into_lua! {
-    EnumTypeValueVariant {
-        name,
-        value,
-        doc_comment,
-        attributes,
+    RawPointerData {
+        mutable,
+        inner,
     }
 }
 
-/// A closure definition itself. The args are just a struct ultimately
-#[derive(Clone, Debug)]
-pub struct ClosureTypeValue {
-    pub args: Vec<TypeValue>,
-    pub return_type: Option<Box<TypeValue>>,
+impl_from_lua! {
+    RawPointerData {
+        mutable,
+        inner,
+    }
+}
+
+/// The Lua form of [`TypeValue::ImplTrait`]
+struct ImplTraitData {
+    bound: Location,
 }
 
 impl_into_lua! {
-    ClosureTypeValue {
-        args,
-        return_type => return_type.map(|b| *b),
+    ImplTraitData {
+        bound,
     }
 }
 
-#[derive(Clone, Debug)]
-pub struct FunctionTypeValue {
-    pub location: Location,
-    pub args_struct: StructTypeValue,
-    pub return_type: Option<Box<TypeValue>>,
-    pub item_id: String,
-    pub attributes: Vec<Attribute>,
-    pub doc_comment: Option<String>,
-    pub is_async: bool,
+impl_from_lua! {
+    ImplTraitData {
+        bound,
+    }
+}
+
+/// The Lua form of [`TypeValue::Unsupported`]
+struct UnsupportedData {
+    rust_type: String,
 }
 
 impl_into_lua! {
-    FunctionTypeValue {
-        location,
-        args_struct,
-        return_type => return_type.map(|b| *b),
-        item_id,
-        attributes,
-        doc_comment,
-        is_async,
+    UnsupportedData {
+        rust_type,
     }
 }
 
-/// An attribute in the source code.
-#[derive(Clone, Debug)]
-pub struct Attribute {
+impl_from_lua! {
+    UnsupportedData {
+        rust_type,
+    }
+}
+
+impl TypeValue {
+    /// Whether this is a string, bool or one of the integer or float primitives
+    pub fn is_primitive(&self) -> bool {
+        use TypeValue::*;
+
+        matches!(
+            self,
+            String
+                | Bool
+                | U8
+                | U16
+                | U32
+                | U64
+                | U128
+                | Usize
+                | I8
+                | I16
+                | I32
+                | I64
+                | I128
+                | Isize
+                | F16
+                | F32
+                | F64
+                | F128
+        )
+    }
+
+    /// The type held by a single-type wrapper (`Option`, `Vec` or `NonZero`), or the type itself
+    /// for anything else. Smart pointers like `Box` never need unwrapping, since they are elevated
+    /// as the type they point to
+    pub fn unwrap_inner(self) -> TypeValue {
+        match self {
+            TypeValue::Option(inner) | TypeValue::Vec(inner) | TypeValue::NonZero(inner) => *inner,
+            other => other,
+        }
+    }
+
+    /// Whether a value of this type is picked out by `filter`. Structs and enums are matched by
+    /// location alone, so a script can filter by a type it only knows the path of. Everything else
+    /// has to be equal
+    pub fn matches_type_filter(&self, filter: &TypeValue) -> bool {
+        match (self, filter) {
+            (TypeValue::Struct(s), TypeValue::Struct(filter)) => {
+                s.location.same_item(&filter.location)
+            }
+            (TypeValue::Enum(e), TypeValue::Enum(filter)) => e.location.same_item(&filter.location),
+            _ => self == filter,
+        }
+    }
+}
+
+/// The inverse of the `IntoLua` impl, so scripts can build synthetic types of their own to pass
+/// back into the API
+impl FromLua for TypeValue {
+    fn from_lua(value: mlua::Value, lua: &mlua::Lua) -> mlua::Result<Self> {
+        let table = value
+            .as_table()
+            .ok_or_else(|| mlua::Error::FromLuaConversionError {
+                from: value.type_name(),
+                to: "TypeValue".to_string(),
+                message: Some("expected a table".to_string()),
+            })?;
+
+        let variant_name: String = table.get("variant_name")?;
+        let variant_data: mlua::Value = table.get("variant_data")?;
+
+        let boxed = |data: mlua::Value| TypeValue::from_lua(data, lua).map(Box::new);
+
+        let type_value = match variant_name.as_str() {
+            "String" => TypeValue::String,
+            "U8" => TypeValue::U8,
+            "U16" => TypeValue::U16,
+            "U32" => TypeValue::U32,
+            "U64" => TypeValue::U64,
+            "U128" => TypeValue::U128,
+            "Usize" => TypeValue::Usize,
+            "I8" => TypeValue::I8,
+            "I16" => TypeValue::I16,
+            "I32" => TypeValue::I32,
+            "I64" => TypeValue::I64,
+            "I128" => TypeValue::I128,
+            "Isize" => TypeValue::Isize,
+            "F16" => TypeValue::F16,
+            "F32" => TypeValue::F32,
+            "F64" => TypeValue::F64,
+            "F128" => TypeValue::F128,
+            "Bool" => TypeValue::Bool,
+
+            "Map" => {
+                let data = MapData::from_lua(variant_data, lua)?;
+                TypeValue::Map {
+                    key: Box::new(data.key),
+                    value: Box::new(data.value),
+                    is_ordered: data.is_ordered,
+                }
+            }
+            "Result" => {
+                let data = ResultData::from_lua(variant_data, lua)?;
+                TypeValue::Result(Box::new(data.ok), Box::new(data.err))
+            }
+            "Vec" => TypeValue::Vec(boxed(variant_data)?),
+
+            "Struct" => TypeValue::Struct(StructTypeValue::from_lua(variant_data, lua)?),
+            "Enum" => TypeValue::Enum(EnumTypeValue::from_lua(variant_data, lua)?),
+
+            "Closure" => TypeValue::Closure(ClosureTypeValue::from_lua(variant_data, lua)?),
+            "Function" => TypeValue::Function(FunctionTypeValue::from_lua(variant_data, lua)?),
+
+            "Option" => TypeValue::Option(boxed(variant_data)?),
+            "NonZero" => TypeValue::NonZero(boxed(variant_data)?),
+
+            "Tuple" => TypeValue::Tuple(Vec::<TypeValue>::from_lua(variant_data, lua)?),
+            "RawPointer" => {
+                let data = RawPointerData::from_lua(variant_data, lua)?;
+                TypeValue::RawPointer {
+                    mutable: data.mutable,
+                    inner: Box::new(data.inner),
+                }
+            }
+
+            "RecursiveRef" => TypeValue::RecursiveRef(Location::from_lua(variant_data, lua)?),
+            "Generic" => TypeValue::Generic(String::from_lua(variant_data, lua)?),
+            "ImplTrait" => TypeValue::ImplTrait {
+                bound: ImplTraitData::from_lua(variant_data, lua)?.bound,
+            },
+            "Unsupported" => TypeValue::Unsupported {
+                rust_type: UnsupportedData::from_lua(variant_data, lua)?.rust_type,
+            },
+
+            unknown => {
+                return Err(mlua::Error::FromLuaConversionError {
+                    from: "table",
+                    to: "TypeValue".to_string(),
+                    message: Some(format!("unknown TypeValue variant `{unknown}`")),
+                });
+            }
+        };
+
+        Ok(type_value)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct StructTypeValue {
+    pub location: Location,
+    pub fields: Vec<StructTypeValueField>,
+    pub doc_comment: Option<String>,
+    pub attributes: Vec<Attribute>,
+    pub kind: StructKind,
+    /// The simple names of the traits the struct derives, e.g. `Serialize` for
+    /// `#[derive(serde::Serialize)]`. Always empty for enum variants, whose enum does the deriving
+    pub derives: Vec<String>,
+    /// Whether the struct (or enum variant) is `#[non_exhaustive]`, so more fields may be added
+    /// without a breaking change
+    pub is_non_exhaustive: bool,
+    /// The wrapped type when this is a newtype, a tuple struct with exactly one field like
+    /// `struct UserId(u64)`
+    pub newtype_inner: Option<Box<TypeValue>>,
+    /// Whether `serde::Serialize` (or `serde_core`'s, which serde reexports) is implemented for the
+    /// type by hand rather than derived, so its serialized form can't be read off its fields
+    pub has_custom_serialize: bool,
+    /// Like `has_custom_serialize`, for `serde::Deserialize`
+    pub has_custom_deserialize: bool,
+    /// A hash of `location` that stays the same across queries and runs, so scripts can key a
+    /// table on it to generate each type only once
+    pub type_hash: u64,
+    /// The size of the struct in bytes, unless it is still generic or unsized. Only a `#[repr(C)]`
+    /// struct's layout is guaranteed to be the same from one build to the next
+    pub size: Option<u64>,
+    /// The alignment of the struct in bytes, known whenever `size` is
+    pub align: Option<u64>,
+}
+
+impl StructTypeValue {
+    /// Computes `newtype_inner` for a struct of the given kind and fields
+    pub fn newtype_inner_of(
+        kind: StructKind,
+        fields: &[StructTypeValueField],
+    ) -> Option<Box<TypeValue>> {
+        match (kind, fields) {
+            (StructKind::Tuple, [field]) => Some(Box::new(field.value.clone())),
+            _ => None,
+        }
+    }
+
+    /// Whether a type with the given `derives` implements a trait by hand, given whether it
+    /// implements it at all. Derives are matched by their simple name, e.g. `Serialize`
+    pub fn is_custom_impl(derives: &[String], derive_name: &str, has_impl: bool) -> bool {
+        has_impl && !derives.iter().any(|derive| derive == derive_name)
+    }
+}
+
+impl_into_lua! {
+    StructTypeValue {
+        location,
+        fields,
+        doc_comment,
+        attributes,
+        kind,
+        derives,
+        is_non_exhaustive,
+        newtype_inner => newtype_inner.map(|b| *b),
+        has_custom_serialize,
+        has_custom_deserialize,
+        type_hash,
+        size,
+        align,
+    }
+}
+
+impl_from_lua! {
+    StructTypeValue {
+        location,
+        fields,
+        doc_comment,
+        attributes,
+        kind,
+        derives,
+        is_non_exhaustive,
+        newtype_inner: Option<TypeValue> => newtype_inner.map(Box::new),
+        has_custom_serialize,
+        has_custom_deserialize,
+        type_hash: Option<u64> => type_hash.unwrap_or_default(),
+        size,
+        align,
+    }
+}
+
+/// How the fields of a struct (or enum variant) are declared.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum StructKind {
+    /// `struct Point { x: f32, y: f32 }`
+    Named,
+    /// `struct Point(f32, f32)`
+    Tuple,
+    /// `struct Marker;`
+    Unit,
+}
+
+impl_enum_into_lua! {
+    StructKind {
+        Named,
+        Tuple,
+        Unit,
+    }
+}
+
+impl FromLua for StructKind {
+    fn from_lua(value: mlua::Value, _: &mlua::Lua) -> mlua::Result<Self> {
+        let table = value
+            .as_table()
+            .ok_or_else(|| mlua::Error::FromLuaConversionError {
+                from: value.type_name(),
+                to: "StructKind".to_string(),
+                message: Some("expected a table".to_string()),
+            })?;
+
+        let variant_name: String = table.get("variant_name")?;
+
+        match variant_name.as_str() {
+            "Named" => Ok(StructKind::Named),
+            "Tuple" => Ok(StructKind::Tuple),
+            "Unit" => Ok(StructKind::Unit),
+            unknown => Err(mlua::Error::FromLuaConversionError {
+                from: "table",
+                to: "StructKind".to_string(),
+                message: Some(format!("unknown StructKind variant `{unknown}`")),
+            }),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct StructTypeValueField {
+    pub name: Either<usize, String>,
+    /// The doc comment on the field itself, if it has one
+    pub doc_comment: Option<String>,
+    pub attributes: Vec<Attribute>,
+    pub value: TypeValue,
+    /// Whether the field can be left out, either because it is an `Option` or because serde is
+    /// told to default or skip it with `#[serde(default)]` or `#[serde(skip_serializing_if)]`
+    pub is_optional: bool,
+    /// Whether serde leaves the field out of the serialized form entirely, with
+    /// `#[serde(skip)]` or `#[serde(skip_serializing)]`
+    pub has_serde_skip: bool,
+    /// The name given with `#[serde(rename = "...")]`, without the quotes
+    pub serde_rename: Option<String>,
+}
+
+impl StructTypeValueField {
+    /// Computes `is_optional` for a field with the given type and attributes
+    pub fn is_optional_for(value: &TypeValue, attributes: &[Attribute]) -> bool {
+        matches!(value, TypeValue::Option(_))
+            || attributes.iter().any(|attr| {
+                attr.serde_keys()
+                    .any(|key| key == "default" || key == "skip_serializing_if")
+            })
+    }
+
+    /// Computes `has_serde_skip` for a field with the given attributes
+    pub fn has_serde_skip_for(attributes: &[Attribute]) -> bool {
+        attributes.iter().any(|attr| {
+            attr.serde_keys()
+                .any(|key| key == "skip" || key == "skip_serializing")
+        })
+    }
+
+    /// Computes `serde_rename` for a field with the given attributes
+    pub fn serde_rename_for(attributes: &[Attribute]) -> Option<String> {
+        attributes
+            .iter()
+            .find_map(|attr| attr.serde_value("rename"))
+    }
+}
+
+impl_into_lua! {
+    StructTypeValueField {
+        name,
+        doc_comment,
+        attributes,
+        value,
+        is_optional,
+        has_serde_skip,
+        serde_rename,
+    }
+}
+
+impl_from_lua! {
+    StructTypeValueField {
+        name,
+        doc_comment,
+        attributes,
+        value,
+        is_optional,
+        has_serde_skip,
+        serde_rename,
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct EnumTypeValue {
+    pub location: Location,
+    /// The variants in the order they are declared, which is also the order of their implicit
+    /// discriminants
+    pub variants: Vec<EnumTypeValueVariant>,
+    pub doc_comment: Option<String>,
+    pub attributes: Vec<Attribute>,
+    /// Whether every variant is a unit variant, i.e. the enum could be written as a C enum
+    pub is_c_like: bool,
+    /// The simple names of the traits the enum derives, e.g. `Serialize` for
+    /// `#[derive(serde::Serialize)]`
+    pub derives: Vec<String>,
+    /// The field name given with `#[serde(tag = "...")]` when serde tags the enum internally (or
+    /// adjacently, alongside `content`), without the quotes
+    pub serde_tag: Option<String>,
+    /// Whether the enum is `#[non_exhaustive]`, so more variants may be added without a breaking
+    /// change
+    pub is_non_exhaustive: bool,
+    /// A hash of `location`, see `StructTypeValue::type_hash`
+    pub type_hash: u64,
+}
+
+impl EnumTypeValue {
+    /// Computes `serde_tag` for an enum with the given attributes
+    pub fn serde_tag_for(attributes: &[Attribute]) -> Option<String> {
+        attributes.iter().find_map(|attr| attr.serde_value("tag"))
+    }
+}
+
+impl_into_lua! {
+    EnumTypeValue {
+        location,
+        variants,
+        doc_comment,
+        attributes,
+        is_c_like,
+        derives,
+        serde_tag,
+        is_non_exhaustive,
+        type_hash,
+    }
+}
+
+impl_from_lua! {
+    EnumTypeValue {
+        location,
+        variants,
+        doc_comment,
+        attributes,
+        is_c_like,
+        derives,
+        serde_tag,
+        is_non_exhaustive,
+        type_hash: Option<u64> => type_hash.unwrap_or_default(),
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct EnumTypeValueVariant {
+    pub name: String,
+    /// The variant's fields as a struct, whose `kind` tells `Variant { a: u32 }`, `Variant(u32)`
+    /// and unit variants apart. Fields of struct-like variants keep their names, while tuple-like
+    /// ones are named by position
+    pub value: Option<TypeValue>,
+    pub doc_comment: Option<String>,
+    pub attributes: Vec<Attribute>,
+    /// The name given with `#[serde(rename = "...")]`, without the quotes
+    pub serde_rename: Option<String>,
+}
+
+impl EnumTypeValueVariant {
+    /// Computes `serde_rename` for a variant with the given attributes
+    pub fn serde_rename_for(attributes: &[Attribute]) -> Option<String> {
+        attributes
+            .iter()
+            .find_map(|attr| attr.serde_value("rename"))
+    }
+}
+
+impl_into_lua! {
+    EnumTypeValueVariant {
+        name,
+        value,
+        doc_comment,
+        attributes,
+        serde_rename,
+    }
+}
+
+impl_from_lua! {
+    EnumTypeValueVariant {
+        name,
+        value,
+        doc_comment,
+        attributes,
+        serde_rename,
+    }
+}
+
+/// A closure definition itself. The args are just a struct ultimately
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClosureTypeValue {
+    pub args: Vec<TypeValue>,
+    pub return_type: Option<Box<TypeValue>>,
+}
+
+impl_into_lua! {
+    ClosureTypeValue {
+        args,
+        return_type => return_type.map(|b| *b),
+    }
+}
+
+impl_from_lua! {
+    ClosureTypeValue {
+        args,
+        return_type: Option<TypeValue> => return_type.map(Box::new),
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FunctionTypeValue {
+    pub location: Location,
+    pub args_struct: StructTypeValue,
+    pub return_type: Option<Box<TypeValue>>,
+    pub item_id: String,
+    pub attributes: Vec<Attribute>,
+    pub doc_comment: Option<String>,
+    pub is_async: bool,
+    /// The pretty-printed predicates of the function's where clause and inline bounds, e.g.
+    /// `T: std::clone::Clone`. Implicit `Sized` bounds on type parameters are included
+    pub where_clause_text: Vec<String>,
+    /// Whether the function is annotated with `#[test]`. Test functions only exist when the crate
+    /// is checked as a test, e.g. with `--tests` in the forwarded cargo args
+    pub is_test: bool,
+    /// Whether the function, or any module it sits in, is annotated with `#[cfg(test)]`
+    pub is_cfg_test: bool,
+    /// How many lifetimes the function is generic over, counting elided ones. Non-zero means the
+    /// function borrows from at least one of its arguments
+    pub lifetime_count: usize,
+    /// Whether the function is declared with a plain `pub`. This says nothing of the modules
+    /// around it, so it may still be unreachable from outside its crate
+    pub is_public: bool,
+    /// The visibility as it would be written on the function, e.g. `pub`, `pub(crate)`,
+    /// `pub(super)` or `pub(in crate::routes)`. Empty for private functions
+    pub visibility: String,
+    /// Whether the function is declared with no arguments at all. A function taking a single `()`
+    /// still takes an argument, so this tells `fn f()` apart from `fn f(_: ())`
+    pub takes_no_args: bool,
+    /// For functions in a trait impl, the location of the trait method they implement
+    pub overrides_trait_method: Option<Location>,
+    /// Functions of the same name defined on the same type in later impl blocks. Only filled in
+    /// when `query_functions` is given no `impl_block_number`, with this being the first of them
+    pub overloads: Vec<FunctionTypeValue>,
+}
+
+impl_into_lua! {
+    FunctionTypeValue {
+        location,
+        args_struct,
+        return_type => return_type.map(|b| *b),
+        item_id,
+        attributes,
+        doc_comment,
+        is_async,
+        where_clause_text,
+        is_test,
+        is_cfg_test,
+        lifetime_count,
+        is_public,
+        visibility,
+        takes_no_args,
+        overrides_trait_method,
+        overloads,
+    }
+}
+
+impl_from_lua! {
+    FunctionTypeValue {
+        location,
+        args_struct,
+        return_type: Option<TypeValue> => return_type.map(Box::new),
+        item_id,
+        attributes,
+        doc_comment,
+        is_async,
+        where_clause_text,
+        is_test,
+        is_cfg_test,
+        lifetime_count,
+        is_public,
+        visibility,
+        takes_no_args,
+        overrides_trait_method,
+        overloads: Option<Vec<FunctionTypeValue>> => overloads.unwrap_or_default(),
+    }
+}
+
+impl FunctionTypeValue {
+    /// Folds functions sharing a path and `owner` into the `overloads` of the first of them, in the
+    /// order given. `owner` tells apart the types functions are defined on, since the impl blocks
+    /// of two types in one module give their functions the same path
+    pub fn group_overloads<K: PartialEq>(
+        functions: impl IntoIterator<Item = (K, FunctionTypeValue)>,
+    ) -> Vec<FunctionTypeValue> {
+        let mut grouped: Vec<(K, FunctionTypeValue)> = Vec::new();
+        for (owner, function) in functions {
+            let first = grouped.iter_mut().find(|(first_owner, first)| {
+                *first_owner == owner
+                    && first.location.crate_name == function.location.crate_name
+                    && first.location.path == function.location.path
+            });
+
+            match first {
+                Some((_, first)) => first.overloads.push(function),
+                None => grouped.push((owner, function)),
+            }
+        }
+
+        grouped.into_iter().map(|(_, function)| function).collect()
+    }
+
+    /// Whether the function is left out of queries unless the script asks for hidden items with
+    /// `rtk.set_include_hidden(true)`, being either `#[doc(hidden)]` or not plainly `pub`
+    pub fn is_hidden(&self) -> bool {
+        !self.is_public || Attribute::is_doc_hidden(&self.attributes)
+    }
+}
+
+/// An attribute in the source code.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Attribute {
     pub name: String,
     // in the case of a rename, this will be `"my_name"` _NOT_ `my_name`
     pub value_str: Option<String>,
+    /// Whether this is a `#[derive(...)]` attribute
+    pub is_derive: bool,
+    /// The paths of the traits listed in a derive attribute, e.g. `serde::Serialize`. Empty for
+    /// any other attribute
+    pub derived_traits: Vec<String>,
+}
+
+impl Attribute {
+    pub fn new(name: String, value_str: Option<String>) -> Self {
+        let is_derive = name == "derive";
+        let derived_traits = match &value_str {
+            Some(value_str) if is_derive => value_str
+                .split(',')
+                .map(str::trim)
+                .filter(|t| !t.is_empty())
+                .map(String::from)
+                .collect(),
+            _ => vec![],
+        };
+
+        Attribute {
+            name,
+            value_str,
+            is_derive,
+            derived_traits,
+        }
+    }
+
+    /// The first of `attributes` called `name`, e.g. `serde` for `#[serde(rename = "id")]`
+    pub fn find<'a>(attributes: &'a [Attribute], name: &str) -> Option<&'a Attribute> {
+        attributes.iter().find(|attr| attr.name == name)
+    }
+
+    /// The simple names of every trait derived across `attributes`, with any crate path dropped,
+    /// e.g. `["Debug", "Serialize"]` for `#[derive(Debug, serde::Serialize)]`
+    pub fn derives_of(attributes: &[Attribute]) -> Vec<String> {
+        attributes
+            .iter()
+            .flat_map(|attr| &attr.derived_traits)
+            .map(|t| t.rsplit("::").next().unwrap_or(t).trim().to_string())
+            .collect()
+    }
+
+    /// Whether `attributes` include `#[doc(hidden)]`, alone or alongside other `doc` arguments
+    pub fn is_doc_hidden(attributes: &[Attribute]) -> bool {
+        attributes.iter().any(|attr| {
+            attr.name == "doc"
+                && attr.value_str.as_deref().is_some_and(|value_str| {
+                    split_top_level_args(value_str).any(|arg| arg.trim() == "hidden")
+                })
+        })
+    }
+
+    /// The argument keys of a `#[serde(...)]` attribute, e.g. `default` and `rename` for
+    /// `#[serde(default, rename = "id")]`. Yields nothing for any other attribute
+    pub fn serde_keys(&self) -> impl Iterator<Item = &str> {
+        let args = match &self.value_str {
+            Some(value_str) if self.name == "serde" => value_str.as_str(),
+            _ => "",
+        };
+
+        split_top_level_args(args)
+            .map(|arg| arg.split(['=', '(']).next().unwrap_or_default().trim())
+            .filter(|key| !key.is_empty())
+    }
+
+    /// The value given to `key` in a `#[serde(...)]` attribute with its quotes stripped, e.g. `id`
+    /// for `rename` in `#[serde(rename = "id")]`. `None` if the key isn't given a value
+    pub fn serde_value(&self, key: &str) -> Option<String> {
+        let args = match &self.value_str {
+            Some(value_str) if self.name == "serde" => value_str.as_str(),
+            _ => return None,
+        };
+
+        split_top_level_args(args).find_map(|arg| {
+            let (arg_key, value) = arg.split_once('=')?;
+            (arg_key.trim() == key).then(|| value.trim().trim_matches('"').to_string())
+        })
+    }
+}
+
+/// Splits attribute arguments on commas, leaving commas inside string literals and nested
+/// delimited groups like `with(a, b)` alone
+fn split_top_level_args(args: &str) -> impl Iterator<Item = &str> {
+    let mut in_str = false;
+    let mut depth = 0usize;
+    let mut prev = None;
+
+    args.split(move |c| {
+        let escaped = prev == Some('\\');
+        prev = Some(c);
+
+        match c {
+            '"' if !escaped => in_str = !in_str,
+            '(' | '[' | '{' if !in_str => depth += 1,
+            ')' | ']' | '}' if !in_str => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+
+        c == ',' && !in_str && depth == 0
+    })
 }
 
 impl_into_lua! {
     Attribute {
         name,
         value_str,
+        is_derive,
+        derived_traits,
+    }
+}
+
+impl_from_lua! {
+    Attribute {
+        name,
+        value_str,
+        is_derive,
+        derived_traits,
     }
 }
 
@@ -461,6 +1969,8 @@ pub struct FunctionCall {
     pub location: Location,
     pub args: Vec<Value>,
     pub in_item_id: String,
+    /// Whether the call sits in code only compiled for tests, i.e. beneath a `#[cfg(test)]` module
+    pub is_in_test_module: bool,
 }
 
 impl_into_lua! {
@@ -468,20 +1978,1252 @@ impl_into_lua! {
         location,
         args,
         in_item_id,
+        is_in_test_module,
+    }
+}
+
+/// A place a struct is constructed with a struct expression, like `User { id, name }`
+#[derive(Clone, Debug)]
+pub struct StructLiteralSite {
+    /// The location of the struct, or of the variant for enum struct variants
+    pub location: Location,
+    /// The fields as written in the expression, in order. Fields whose value can't be elevated are
+    /// left out
+    pub fields: Vec<(String, Value)>,
+    pub in_item_id: String,
+}
+
+impl_into_lua! {
+    StructLiteralSite {
+        location,
+        fields => fields
+            .into_iter()
+            .map(|(name, value)| StructLiteralField { name, value })
+            .collect::<Vec<_>>(),
+        in_item_id,
+    }
+}
+
+/// A place a bang macro is invoked, like `define_route!("/users", get_users)`
+#[derive(Clone, Debug)]
+pub struct MacroCall {
+    /// The name the macro is invoked by, without the `!`
+    pub name: String,
+    /// The whole invocation as written in source
+    pub text: String,
+    /// What was written between the invocation's delimiters, e.g. `"/users", get_users`
+    pub args_text: String,
+    pub in_item_id: String,
+}
+
+impl MacroCall {
+    /// Computes `args_text` from the `text` of an invocation
+    pub fn args_text_of(text: &str) -> String {
+        let Some((_, delimited)) = text.split_once('!') else {
+            return String::new();
+        };
+
+        let delimited = delimited.trim();
+        let mut chars = delimited.chars();
+        match (chars.next(), chars.next_back()) {
+            (Some('(' | '[' | '{'), Some(')' | ']' | '}')) => chars.as_str().trim().to_string(),
+            _ => String::new(),
+        }
+    }
+}
+
+impl_into_lua! {
+    MacroCall {
+        name,
+        text,
+        args_text,
+        in_item_id,
+    }
+}
+
+/// A `const` item, like `const MAX_RETRIES: u32 = 3;`
+#[derive(Clone, Debug)]
+pub struct ConstantValue {
+    pub location: Location,
+    pub type_value: TypeValue,
+    /// The value the constant is initialized with, if it can be elevated
+    pub value: Option<Value>,
+    pub doc_comment: Option<String>,
+    pub attributes: Vec<Attribute>,
+}
+
+impl_into_lua! {
+    ConstantValue {
+        location,
+        type_value,
+        value,
+        doc_comment,
+        attributes,
+    }
+}
+
+/// A field of a struct as serde serializes it
+#[derive(Clone, Debug)]
+pub struct SerdeField {
+    /// The name the field is serialized under
+    pub name: String,
+    pub field: StructTypeValueField,
+}
+
+impl SerdeField {
+    /// The fields of `s` serde serializes, leaving out those with `#[serde(skip)]` or
+    /// `#[serde(skip_serializing)]`. Fields are named by their own `#[serde(rename = "...")]`, or
+    /// failing that by the struct's `#[serde(rename_all = "...")]`. Renames split into separate
+    /// `serialize` and `deserialize` names are not followed
+    pub fn of_struct(s: &StructTypeValue) -> Vec<SerdeField> {
+        let rename_all = s
+            .attributes
+            .iter()
+            .find_map(|attr| attr.serde_value("rename_all"));
+
+        s.fields
+            .iter()
+            .filter(|field| !field.has_serde_skip)
+            .map(|field| {
+                let name = match (&field.serde_rename, &field.name) {
+                    (Some(rename), _) => rename.clone(),
+                    (None, Either::Right(name)) => match &rename_all {
+                        Some(rule) => apply_serde_rename_rule(rule, name),
+                        None => name.clone(),
+                    },
+                    (None, Either::Left(index)) => index.to_string(),
+                };
+
+                SerdeField {
+                    name,
+                    field: field.clone(),
+                }
+            })
+            .collect()
+    }
+}
+
+impl_into_lua! {
+    SerdeField {
+        name,
+        field,
+    }
+}
+
+/// Renames a snake_case field name following one of serde's `rename_all` rules, leaving it alone
+/// for rules serde doesn't have
+fn apply_serde_rename_rule(rule: &str, name: &str) -> String {
+    let pascal = || {
+        name.split('_')
+            .map(|word| {
+                let mut chars = word.chars();
+                chars
+                    .next()
+                    .map(|first| first.to_uppercase().chain(chars).collect::<String>())
+                    .unwrap_or_default()
+            })
+            .collect::<String>()
+    };
+
+    match rule {
+        "lowercase" | "snake_case" => name.to_lowercase(),
+        "UPPERCASE" | "SCREAMING_SNAKE_CASE" => name.to_uppercase(),
+        "PascalCase" => pascal(),
+        "camelCase" => {
+            let pascal = pascal();
+            let mut chars = pascal.chars();
+            chars
+                .next()
+                .map(|first| first.to_lowercase().chain(chars).collect())
+                .unwrap_or_default()
+        }
+        "kebab-case" => name.replace('_', "-"),
+        "SCREAMING-KEBAB-CASE" => name.replace('_', "-").to_uppercase(),
+        _ => name.to_string(),
     }
 }
 
 #[derive(Clone, Debug)]
 pub struct TraitImpl {
     pub trait_location: Location,
+    /// The generic args the trait is implemented with, not including `Self`. For instance
+    /// `impl From<String> for Name` has a single `String` arg. Args that can't be represented are
+    /// kept as [`TypeValue::Unsupported`], so each arg stays in its position
+    pub trait_generic_args: Vec<TypeValue>,
     pub for_type: TypeValue,
+    /// The attributes on the declaration of the type the trait is implemented for, looking through
+    /// references. Empty for types without a declaration of their own, like primitives and tuples
+    pub for_type_attributes: Vec<Attribute>,
     pub functions: Vec<FunctionTypeValue>,
 }
 
 impl_into_lua! {
     TraitImpl {
         trait_location,
+        trait_generic_args,
         for_type,
+        for_type_attributes,
+        functions,
+    }
+}
+
+/// A trait as it is declared, for generating an interface from its methods
+#[derive(Clone, Debug)]
+pub struct TraitDef {
+    pub location: Location,
+    pub doc_comment: Option<String>,
+    pub attributes: Vec<Attribute>,
+    /// Every method the trait declares, whether or not it gives a default body
+    pub functions: Vec<FunctionTypeValue>,
+    /// The names of the trait's associated types, e.g. `Item` for `type Item;`
+    pub associated_types: Vec<String>,
+    /// The trait's associated consts, with a `value` only when they are given a default
+    pub associated_consts: Vec<ConstantValue>,
+}
+
+impl_into_lua! {
+    TraitDef {
+        location,
+        doc_comment,
+        attributes,
         functions,
+        associated_types,
+        associated_consts,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{function_type_value, struct_field, struct_type_value};
+
+    fn render(lua: &Lua, template: &str, values: &str) -> mlua::Result<String> {
+        let values: mlua::Table = lua.load(values).eval()?;
+        render_template(template, &values)
+    }
+
+    #[test]
+    fn render_substitutes_top_level_keys() {
+        let lua = Lua::new();
+        let rendered = render(
+            &lua,
+            "{{name}}: {{ count }} ({{ enabled }}, {{ ratio }})",
+            r#"{ name = "users", count = 3, enabled = true, ratio = 0.5 }"#,
+        )
+        .unwrap();
+
+        assert_eq!(rendered, "users: 3 (true, 0.5)");
+    }
+
+    #[test]
+    fn render_walks_nested_tables() {
+        let lua = Lua::new();
+        let rendered = render(
+            &lua,
+            "{{ user.name }} is {{ user.tags.2 }}",
+            r#"{ user = { name = "jack", tags = { "admin", "owner" } } }"#,
+        )
+        .unwrap();
+
+        assert_eq!(rendered, "jack is owner");
+    }
+
+    #[test]
+    fn render_errors_on_missing_key() {
+        let lua = Lua::new();
+        let err = render(&lua, "hello {{ nope }}", "{}").unwrap_err();
+
+        assert!(err.to_string().contains("template key `nope` is missing"));
+    }
+
+    #[test]
+    fn indent_skips_blank_lines() {
+        assert_eq!(
+            indent_text(2, "fn f() {\n\n}", DEFAULT_INDENT_UNIT),
+            "        fn f() {\n\n        }"
+        );
+        assert_eq!(indent_text(1, "a\n  \nb", "\t"), "\ta\n  \n\tb");
+    }
+
+    #[test]
+    fn dedent_strips_common_indentation() {
+        assert_eq!(
+            dedent_text("    struct A {\n        x: u32,\n\n    }"),
+            "struct A {\n    x: u32,\n\n}"
+        );
+        assert_eq!(dedent_text("  \n    a\n"), "\na\n");
+    }
+
+    #[test]
+    fn dedent_only_strips_identical_indentation() {
+        // a tab and four spaces have nothing in common
+        assert_eq!(dedent_text("\ta\n    b"), "\ta\n    b");
+        assert_eq!(dedent_text("\t\ta\n\tb"), "\ta\nb");
+        // multi-byte whitespace is stripped whole rather than sliced through
+        assert_eq!(dedent_text("\u{3000}a\n\u{3000} b"), "a\n b");
+        assert_eq!(dedent_text("\u{3000}a\n b"), "\u{3000}a\n b");
+    }
+
+    #[test]
+    fn render_errors_on_unterminated_key() {
+        let lua = Lua::new();
+        let err = render(&lua, "hello {{ name", r#"{ name = "jack" }"#).unwrap_err();
+
+        assert!(err.to_string().contains("unterminated"));
+    }
+
+    #[test]
+    fn location_formats_with_impl_block() {
+        let location = Location {
+            crate_name: "axum".to_string(),
+            path: vec!["routing".to_string(), "route".to_string()],
+            impl_block_number: Some(3),
+            is_local: false,
+        };
+
+        assert_eq!(location.to_string(), "axum::routing::route{impl#3}");
+    }
+
+    #[test]
+    fn location_formats_without_impl_block() {
+        let location = Location {
+            crate_name: "my_crate".to_string(),
+            path: vec!["handlers".to_string(), "get_user".to_string()],
+            impl_block_number: None,
+            is_local: false,
+        };
+
+        assert_eq!(location.to_string(), "my_crate::handlers::get_user");
+    }
+
+    #[test]
+    fn location_from_lua_is_not_local_but_is_the_same_item() {
+        let lua = Lua::new();
+        let declared = Location {
+            crate_name: "my_crate".to_string(),
+            path: vec!["User".to_string()],
+            impl_block_number: None,
+            is_local: true,
+        };
+
+        let query: Location = lua
+            .load(r#"{ crate_name = "my_crate", path = { "User" } }"#)
+            .eval()
+            .unwrap();
+        assert!(!query.is_local);
+        assert_ne!(query, declared);
+        assert!(query.same_item(&declared));
+
+        let round_tripped = Location::from_lua(declared.clone().into_lua(&lua).unwrap(), &lua);
+        assert!(round_tripped.unwrap().is_local);
+    }
+
+    fn round_trip(lua: &Lua, type_value: TypeValue) -> TypeValue {
+        let value = type_value.into_lua(lua).unwrap();
+        TypeValue::from_lua(value, lua).unwrap()
+    }
+
+    #[test]
+    fn type_value_struct_round_trips() {
+        let lua = Lua::new();
+        let original = TypeValue::Struct(StructTypeValue {
+            fields: vec![
+                StructTypeValueField {
+                    doc_comment: Some("The user id".to_string()),
+                    attributes: vec![Attribute::new(
+                        "serde".to_string(),
+                        Some("rename=\"userId\"".to_string()),
+                    )],
+                    ..struct_field(Either::Right("id".to_string()), TypeValue::U32)
+                },
+                struct_field(
+                    Either::Right("tags".to_string()),
+                    TypeValue::Vec(Box::new(TypeValue::String)),
+                ),
+            ],
+            ..struct_type_value(Location {
+                crate_name: "my_crate".to_string(),
+                path: vec!["models".to_string(), "User".to_string()],
+                impl_block_number: None,
+                is_local: false,
+            })
+        });
+
+        assert_eq!(round_trip(&lua, original.clone()), original);
+    }
+
+    #[test]
+    fn enum_variants_keep_their_shape() {
+        // enum Shape { Circle { radius: f64 }, Rect(f64, f64) }
+        let location = Location {
+            crate_name: "my_crate".to_string(),
+            path: vec!["Shape".to_string()],
+            impl_block_number: None,
+            is_local: true,
+        };
+        let field = |name: Either<usize, String>| struct_field(name, TypeValue::F64);
+        let variant = |name: &str, kind: StructKind, fields: Vec<StructTypeValueField>| {
+            EnumTypeValueVariant {
+                name: name.to_string(),
+                value: Some(TypeValue::Struct(StructTypeValue {
+                    fields,
+                    kind,
+                    ..struct_type_value(location.clone())
+                })),
+                doc_comment: None,
+                attributes: vec![],
+                serde_rename: None,
+            }
+        };
+
+        let lua = Lua::new();
+        let original = TypeValue::Enum(EnumTypeValue {
+            location: location.clone(),
+            variants: vec![
+                variant(
+                    "Circle",
+                    StructKind::Named,
+                    vec![field(Either::Right("radius".to_string()))],
+                ),
+                variant(
+                    "Rect",
+                    StructKind::Tuple,
+                    vec![field(Either::Left(0)), field(Either::Left(1))],
+                ),
+            ],
+            doc_comment: None,
+            attributes: vec![],
+            is_c_like: false,
+            type_hash: 0,
+            derives: vec![],
+            is_non_exhaustive: false,
+            serde_tag: None,
+        });
+
+        lua.globals()
+            .set("shape", original.clone().into_lua(&lua).unwrap())
+            .unwrap();
+        let shapes: String = lua
+            .load(
+                r#"
+                local shapes = {}
+                for _, variant in ipairs(shape.variant_data.variants) do
+                    local value = variant.value.variant_data
+                    local names = {}
+                    for _, field in ipairs(value.fields) do
+                        table.insert(names, tostring(field.name))
+                    end
+                    table.insert(
+                        shapes,
+                        variant.name .. ":" .. value.kind.variant_name .. "(" .. table.concat(names, ",") .. ")"
+                    )
+                end
+                return table.concat(shapes, ";")
+            "#,
+            )
+            .eval()
+            .unwrap();
+
+        assert_eq!(shapes, "Circle:Named(radius);Rect:Tuple(0,1)");
+        assert_eq!(round_trip(&lua, original.clone()), original);
+    }
+
+    #[test]
+    fn enum_variants_keep_their_declaration_order() {
+        // enum Weekday { Monday, Tuesday, Wednesday, Thursday, Friday }
+        let lua = Lua::new();
+        let names = ["Monday", "Tuesday", "Wednesday", "Thursday", "Friday"];
+        let original = TypeValue::Enum(EnumTypeValue {
+            location: Location {
+                crate_name: "my_crate".to_string(),
+                path: vec!["Weekday".to_string()],
+                impl_block_number: None,
+                is_local: true,
+            },
+            variants: names
+                .iter()
+                .map(|name| EnumTypeValueVariant {
+                    name: name.to_string(),
+                    value: None,
+                    doc_comment: None,
+                    attributes: vec![],
+                    serde_rename: None,
+                })
+                .collect(),
+            doc_comment: None,
+            attributes: vec![],
+            is_c_like: true,
+            type_hash: 0,
+            derives: vec![],
+            serde_tag: None,
+            is_non_exhaustive: false,
+        });
+
+        lua.globals()
+            .set("weekday", original.clone().into_lua(&lua).unwrap())
+            .unwrap();
+        let order: Vec<String> = lua
+            .load(
+                r#"
+                local order = {}
+                for _, variant in ipairs(weekday.variant_data.variants) do
+                    table.insert(order, variant.name)
+                end
+                return order
+                "#,
+            )
+            .eval()
+            .unwrap();
+
+        assert_eq!(order, names);
+        assert_eq!(round_trip(&lua, original.clone()), original);
+    }
+
+    #[test]
+    fn newtype_struct_exposes_its_inner_type() {
+        // struct UserId(u64)
+        let lua = Lua::new();
+        let field = |name: usize| struct_field(Either::Left(name), TypeValue::U64);
+        let fields = vec![field(0)];
+        let original = TypeValue::Struct(StructTypeValue {
+            newtype_inner: StructTypeValue::newtype_inner_of(StructKind::Tuple, &fields),
+            fields,
+            kind: StructKind::Tuple,
+            ..struct_type_value(Location {
+                crate_name: "my_crate".to_string(),
+                path: vec!["UserId".to_string()],
+                impl_block_number: None,
+                is_local: true,
+            })
+        });
+
+        lua.globals()
+            .set("user_id", original.clone().into_lua(&lua).unwrap())
+            .unwrap();
+        let inner: String = lua
+            .load("return user_id.variant_data.newtype_inner.variant_name")
+            .eval()
+            .unwrap();
+
+        assert_eq!(inner, "U64");
+        assert_eq!(round_trip(&lua, original.clone()), original);
+        assert_eq!(
+            StructTypeValue::newtype_inner_of(StructKind::Tuple, &[field(0), field(1)]),
+            None
+        );
+        assert_eq!(
+            StructTypeValue::newtype_inner_of(StructKind::Named, &[field(0)]),
+            None
+        );
+    }
+
+    #[test]
+    fn hand_written_serde_impls_are_flagged_as_custom() {
+        let lua = Lua::new();
+        let derives = vec!["Debug".to_string(), "Serialize".to_string()];
+        let original = TypeValue::Struct(StructTypeValue {
+            kind: StructKind::Unit,
+            has_custom_serialize: StructTypeValue::is_custom_impl(&derives, "Serialize", true),
+            has_custom_deserialize: StructTypeValue::is_custom_impl(&derives, "Deserialize", true),
+            derives,
+            ..struct_type_value(Location {
+                crate_name: "my_crate".to_string(),
+                path: vec!["Timestamp".to_string()],
+                impl_block_number: None,
+                is_local: true,
+            })
+        });
+
+        lua.globals()
+            .set("timestamp", original.clone().into_lua(&lua).unwrap())
+            .unwrap();
+        let (serialize, deserialize): (bool, bool) = lua
+            .load(
+                "return timestamp.variant_data.has_custom_serialize, \
+                 timestamp.variant_data.has_custom_deserialize",
+            )
+            .eval()
+            .unwrap();
+
+        assert!(!serialize);
+        assert!(deserialize);
+        assert!(!StructTypeValue::is_custom_impl(&[], "Deserialize", false));
+        assert_eq!(round_trip(&lua, original.clone()), original);
+    }
+
+    #[test]
+    fn type_hash_keys_tables_as_an_integer() {
+        let lua = Lua::new();
+        let user = TypeValue::Struct(StructTypeValue {
+            // the driver keeps hashes within 63 bits
+            type_hash: u64::MAX >> 1,
+            ..struct_type_value(Location {
+                crate_name: "my_crate".to_string(),
+                path: vec!["User".to_string()],
+                impl_block_number: None,
+                is_local: true,
+            })
+        });
+
+        lua.globals()
+            .set("as_field", user.clone().into_lua(&lua).unwrap())
+            .unwrap();
+        lua.globals()
+            .set("as_return", user.clone().into_lua(&lua).unwrap())
+            .unwrap();
+        let (is_integer, deduplicated): (bool, bool) = lua
+            .load(
+                r#"
+                local seen = {}
+                seen[as_field.variant_data.type_hash] = true
+                return math.type(as_field.variant_data.type_hash) == "integer",
+                    seen[as_return.variant_data.type_hash] == true
+                "#,
+            )
+            .eval()
+            .unwrap();
+
+        assert!(is_integer);
+        assert!(deduplicated);
+        assert_eq!(round_trip(&lua, user.clone()), user);
+    }
+
+    #[test]
+    fn struct_layout_round_trips() {
+        // #[repr(C)] struct Header { a: u32, b: u8 }
+        let lua = Lua::new();
+        let field = |name: &str, value| struct_field(Either::Right(name.to_string()), value);
+        let original = TypeValue::Struct(StructTypeValue {
+            fields: vec![field("a", TypeValue::U32), field("b", TypeValue::U8)],
+            attributes: vec![Attribute::new("repr".to_string(), Some("C".to_string()))],
+            // padded out to a multiple of the u32's alignment
+            size: Some(8),
+            align: Some(4),
+            ..struct_type_value(Location {
+                crate_name: "my_crate".to_string(),
+                path: vec!["Header".to_string()],
+                impl_block_number: None,
+                is_local: true,
+            })
+        });
+
+        lua.globals()
+            .set("header", original.clone().into_lua(&lua).unwrap())
+            .unwrap();
+        let (size, align): (u64, u64) = lua
+            .load("return header.variant_data.size, header.variant_data.align")
+            .eval()
+            .unwrap();
+
+        assert_eq!((size, align), (8, 4));
+        assert_eq!(round_trip(&lua, original.clone()), original);
+    }
+
+    #[test]
+    fn type_value_option_round_trips() {
+        let lua = Lua::new();
+        let original = TypeValue::Option(Box::new(TypeValue::Tuple(vec![
+            TypeValue::Bool,
+            TypeValue::I64,
+        ])));
+
+        assert_eq!(round_trip(&lua, original.clone()), original);
+    }
+
+    #[test]
+    fn struct_half_and_quad_float_fields_round_trip() {
+        // struct Sample { half: f16, quad: f128 }
+        let lua = Lua::new();
+        let field =
+            |name: &str, value: TypeValue| struct_field(Either::Right(name.to_string()), value);
+        let original = TypeValue::Struct(StructTypeValue {
+            fields: vec![
+                field("half", TypeValue::F16),
+                field("quad", TypeValue::F128),
+            ],
+            ..struct_type_value(Location {
+                crate_name: "my_crate".to_string(),
+                path: vec!["Sample".to_string()],
+                impl_block_number: None,
+                is_local: true,
+            })
+        });
+
+        lua.globals()
+            .set("arg", original.clone().into_lua(&lua).unwrap())
+            .unwrap();
+        let (half, quad): (String, String) = lua
+            .load(
+                "return arg.variant_data.fields[1].value.variant_name, \
+                 arg.variant_data.fields[2].value.variant_name",
+            )
+            .eval()
+            .unwrap();
+
+        assert_eq!((half.as_str(), quad.as_str()), ("F16", "F128"));
+        assert!(TypeValue::F16.is_primitive() && TypeValue::F128.is_primitive());
+        assert_eq!(round_trip(&lua, original.clone()), original);
+    }
+
+    #[test]
+    fn type_value_non_zero_round_trips() {
+        let lua = Lua::new();
+        let original = TypeValue::NonZero(Box::new(TypeValue::U32));
+
+        assert_eq!(round_trip(&lua, original.clone()), original);
+    }
+
+    #[test]
+    fn type_value_map_exposes_key_and_value_types() {
+        let lua = Lua::new();
+        let original = TypeValue::Map {
+            key: Box::new(TypeValue::String),
+            value: Box::new(TypeValue::Vec(Box::new(TypeValue::U32))),
+            is_ordered: false,
+        };
+
+        lua.globals()
+            .set("map", original.clone().into_lua(&lua).unwrap())
+            .unwrap();
+        let (key, value): (String, String) = lua
+            .load(
+                "return map.variant_data.key.variant_name, \
+                 map.variant_data.value.variant_data.variant_name",
+            )
+            .eval()
+            .unwrap();
+
+        assert_eq!((key.as_str(), value.as_str()), ("String", "U32"));
+        assert_eq!(round_trip(&lua, original.clone()), original);
+    }
+
+    #[test]
+    fn type_value_map_tells_btree_maps_apart() {
+        let lua = Lua::new();
+        let map = |is_ordered| TypeValue::Map {
+            key: Box::new(TypeValue::U64),
+            value: Box::new(TypeValue::String),
+            is_ordered,
+        };
+
+        for original in [map(false), map(true)] {
+            lua.globals()
+                .set("map", original.clone().into_lua(&lua).unwrap())
+                .unwrap();
+            let (variant_name, is_ordered): (String, bool) = lua
+                .load("return map.variant_name, map.variant_data.is_ordered")
+                .eval()
+                .unwrap();
+
+            assert_eq!(variant_name, "Map");
+            assert_eq!(map(is_ordered), original);
+            assert_eq!(round_trip(&lua, original.clone()), original);
+        }
+    }
+
+    #[test]
+    fn type_value_result_round_trips() {
+        let lua = Lua::new();
+        let original = TypeValue::Result(
+            Box::new(TypeValue::Option(Box::new(TypeValue::U64))),
+            Box::new(TypeValue::String),
+        );
+
+        assert_eq!(round_trip(&lua, original.clone()), original);
+    }
+
+    #[test]
+    fn type_value_double_option_stays_nested() {
+        let lua = Lua::new();
+        let original = TypeValue::Option(Box::new(TypeValue::Option(Box::new(TypeValue::String))));
+
+        assert_eq!(round_trip(&lua, original.clone()), original);
+    }
+
+    #[test]
+    fn serde_default_or_skip_makes_field_optional() {
+        let serde = |args: &str| vec![Attribute::new("serde".to_string(), Some(args.to_string()))];
+
+        assert!(StructTypeValueField::is_optional_for(
+            &TypeValue::U32,
+            &serde("default")
+        ));
+        assert!(StructTypeValueField::is_optional_for(
+            &TypeValue::Vec(Box::new(TypeValue::String)),
+            &serde("skip_serializing_if=\"Vec::is_empty\"")
+        ));
+        assert!(!StructTypeValueField::is_optional_for(
+            &TypeValue::U32,
+            &serde("rename=\"default, skip_serializing_if\"")
+        ));
+        assert!(StructTypeValueField::is_optional_for(
+            &TypeValue::Option(Box::new(TypeValue::U32)),
+            &[]
+        ));
+        assert!(!StructTypeValueField::is_optional_for(&TypeValue::U32, &[]));
+    }
+
+    #[test]
+    fn serde_skip_and_rename_are_read_from_attributes() {
+        let serde = |args: &str| vec![Attribute::new("serde".to_string(), Some(args.to_string()))];
+
+        assert!(StructTypeValueField::has_serde_skip_for(&serde("skip")));
+        assert!(StructTypeValueField::has_serde_skip_for(&serde(
+            "default,skip_serializing"
+        )));
+        assert!(!StructTypeValueField::has_serde_skip_for(&serde(
+            "skip_serializing_if=\"Option::is_none\""
+        )));
+
+        assert_eq!(
+            StructTypeValueField::serde_rename_for(&serde("default,rename=\"userId\"")),
+            Some("userId".to_string())
+        );
+        assert_eq!(
+            StructTypeValueField::serde_rename_for(&serde("rename_all=\"camelCase\"")),
+            None
+        );
+        assert_eq!(StructTypeValueField::serde_rename_for(&[]), None);
+    }
+
+    #[test]
+    fn enum_serde_tag_and_variant_rename_are_read_from_attributes() {
+        let serde = |args: &str| vec![Attribute::new("serde".to_string(), Some(args.to_string()))];
+
+        assert_eq!(
+            EnumTypeValue::serde_tag_for(&serde("tag=\"type\",rename_all=\"camelCase\"")),
+            Some("type".to_string())
+        );
+        assert_eq!(
+            EnumTypeValue::serde_tag_for(&serde("tag=\"t\",content=\"c\"")),
+            Some("t".to_string())
+        );
+        assert_eq!(EnumTypeValue::serde_tag_for(&serde("untagged")), None);
+
+        assert_eq!(
+            EnumTypeValueVariant::serde_rename_for(&serde("rename=\"user_created\"")),
+            Some("user_created".to_string())
+        );
+        assert_eq!(
+            EnumTypeValueVariant::serde_rename_for(&serde("other")),
+            None
+        );
+    }
+
+    #[test]
+    fn serde_rename_rules_follow_serde() {
+        let cases = [
+            ("lowercase", "user_id"),
+            ("UPPERCASE", "USER_ID"),
+            ("PascalCase", "UserId"),
+            ("camelCase", "userId"),
+            ("snake_case", "user_id"),
+            ("SCREAMING_SNAKE_CASE", "USER_ID"),
+            ("kebab-case", "user-id"),
+            ("SCREAMING-KEBAB-CASE", "USER-ID"),
+            ("not a rule", "user_id"),
+        ];
+
+        for (rule, expected) in cases {
+            assert_eq!(apply_serde_rename_rule(rule, "user_id"), expected, "{rule}");
+        }
+    }
+
+    #[test]
+    fn macro_call_args_text_strips_the_invocation() {
+        assert_eq!(
+            MacroCall::args_text_of("define_route!(\"/users\", get_users)"),
+            "\"/users\", get_users"
+        );
+        assert_eq!(
+            MacroCall::args_text_of("routes! {\n    get_users,\n}"),
+            "get_users,"
+        );
+        assert_eq!(MacroCall::args_text_of("vec![]"), "");
+        assert_eq!(MacroCall::args_text_of("not_a_macro"), "");
+    }
+
+    #[test]
+    fn serde_keys_skip_commas_in_nested_groups() {
+        let attr = Attribute::new(
+            "serde".to_string(),
+            Some(
+                "bound(serialize=\"T: Serialize\", deserialize=\"T: Deserialize\"),default"
+                    .to_string(),
+            ),
+        );
+
+        assert_eq!(
+            attr.serde_keys().collect::<Vec<_>>(),
+            vec!["bound", "default"]
+        );
+    }
+
+    #[test]
+    fn method_call_query_reads_receiver_type_filter() {
+        let lua = Lua::new();
+        let value = lua
+            .load(
+                r#"{
+                    location = { crate_name = "http", path = { "builder", "build" } },
+                    receiver_type_filter = { crate_name = "http", path = { "builder", "RequestBuilder" } },
+                }"#,
+            )
+            .eval::<mlua::Value>()
+            .unwrap();
+
+        let query = MethodCallQuery::from_lua(value, &lua).unwrap();
+        assert_eq!(
+            query.receiver_type_filter,
+            Some(Location {
+                crate_name: "http".to_string(),
+                path: vec!["builder".to_string(), "RequestBuilder".to_string()],
+                impl_block_number: None,
+                is_local: false,
+            })
+        );
+    }
+
+    #[test]
+    fn struct_kinds_round_trip() {
+        let lua = Lua::new();
+        for kind in [StructKind::Named, StructKind::Tuple, StructKind::Unit] {
+            let value = kind.into_lua(&lua).unwrap();
+            assert_eq!(StructKind::from_lua(value, &lua).unwrap(), kind);
+        }
+    }
+
+    #[test]
+    fn derive_attribute_lists_traits() {
+        let attr = Attribute::new(
+            "derive".to_string(),
+            Some("Debug, Clone,serde::Serialize".to_string()),
+        );
+
+        assert!(attr.is_derive);
+        assert_eq!(
+            attr.derived_traits,
+            vec!["Debug", "Clone", "serde::Serialize"]
+        );
+    }
+
+    #[test]
+    fn non_derive_attribute_has_no_traits() {
+        let attr = Attribute::new("serde".to_string(), Some("rename=\"a, b\"".to_string()));
+
+        assert!(!attr.is_derive);
+        assert!(attr.derived_traits.is_empty());
+    }
+
+    #[test]
+    fn derives_of_uses_simple_trait_names() {
+        let attributes = vec![
+            Attribute::new("derive".to_string(), Some("Debug, Clone".to_string())),
+            Attribute::new(
+                "serde".to_string(),
+                Some("rename_all = \"camelCase\"".to_string()),
+            ),
+            Attribute::new(
+                "derive".to_string(),
+                Some("serde::Serialize, ::serde::Deserialize".to_string()),
+            ),
+        ];
+
+        assert_eq!(
+            Attribute::derives_of(&attributes),
+            vec!["Debug", "Clone", "Serialize", "Deserialize"]
+        );
+    }
+
+    #[test]
+    fn type_value_generic_round_trips() {
+        let lua = Lua::new();
+        let original = TypeValue::Generic("T".to_string());
+
+        assert_eq!(round_trip(&lua, original.clone()), original);
+    }
+
+    #[test]
+    fn type_value_impl_trait_exposes_its_bound() {
+        // fn f(x: impl Into<String>)
+        let lua = Lua::new();
+        let original = TypeValue::ImplTrait {
+            bound: Location {
+                crate_name: "core".to_string(),
+                path: vec!["convert".to_string(), "Into".to_string()],
+                impl_block_number: None,
+                is_local: false,
+            },
+        };
+
+        lua.globals()
+            .set("arg", original.clone().into_lua(&lua).unwrap())
+            .unwrap();
+        let bound: String = lua
+            .load("return arg.variant_data.bound.path[#arg.variant_data.bound.path]")
+            .eval()
+            .unwrap();
+
+        assert_eq!(bound, "Into");
+        assert_eq!(round_trip(&lua, original.clone()), original);
+    }
+
+    #[test]
+    fn struct_keeps_fields_of_unsupported_types() {
+        // struct Handler { callback: fn(u32) -> bool, id: usize }
+        let lua = Lua::new();
+        let field =
+            |name: &str, value: TypeValue| struct_field(Either::Right(name.to_string()), value);
+        let original = TypeValue::Struct(StructTypeValue {
+            fields: vec![
+                field(
+                    "callback",
+                    TypeValue::Unsupported {
+                        rust_type: "fn(u32) -> bool".to_string(),
+                    },
+                ),
+                field("id", TypeValue::Usize),
+            ],
+            ..struct_type_value(Location {
+                crate_name: "my_crate".to_string(),
+                path: vec!["Handler".to_string()],
+                impl_block_number: None,
+                is_local: true,
+            })
+        });
+
+        lua.globals()
+            .set("arg", original.clone().into_lua(&lua).unwrap())
+            .unwrap();
+        let callback: String = lua
+            .load(
+                r#"
+                local callback = arg.variant_data.fields[1].value
+                return callback.variant_name .. ":" .. callback.variant_data.rust_type
+            "#,
+            )
+            .eval()
+            .unwrap();
+
+        assert_eq!(callback, "Unsupported:fn(u32) -> bool");
+        assert_eq!(round_trip(&lua, original.clone()), original);
+    }
+
+    #[test]
+    fn struct_raw_pointer_fields_keep_their_mutability() {
+        // struct Ffi { data: *const u8, out: *mut i32 }
+        let lua = Lua::new();
+        let field = |name: &str, mutable: bool, inner: TypeValue| {
+            struct_field(
+                Either::Right(name.to_string()),
+                TypeValue::RawPointer {
+                    mutable,
+                    inner: Box::new(inner),
+                },
+            )
+        };
+        let original = TypeValue::Struct(StructTypeValue {
+            fields: vec![
+                field("data", false, TypeValue::U8),
+                field("out", true, TypeValue::I32),
+            ],
+            ..struct_type_value(Location {
+                crate_name: "my_crate".to_string(),
+                path: vec!["Ffi".to_string()],
+                impl_block_number: None,
+                is_local: true,
+            })
+        });
+
+        lua.globals()
+            .set("arg", original.clone().into_lua(&lua).unwrap())
+            .unwrap();
+        let pointers: String = lua
+            .load(
+                r#"
+                local out = ""
+                for _, field in ipairs(arg.variant_data.fields) do
+                    local ptr = field.value.variant_data
+                    local kind = ptr.mutable and "*mut " or "*const "
+                    out = out .. field.name .. "=" .. kind .. ptr.inner.variant_name .. ";"
+                end
+                return out
+            "#,
+            )
+            .eval()
+            .unwrap();
+
+        assert_eq!(pointers, "data=*const U8;out=*mut I32;");
+        assert_eq!(round_trip(&lua, original.clone()), original);
+    }
+
+    #[test]
+    fn function_lifetime_count_round_trips() {
+        // fn f<'a>(x: &'a str)
+        let lua = Lua::new();
+        let location = Location {
+            crate_name: "my_crate".to_string(),
+            path: vec!["f".to_string()],
+            impl_block_number: None,
+            is_local: false,
+        };
+        let original = TypeValue::Function(FunctionTypeValue {
+            args_struct: StructTypeValue {
+                fields: vec![struct_field(
+                    Either::Right("x".to_string()),
+                    TypeValue::String,
+                )],
+                ..struct_type_value(location.clone())
+            },
+            lifetime_count: 1,
+            takes_no_args: false,
+            ..function_type_value(location)
+        });
+
+        assert_eq!(round_trip(&lua, original.clone()), original);
+    }
+
+    #[test]
+    fn struct_literal_keeps_field_order() {
+        let lua = Lua::new();
+        let value = Value::StructLiteral {
+            location: Location {
+                crate_name: "my_crate".to_string(),
+                path: vec!["Config".to_string()],
+                impl_block_number: None,
+                is_local: false,
+            },
+            fields: vec![
+                ("timeout".to_string(), Value::IntegerLiteral(30)),
+                ("name".to_string(), Value::StringLiteral("api".to_string())),
+            ],
+        };
+
+        let value = value.into_lua(&lua).unwrap();
+        let table = value.as_table().unwrap();
+        assert_eq!(
+            table.get::<String>("variant_name").unwrap(),
+            "StructLiteral"
+        );
+
+        let fields: Vec<mlua::Table> = table
+            .get::<mlua::Table>("variant_data")
+            .and_then(|data| data.get("fields"))
+            .unwrap();
+        let names: Vec<String> = fields
+            .iter()
+            .map(|f| f.get::<String>("name").unwrap())
+            .collect();
+        assert_eq!(names, vec!["timeout", "name"]);
+        assert_eq!(
+            fields[0]
+                .get::<mlua::Table>("value")
+                .and_then(|v| v.get::<i64>("variant_data"))
+                .unwrap(),
+            30
+        );
+    }
+
+    #[test]
+    fn query_without_impl_block_matches_any_impl_block() {
+        let router_new = Location {
+            crate_name: "axum".to_string(),
+            path: vec!["routing".to_string(), "new".to_string()],
+            impl_block_number: Some(2),
+            is_local: false,
+        };
+
+        let mut query = Location {
+            impl_block_number: None,
+            ..router_new.clone()
+        };
+        assert!(router_new.matches_query(&query));
+
+        query.impl_block_number = Some(2);
+        assert!(router_new.matches_query(&query));
+
+        query.impl_block_number = Some(1);
+        assert!(!router_new.matches_query(&query));
+
+        // an item outside of any impl block never matches a query for one
+        let free_fn = Location {
+            impl_block_number: None,
+            ..router_new
+        };
+        assert!(!free_fn.matches_query(&query));
+    }
+
+    #[test]
+    fn array_literal_is_a_lua_array() {
+        let lua = Lua::new();
+        let value = Value::ArrayLiteral(vec![
+            Value::IntegerLiteral(1),
+            Value::StringLiteral("two".to_string()),
+        ])
+        .into_lua(&lua)
+        .unwrap();
+
+        let elements: Vec<mlua::Table> = value.as_table().unwrap().get("variant_data").unwrap();
+        assert_eq!(elements.len(), 2);
+        assert_eq!(elements[0].get::<i64>("variant_data").unwrap(), 1);
+        assert_eq!(elements[1].get::<String>("variant_data").unwrap(), "two");
+    }
+
+    #[test]
+    fn enum_variant_literal_exposes_variant_and_inner() {
+        // Status::Active(42)
+        let lua = Lua::new();
+        let value = Value::EnumVariantLiteral {
+            location: Location {
+                crate_name: "my_crate".to_string(),
+                path: vec!["Status".to_string()],
+                impl_block_number: None,
+                is_local: true,
+            },
+            variant: "Active".to_string(),
+            inner: Some(Box::new(Value::IntegerLiteral(42))),
+        };
+
+        lua.globals()
+            .set("status", value.into_lua(&lua).unwrap())
+            .unwrap();
+        let (variant_name, enum_name, variant, inner): (String, String, String, i64) = lua
+            .load(
+                "local data = status.variant_data \
+                 return status.variant_name, data.location.path[1], data.variant, \
+                 data.inner.variant_data",
+            )
+            .eval()
+            .unwrap();
+
+        assert_eq!(
+            (
+                variant_name.as_str(),
+                enum_name.as_str(),
+                variant.as_str(),
+                inner
+            ),
+            ("EnumVariantLiteral", "Status", "Active", 42)
+        );
+    }
+
+    #[test]
+    fn type_value_from_lua_rejects_unknown_variant() {
+        let lua = Lua::new();
+        let value = lua
+            .load(r#"{ variant_name = "Nope" }"#)
+            .eval::<mlua::Value>()
+            .unwrap();
+
+        let err = TypeValue::from_lua(value, &lua).unwrap_err();
+        assert!(err.to_string().contains("unknown TypeValue variant `Nope`"));
+    }
+
+    #[test]
+    fn render_leaves_plain_text_untouched() {
+        let lua = Lua::new();
+        let rendered = render(&lua, "no keys { here }", "{}").unwrap();
+
+        assert_eq!(rendered, "no keys { here }");
     }
 }
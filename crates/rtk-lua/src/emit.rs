@@ -0,0 +1,84 @@
+use std::collections::HashSet;
+
+/// The priority used for plain `rtk.emit` calls.
+pub const DEFAULT_EMIT_PRIORITY: i64 = 0;
+
+/// Buffers emitted text so it can be reordered before being flushed to the output file. Entries
+/// are ordered by priority (lowest first), with ties keeping the order they were emitted in.
+#[derive(Clone, Debug, Default)]
+pub struct EmitBuffer {
+    entries: Vec<(i64, String)>,
+    /// Every text pushed over the buffer's life, including those already drained
+    emitted: HashSet<String>,
+}
+
+impl EmitBuffer {
+    pub fn push(&mut self, priority: i64, text: String) {
+        self.emitted.insert(text.clone());
+        self.entries.push((priority, text));
+    }
+
+    /// Push the text unless the exact same text has been pushed before, returning whether it was
+    pub fn push_unique(&mut self, priority: i64, text: String) -> bool {
+        if self.emitted.contains(&text) {
+            return false;
+        }
+
+        self.push(priority, text);
+        true
+    }
+
+    /// Drain the buffer into the final output text. `sort_by_key` is stable, so entries of the same
+    /// priority stay in insertion order
+    pub fn drain_ordered(&mut self) -> String {
+        let mut entries = std::mem::take(&mut self.entries);
+        entries.sort_by_key(|(priority, _)| *priority);
+
+        entries.into_iter().map(|(_, text)| text).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lower_priority_flushes_first() {
+        let mut buffer = EmitBuffer::default();
+        buffer.push(10, "function".to_string());
+        buffer.push(-10, "type".to_string());
+
+        assert_eq!(buffer.drain_ordered(), "typefunction");
+    }
+
+    #[test]
+    fn equal_priority_keeps_insertion_order() {
+        let mut buffer = EmitBuffer::default();
+        buffer.push(DEFAULT_EMIT_PRIORITY, "a".to_string());
+        buffer.push(1, "c".to_string());
+        buffer.push(DEFAULT_EMIT_PRIORITY, "b".to_string());
+
+        assert_eq!(buffer.drain_ordered(), "abc");
+    }
+
+    #[test]
+    fn drain_empties_the_buffer() {
+        let mut buffer = EmitBuffer::default();
+        buffer.push(DEFAULT_EMIT_PRIORITY, "a".to_string());
+
+        assert_eq!(buffer.drain_ordered(), "a");
+        assert_eq!(buffer.drain_ordered(), "");
+    }
+
+    #[test]
+    fn push_unique_skips_text_already_pushed() {
+        let mut buffer = EmitBuffer::default();
+        buffer.push(DEFAULT_EMIT_PRIORITY, "import a\n".to_string());
+
+        assert!(!buffer.push_unique(DEFAULT_EMIT_PRIORITY, "import a\n".to_string()));
+        assert!(buffer.push_unique(DEFAULT_EMIT_PRIORITY, "import b\n".to_string()));
+        assert!(!buffer.push_unique(-1, "import b\n".to_string()));
+
+        assert_eq!(buffer.drain_ordered(), "import a\nimport b\n");
+    }
+}
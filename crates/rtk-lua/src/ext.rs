@@ -9,6 +9,32 @@ pub trait TableSetFnExt {
         F: Fn(I) -> O + Send + Sync + 'static,
         I: FromLuaMulti,
         O: IntoLua;
+
+    /// Same as [`TableSetFnExt::set_rtk_api_fn`], except the function can fail and raise a Lua
+    /// error back into the script
+    fn set_rtk_api_fallible_fn<F, I, O>(
+        &self,
+        lua: &mlua::Lua,
+        key: &'static str,
+        f: F,
+    ) -> mlua::Result<()>
+    where
+        F: Fn(I) -> mlua::Result<O> + Send + Sync + 'static,
+        I: FromLuaMulti,
+        O: IntoLua;
+
+    /// Same as [`TableSetFnExt::set_rtk_api_fallible_fn`], except the function is also handed the
+    /// Lua instance for when it needs to build tables itself
+    fn set_rtk_api_fn_with_lua<F, I, O>(
+        &self,
+        lua: &mlua::Lua,
+        key: &'static str,
+        f: F,
+    ) -> mlua::Result<()>
+    where
+        F: Fn(&mlua::Lua, I) -> mlua::Result<O> + Send + Sync + 'static,
+        I: FromLuaMulti,
+        O: IntoLua;
 }
 
 impl TableSetFnExt for mlua::Table {
@@ -25,4 +51,36 @@ impl TableSetFnExt for mlua::Table {
 
         self.set(key, function)
     }
+
+    fn set_rtk_api_fallible_fn<F, I, O>(
+        &self,
+        lua: &mlua::Lua,
+        key: &'static str,
+        f: F,
+    ) -> mlua::Result<()>
+    where
+        F: Fn(I) -> mlua::Result<O> + Send + Sync + 'static,
+        I: FromLuaMulti,
+        O: IntoLua,
+    {
+        let function = lua.create_function(move |_, a: I| f(a))?;
+
+        self.set(key, function)
+    }
+
+    fn set_rtk_api_fn_with_lua<F, I, O>(
+        &self,
+        lua: &mlua::Lua,
+        key: &'static str,
+        f: F,
+    ) -> mlua::Result<()>
+    where
+        F: Fn(&mlua::Lua, I) -> mlua::Result<O> + Send + Sync + 'static,
+        I: FromLuaMulti,
+        O: IntoLua,
+    {
+        let function = lua.create_function(move |lua, a: I| f(lua, a))?;
+
+        self.set(key, function)
+    }
 }
@@ -2,41 +2,1544 @@
 //! systems for their own languages.
 
 mod api;
+mod emit;
 mod ext;
 mod macros;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+mod typescript;
 mod versioning;
 
 use anyhow::Context;
 pub use api::{
-    Attribute, ClosureTypeValue, EnumTypeValue, EnumTypeValueVariant, FunctionCall,
-    FunctionTypeValue, Location, MethodCall, MethodCallQuery, RtkLuaScriptExecutor,
-    StructTypeValue, StructTypeValueField, TraitImpl, TypeValue, Value,
+    Attribute, ClosureTypeValue, ConstantValue, EnumTypeValue, EnumTypeValueVariant, FunctionCall,
+    FunctionTypeValue, Location, MacroCall, MethodCall, MethodCallQuery, QueryEmptyBehavior,
+    RtkLuaScriptExecutor, SerdeField, StructKind, StructLiteralSite, StructTypeValue,
+    StructTypeValueField, TraitDef, TraitImpl, TypeValue, Value,
 };
+pub use emit::{DEFAULT_EMIT_PRIORITY, EmitBuffer};
 pub use mlua::Either;
 use mlua::{LuaOptions, StdLib};
 pub use versioning::RtkRustcDriverVersion;
 
+/// The global the API is injected under by default.
+pub const DEFAULT_NAMESPACE: &str = "rtk";
+
+/// The environment variable every `RtkLua` constructor reads a memory limit, in megabytes, from.
+pub const MEMORY_LIMIT_ENV_VAR: &str = "RTK_LUA_MEMORY_LIMIT_MB";
+
+/// The chunk name given to scripts executed without one, so errors read `script.lua:3: ...`
+const DEFAULT_SCRIPT_NAME: &str = "script.lua";
+
 pub struct RtkLua {
     lua: mlua::Lua,
 }
 
 impl RtkLua {
+    /// Create the Lua instance with the API injected under `rtk`. Like every constructor, if
+    /// `RTK_LUA_MEMORY_LIMIT_MB` is set the instance is limited to that much memory
     pub fn new(exec: impl RtkLuaScriptExecutor) -> anyhow::Result<Self> {
+        Self::new_with_namespace(exec, DEFAULT_NAMESPACE)
+    }
+
+    /// Create the Lua instance with its memory capped at `memory_limit_bytes`, overriding
+    /// `RTK_LUA_MEMORY_LIMIT_MB`. Scripts that go over it fail with an out of memory error rather
+    /// than taking the whole process down
+    pub fn new_with_memory_limit(
+        exec: impl RtkLuaScriptExecutor,
+        memory_limit_bytes: usize,
+    ) -> anyhow::Result<Self> {
+        let rtk_lua = Self::new_with_namespace(exec, DEFAULT_NAMESPACE)?;
+        rtk_lua.set_memory_limit(memory_limit_bytes)?;
+
+        Ok(rtk_lua)
+    }
+
+    fn set_memory_limit(&self, memory_limit_bytes: usize) -> anyhow::Result<()> {
+        self.lua
+            .set_memory_limit(memory_limit_bytes)
+            .context("failed to set Lua memory limit")?;
+
+        Ok(())
+    }
+
+    /// Create the Lua instance with the API injected under the global `namespace` rather than
+    /// `rtk`, for embedding where the `rtk` global is already taken
+    pub fn new_with_namespace(
+        exec: impl RtkLuaScriptExecutor,
+        namespace: &str,
+    ) -> anyhow::Result<Self> {
         let lua = unsafe { mlua::Lua::unsafe_new_with(StdLib::ALL, LuaOptions::new()) };
 
+        Self::with_api_injected(lua, exec, namespace)
+    }
+
+    /// Create a Lua instance for running untrusted scripts. Only the pure standard libraries are
+    /// loaded, so there is no `io`, `os` or `package` (and so no `require`) available. The base
+    /// library is always loaded, so its `dofile`, `loadfile` and `load` are removed as well
+    pub fn new_sandboxed(exec: impl RtkLuaScriptExecutor) -> anyhow::Result<Self> {
+        let libs = StdLib::COROUTINE | StdLib::TABLE | StdLib::STRING | StdLib::UTF8 | StdLib::MATH;
+        let lua = mlua::Lua::new_with(libs, LuaOptions::new())
+            .context("failed to create sandboxed Lua instance")?;
+
+        for name in ["dofile", "loadfile", "load"] {
+            lua.globals()
+                .set(name, mlua::Value::Nil)
+                .with_context(|| format!("failed to remove `{name}` from sandboxed globals"))?;
+        }
+
+        Self::with_api_injected(lua, exec, DEFAULT_NAMESPACE)
+    }
+
+    fn with_api_injected(
+        lua: mlua::Lua,
+        exec: impl RtkLuaScriptExecutor,
+        namespace: &str,
+    ) -> anyhow::Result<Self> {
         let api = lua.create_table().context("failed to create api table")?;
         api::inject(&lua, &api, exec).context("failed to inject api into table")?;
 
         lua.globals()
-            .set("rtk", api)
-            .context("failed to set rtk api in preload")?;
+            .set(namespace, api)
+            .with_context(|| format!("failed to set `{namespace}` api in globals"))?;
+
+        let rtk_lua = RtkLua { lua };
 
-        Ok(RtkLua { lua })
+        let memory_limit_mb = std::env::var(MEMORY_LIMIT_ENV_VAR).ok();
+        if let Some(memory_limit_bytes) = parse_memory_limit_mb(memory_limit_mb.as_deref())? {
+            rtk_lua.set_memory_limit(memory_limit_bytes)?;
+        }
+
+        Ok(rtk_lua)
     }
 
-    pub fn execute(&self, script: &str) -> anyhow::Result<()> {
-        self.lua.load(script).exec()?;
+    /// Register `source` as a module that scripts can load with `require(name)`. The source is
+    /// compiled straight away so syntax errors surface here, but it is only run once required
+    pub fn preload_lua_module(&self, name: &str, source: &str) -> anyhow::Result<()> {
+        let loader = self
+            .lua
+            .load(source)
+            .set_name(format!("={name}"))
+            .into_function()
+            .with_context(|| format!("failed to load Lua module `{name}`"))?;
+
+        let preload: mlua::Table = self
+            .lua
+            .globals()
+            .get::<mlua::Table>("package")
+            .and_then(|package| package.get("preload"))
+            .context("failed to get package.preload table")?;
+
+        preload
+            .set(name, loader)
+            .with_context(|| format!("failed to preload Lua module `{name}`"))?;
 
         Ok(())
     }
+
+    /// Compile the script without running it, referring to it as `name` in errors. A syntax error
+    /// is reported as just `name:<line>: <message>`, the form editors and CI annotations pick up
+    pub fn check_syntax_named(&self, script: &str, name: &str) -> anyhow::Result<()> {
+        match self
+            .lua
+            .load(script)
+            .set_name(format!("@{name}"))
+            .into_function()
+        {
+            Ok(_) => Ok(()),
+            Err(mlua::Error::SyntaxError { message, .. }) => Err(anyhow::anyhow!(message)),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn execute(&self, script: &str) -> anyhow::Result<()> {
+        self.execute_named(script, DEFAULT_SCRIPT_NAME)
+    }
+
+    /// Execute the script, referring to it as `name` in errors. Both syntax and runtime errors
+    /// then point at `name:<line>`, and runtime errors carry the Lua traceback along with them
+    pub fn execute_named(&self, script: &str, name: &str) -> anyhow::Result<()> {
+        match self.lua.load(script).set_name(format!("@{name}")).exec() {
+            Ok(()) => Ok(()),
+            Err(mlua::Error::MemoryError(e)) => Err(anyhow::anyhow!(
+                "the Lua script ran out of memory ({e}), the limit can be raised with \
+                 `{MEMORY_LIMIT_ENV_VAR}`"
+            )),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// Parses the value of `RTK_LUA_MEMORY_LIMIT_MB` into a limit in bytes
+fn parse_memory_limit_mb(value: Option<&str>) -> anyhow::Result<Option<usize>> {
+    let Some(value) = value else {
+        return Ok(None);
+    };
+
+    let memory_limit_mb: usize = value.trim().parse().with_context(|| {
+        format!("`{MEMORY_LIMIT_ENV_VAR}` must be a whole number of megabytes, got `{value}`")
+    })?;
+
+    Ok(Some(memory_limit_mb * 1024 * 1024))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{
+        MockRtkLuaScriptExecutor, function_type_value, struct_field, struct_type_value,
+    };
+
+    #[test]
+    fn api_is_injected_under_custom_namespace() {
+        let exec = MockRtkLuaScriptExecutor::default();
+        let lua = RtkLua::new_with_namespace(exec.clone(), "myns").unwrap();
+
+        lua.execute(
+            r#"
+            assert(rtk == nil, "api should not be under the default namespace")
+            myns.version("1.2.3")
+            myns.emit("hello")
+        "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            *exec.version.lock().unwrap(),
+            Some(RtkRustcDriverVersion::CratesIo {
+                major: 1,
+                minor: 2,
+                patch: 3
+            })
+        );
+        assert_eq!(exec.emitted(), "hello");
+    }
+
+    #[test]
+    fn preloaded_module_can_be_required() {
+        let exec = MockRtkLuaScriptExecutor::default();
+        let lua = RtkLua::new(exec.clone()).unwrap();
+
+        lua.preload_lua_module(
+            "my_utils",
+            r#"
+            local M = {}
+            function M.greet(name)
+                return "hello " .. name
+            end
+            return M
+        "#,
+        )
+        .unwrap();
+
+        lua.execute(
+            r#"
+            local my_utils = require("my_utils")
+            rtk.emit(my_utils.greet("rtk"))
+        "#,
+        )
+        .unwrap();
+
+        assert_eq!(exec.emitted(), "hello rtk");
+    }
+
+    #[test]
+    fn preloading_invalid_module_fails() {
+        let lua = RtkLua::new(MockRtkLuaScriptExecutor::default()).unwrap();
+
+        let result = lua.preload_lua_module("broken", "local = ");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn runtime_error_reports_line_and_traceback() {
+        let lua = RtkLua::new(MockRtkLuaScriptExecutor::default()).unwrap();
+
+        let script = "local function explode()\n    error(\"boom\")\nend\n\nexplode()\n";
+        let err = lua.execute(script).unwrap_err().to_string();
+
+        assert!(
+            err.contains("script.lua:2: boom"),
+            "unexpected error: {err}"
+        );
+        assert!(err.contains("stack traceback"), "unexpected error: {err}");
+    }
+
+    #[test]
+    fn syntax_error_reports_script_name() {
+        let lua = RtkLua::new(MockRtkLuaScriptExecutor::default()).unwrap();
+
+        let err = lua
+            .execute_named("local x = \n\n)", "my_script.lua")
+            .unwrap_err()
+            .to_string();
+
+        assert!(err.contains("my_script.lua:3:"), "unexpected error: {err}");
+    }
+
+    #[test]
+    fn check_syntax_reports_file_and_line_without_running() {
+        let exec = MockRtkLuaScriptExecutor::default();
+        let lua = RtkLua::new(exec.clone()).unwrap();
+
+        lua.check_syntax_named(r#"rtk.emit("ran")"#, "my_script.lua")
+            .unwrap();
+        assert_eq!(exec.emitted(), "");
+
+        let err = lua
+            .check_syntax_named("local x = \n\n)", "my_script.lua")
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.starts_with("my_script.lua:3:"),
+            "unexpected error: {err}"
+        );
+    }
+
+    #[test]
+    fn sandboxed_lua_has_no_os_access() {
+        let sandboxed = RtkLua::new_sandboxed(MockRtkLuaScriptExecutor::default()).unwrap();
+        sandboxed
+            .execute(
+                r#"
+                assert(os == nil, "os should not be loaded")
+                assert(io == nil, "io should not be loaded")
+                assert(require == nil, "require should not be loaded")
+                assert(dofile == nil, "dofile should not be loaded")
+                assert(loadfile == nil, "loadfile should not be loaded")
+                assert(load == nil, "load should not be loaded")
+                assert(rtk.emit ~= nil, "the api should still be injected")
+            "#,
+            )
+            .unwrap();
+
+        let unsandboxed = RtkLua::new(MockRtkLuaScriptExecutor::default()).unwrap();
+        unsandboxed
+            .execute(
+                r#"
+                assert(type(os.execute) == "function")
+                assert(type(load) == "function")
+            "#,
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn query_impls_for_type_lists_each_trait() {
+        let location = |crate_name: &str, path: &[&str]| Location {
+            crate_name: crate_name.to_string(),
+            path: path.iter().map(|s| s.to_string()).collect(),
+            impl_block_number: None,
+            is_local: false,
+        };
+
+        let exec = MockRtkLuaScriptExecutor {
+            impls_for_type: vec![
+                (
+                    location("my_crate", &["User"]),
+                    location("serde", &["ser", "Serialize"]),
+                ),
+                (
+                    location("my_crate", &["User"]),
+                    location("core", &["clone", "Clone"]),
+                ),
+                (
+                    location("my_crate", &["Other"]),
+                    location("core", &["fmt", "Debug"]),
+                ),
+            ],
+            ..Default::default()
+        };
+        let lua = RtkLua::new(exec.clone()).unwrap();
+
+        lua.execute(
+            r#"
+            local traits = rtk.query_impls_for_type({ crate_name = "my_crate", path = { "User" } })
+            for _, t in ipairs(traits) do
+                rtk.emit(rtk.fmt_location(t) .. ";")
+            end
+        "#,
+        )
+        .unwrap();
+
+        assert_eq!(exec.emitted(), "serde::ser::Serialize;core::clone::Clone;");
+    }
+
+    #[test]
+    fn method_calls_report_their_chain_depth() {
+        // Router::new().route(..).route(..)
+        let method = |name: &str, parent: Option<MethodCallQuery>| MethodCallQuery {
+            parent: parent.map(Box::new),
+            receiver_type_filter: None,
+            location: Location {
+                crate_name: "axum".to_string(),
+                path: vec!["routing".to_string(), name.to_string()],
+                impl_block_number: Some(0),
+                is_local: false,
+            },
+        };
+        let first_route = method("route", Some(method("new", None)));
+        let second_route = method("route", Some(first_route.clone()));
+        let call = |origin: MethodCallQuery| MethodCall {
+            chain_depth: origin.chain_depth(),
+            origin,
+            args: vec![],
+            in_item_id: "0/0".to_string(),
+            is_in_test_module: false,
+        };
+
+        let exec = MockRtkLuaScriptExecutor {
+            method_calls: vec![call(first_route), call(second_route)],
+            ..Default::default()
+        };
+        let lua = RtkLua::new(exec.clone()).unwrap();
+
+        lua.execute(
+            r#"
+            local function route(parent)
+                return {
+                    parent = parent,
+                    location = {
+                        crate_name = "axum",
+                        path = { "routing", "route" },
+                        impl_block_number = 0,
+                    },
+                }
+            end
+            local new = {
+                location = { crate_name = "axum", path = { "routing", "new" }, impl_block_number = 0 },
+            }
+
+            for _, query in ipairs({ route(new), route(route(new)) }) do
+                for _, call in ipairs(rtk.query_method_calls(query)) do
+                    rtk.emit(call.chain_depth .. ";")
+                end
+            end
+        "#,
+        )
+        .unwrap();
+
+        assert_eq!(exec.emitted(), "1;2;");
+    }
+
+    #[test]
+    fn query_closures_with_capture_matches_the_variable_name() {
+        let main = Location {
+            crate_name: "my_crate".to_string(),
+            path: vec!["main".to_string()],
+            impl_block_number: None,
+            is_local: true,
+        };
+        let closure = |args: Vec<TypeValue>| ClosureTypeValue {
+            args,
+            return_type: None,
+        };
+
+        let exec = MockRtkLuaScriptExecutor {
+            closures: vec![
+                (main.clone(), "state".to_string(), closure(vec![])),
+                (
+                    main.clone(),
+                    "config".to_string(),
+                    closure(vec![TypeValue::U32]),
+                ),
+                (main, "state".to_string(), closure(vec![TypeValue::String])),
+            ],
+            ..Default::default()
+        };
+        let lua = RtkLua::new(exec.clone()).unwrap();
+
+        lua.execute(
+            r#"
+            local main = { crate_name = "my_crate", path = { "main" } }
+            for _, c in ipairs(rtk.query_closures_with_capture(main, "state")) do
+                rtk.emit(#c.args .. ";")
+            end
+        "#,
+        )
+        .unwrap();
+
+        assert_eq!(exec.emitted(), "0;1;");
+    }
+
+    #[test]
+    fn emit_snapshot() {
+        crate::assert_emit_snapshot!(
+            MockRtkLuaScriptExecutor::default(),
+            r#"
+            rtk.emit_with_priority(1, "export function getUser(): User;\n")
+            rtk.emit(rtk.render("export interface {{ name }} {\n  id: number;\n}\n", { name = "User" }))
+        "#
+        );
+    }
+
+    #[test]
+    fn fmt_location_matches_display() {
+        let location = Location {
+            crate_name: "axum".to_string(),
+            path: vec!["routing".to_string(), "route".to_string()],
+            impl_block_number: Some(0),
+            is_local: false,
+        };
+
+        let exec = MockRtkLuaScriptExecutor::default();
+        let lua = RtkLua::new(exec.clone()).unwrap();
+        lua.execute(
+            r#"
+            rtk.emit(rtk.fmt_location({
+                crate_name = "axum",
+                path = { "routing", "route" },
+                impl_block_number = 0,
+            }))
+        "#,
+        )
+        .unwrap();
+
+        assert_eq!(exec.emitted(), location.to_string());
+    }
+
+    #[test]
+    fn query_modules_lists_nested_modules() {
+        let module = |path: &[&str]| Location {
+            crate_name: "my_crate".to_string(),
+            path: path.iter().map(|s| s.to_string()).collect(),
+            impl_block_number: None,
+            is_local: false,
+        };
+
+        let exec = MockRtkLuaScriptExecutor {
+            modules: vec![
+                module(&[]),
+                module(&["routes"]),
+                module(&["routes", "users"]),
+            ],
+            ..Default::default()
+        };
+        let lua = RtkLua::new(exec.clone()).unwrap();
+
+        lua.execute(
+            r#"
+            for _, m in ipairs(rtk.query_modules()) do
+                rtk.emit(rtk.fmt_location(m) .. ";")
+            end
+        "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            exec.emitted(),
+            "my_crate;my_crate::routes;my_crate::routes::users;"
+        );
+    }
+
+    #[test]
+    fn query_functions_in_module_returns_each_function() {
+        let location = |path: &[&str]| Location {
+            crate_name: "my_crate".to_string(),
+            path: path.iter().map(|s| s.to_string()).collect(),
+            impl_block_number: None,
+            is_local: false,
+        };
+        let exec = MockRtkLuaScriptExecutor {
+            functions: vec![
+                function_type_value(location(&["routes", "get_user"])),
+                function_type_value(location(&["routes", "create_user"])),
+                function_type_value(location(&["db", "connect"])),
+            ],
+            ..Default::default()
+        };
+        let lua = RtkLua::new(exec.clone()).unwrap();
+
+        lua.execute(
+            r#"
+            local module = { crate_name = "my_crate", path = { "routes" } }
+            for _, f in ipairs(rtk.query_functions_in_module(module)) do
+                rtk.emit(rtk.fmt_location(f.location) .. ";")
+            end
+        "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            exec.emitted(),
+            "my_crate::routes::get_user;my_crate::routes::create_user;"
+        );
+    }
+
+    #[test]
+    fn doc_hidden_functions_are_only_queried_when_asked_for() {
+        let location = |name: &str| Location {
+            crate_name: "my_crate".to_string(),
+            path: vec!["routes".to_string(), name.to_string()],
+            impl_block_number: None,
+            is_local: true,
+        };
+        let function = |name: &str, attributes: Vec<Attribute>| FunctionTypeValue {
+            attributes,
+            ..function_type_value(location(name))
+        };
+
+        let exec = MockRtkLuaScriptExecutor {
+            functions: vec![
+                function("get_user", vec![]),
+                function(
+                    "__internal",
+                    vec![Attribute::new(
+                        "doc".to_string(),
+                        Some("hidden".to_string()),
+                    )],
+                ),
+            ],
+            ..Default::default()
+        };
+        let lua = RtkLua::new(exec.clone()).unwrap();
+
+        lua.execute(
+            r#"
+            local routes = { crate_name = "my_crate", path = { "routes" } }
+            local function emit_functions()
+                for _, f in ipairs(rtk.query_functions_in_module(routes)) do
+                    rtk.emit(f.location.path[2] .. ";")
+                end
+            end
+
+            emit_functions()
+            rtk.emit("|")
+            rtk.set_include_hidden(true)
+            emit_functions()
+        "#,
+        )
+        .unwrap();
+
+        assert_eq!(exec.emitted(), "get_user;|get_user;__internal;");
+    }
+
+    #[test]
+    fn functions_across_impl_blocks_are_grouped_as_overloads() {
+        let location = |impl_block_number| Location {
+            crate_name: "my_crate".to_string(),
+            path: vec!["new".to_string()],
+            impl_block_number,
+            is_local: true,
+        };
+        let function = |impl_block_number, arg_count: usize| FunctionTypeValue {
+            args_struct: StructTypeValue {
+                fields: (0..arg_count)
+                    .map(|i| struct_field(Either::Left(i), TypeValue::U32))
+                    .collect(),
+                ..struct_type_value(location(impl_block_number))
+            },
+            takes_no_args: arg_count == 0,
+            ..function_type_value(location(impl_block_number))
+        };
+
+        let exec = MockRtkLuaScriptExecutor {
+            functions: vec![function(Some(0), 0), function(Some(1), 2)],
+            ..Default::default()
+        };
+        let lua = RtkLua::new(exec.clone()).unwrap();
+
+        lua.execute(
+            r#"
+            local all = rtk.query_functions({ crate_name = "my_crate", path = { "new" } })
+            rtk.emit(#all .. ":" .. #all[1].overloads .. ":" .. #all[1].overloads[1].args_struct.fields .. "|")
+
+            local second = rtk.query_functions({
+                crate_name = "my_crate",
+                path = { "new" },
+                impl_block_number = 1,
+            })
+            rtk.emit(#second .. ":" .. #second[1].overloads)
+        "#,
+        )
+        .unwrap();
+
+        assert_eq!(exec.emitted(), "1:1:2|1:0");
+    }
+
+    #[test]
+    fn query_trait_impls_filters_by_generic_args() {
+        let from_trait = Location {
+            crate_name: "core".to_string(),
+            path: vec!["convert".to_string(), "From".to_string()],
+            impl_block_number: None,
+            is_local: false,
+        };
+        let from_impl = |source: TypeValue| TraitImpl {
+            trait_location: from_trait.clone(),
+            trait_generic_args: vec![source],
+            for_type: TypeValue::Bool,
+            for_type_attributes: vec![],
+            functions: vec![],
+        };
+
+        let user = TypeValue::Struct(struct_type_value(Location {
+            crate_name: "my_crate".to_string(),
+            path: vec!["User".to_string()],
+            impl_block_number: None,
+            is_local: true,
+        }));
+
+        let exec = MockRtkLuaScriptExecutor {
+            trait_impls: vec![
+                from_impl(TypeValue::U32),
+                from_impl(TypeValue::String),
+                from_impl(user),
+            ],
+            ..Default::default()
+        };
+        let lua = RtkLua::new(exec.clone()).unwrap();
+
+        lua.execute(
+            r#"
+            local from = { crate_name = "core", path = { "convert", "From" } }
+            local all = rtk.query_trait_impls(from)
+            rtk.emit(#all .. ";")
+
+            local from_string = rtk.query_trait_impls(from, { { variant_name = "String" } })
+            rtk.emit(#from_string .. ";" .. from_string[1].trait_generic_args[1].variant_name .. ";")
+
+            -- structs are picked out by location, whatever else the filter says about them
+            local user = all[3].trait_generic_args[1]
+            user.variant_data.doc_comment = "not the real doc comment"
+            local from_user = rtk.query_trait_impls(from, { user })
+            rtk.emit(#from_user .. ";" .. #rtk.query_trait_impls(from, { user, user }))
+        "#,
+        )
+        .unwrap();
+
+        assert_eq!(exec.emitted(), "3;1;String;1;0");
+    }
+
+    #[test]
+    fn query_trait_defs_lists_methods_and_associated_types() {
+        // trait Repository { type Error; fn find(&self, id: u64) -> Option<String>; }
+        let location = |path: &[&str]| Location {
+            crate_name: "my_crate".to_string(),
+            path: path.iter().map(|s| s.to_string()).collect(),
+            impl_block_number: None,
+            is_local: true,
+        };
+        let find = FunctionTypeValue {
+            args_struct: StructTypeValue {
+                fields: vec![struct_field(
+                    Either::Right("id".to_string()),
+                    TypeValue::U64,
+                )],
+                ..struct_type_value(location(&["Repository", "find"]))
+            },
+            return_type: Some(Box::new(TypeValue::Option(Box::new(TypeValue::String)))),
+            lifetime_count: 1,
+            takes_no_args: false,
+            ..function_type_value(location(&["Repository", "find"]))
+        };
+
+        let exec = MockRtkLuaScriptExecutor {
+            trait_defs: vec![TraitDef {
+                location: location(&["Repository"]),
+                doc_comment: None,
+                attributes: vec![],
+                functions: vec![find],
+                associated_types: vec!["Error".to_string()],
+                associated_consts: vec![],
+            }],
+            ..Default::default()
+        };
+        let lua = RtkLua::new(exec.clone()).unwrap();
+
+        lua.execute(
+            r#"
+            local repository = { crate_name = "my_crate", path = { "Repository" } }
+            for _, def in ipairs(rtk.query_trait_defs(repository)) do
+                for _, f in ipairs(def.functions) do
+                    rtk.emit("fn " .. f.location.path[2] .. ";")
+                end
+                for _, name in ipairs(def.associated_types) do
+                    rtk.emit("type " .. name .. ";")
+                end
+            end
+        "#,
+        )
+        .unwrap();
+
+        assert_eq!(exec.emitted(), "fn find;type Error;");
+    }
+
+    #[test]
+    fn query_all_trait_impls_returns_each_impl() {
+        let display = Location {
+            crate_name: "core".to_string(),
+            path: vec!["fmt".to_string(), "Display".to_string()],
+            impl_block_number: None,
+            is_local: false,
+        };
+        let display_impl = |for_type| TraitImpl {
+            trait_location: display.clone(),
+            trait_generic_args: vec![],
+            for_type,
+            for_type_attributes: vec![],
+            functions: vec![],
+        };
+
+        let exec = MockRtkLuaScriptExecutor {
+            trait_impls: vec![
+                display_impl(TypeValue::String),
+                display_impl(TypeValue::Bool),
+            ],
+            ..Default::default()
+        };
+        let lua = RtkLua::new(exec.clone()).unwrap();
+
+        lua.execute(
+            r#"
+            local display = { crate_name = "core", path = { "fmt", "Display" } }
+            for _, impl in ipairs(rtk.query_all_trait_impls(display)) do
+                rtk.emit(impl.for_type.variant_name .. ";")
+            end
+        "#,
+        )
+        .unwrap();
+
+        assert_eq!(exec.emitted(), "String;Bool;");
+    }
+
+    #[test]
+    fn crate_name_builds_locations_in_the_crate() {
+        let exec = MockRtkLuaScriptExecutor {
+            crate_name: Some("my_crate".to_string()),
+            ..Default::default()
+        };
+        let lua = RtkLua::new(exec.clone()).unwrap();
+
+        lua.execute(
+            r#"
+            local user = { crate_name = rtk.crate_name(), path = { "models", "User" } }
+            rtk.emit(rtk.fmt_location(user))
+        "#,
+        )
+        .unwrap();
+
+        assert_eq!(exec.emitted(), "my_crate::models::User");
+
+        let unset = MockRtkLuaScriptExecutor::default();
+        assert_eq!(unset.primary_crate_name(), "unknown");
+    }
+
+    #[test]
+    fn emit_unique_writes_repeated_text_once() {
+        let exec = MockRtkLuaScriptExecutor::default();
+        let lua = RtkLua::new(exec.clone()).unwrap();
+
+        lua.execute(
+            r#"
+            for _, handler in ipairs({ "get_user", "create_user" }) do
+                rtk.emit_unique("import { api } from './api';\n")
+                rtk.emit("export const " .. handler .. " = api;\n")
+            end
+        "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            exec.emitted(),
+            "import { api } from './api';\nexport const get_user = api;\nexport const create_user = api;\n"
+        );
+    }
+
+    #[test]
+    fn crate_name_and_version_fill_a_file_header() {
+        let exec = MockRtkLuaScriptExecutor {
+            crate_name: Some("my_crate".to_string()),
+            crate_version: Some("0.3.1".to_string()),
+            ..Default::default()
+        };
+        let lua = RtkLua::new(exec.clone()).unwrap();
+
+        lua.execute(
+            r#"
+            rtk.emit("// generated from " .. rtk.crate_name() .. " " .. (rtk.crate_version() or "?"))
+        "#,
+        )
+        .unwrap();
+
+        assert_eq!(exec.emitted(), "// generated from my_crate 0.3.1");
+
+        let lua = RtkLua::new(MockRtkLuaScriptExecutor::default()).unwrap();
+        lua.execute(r#"assert(rtk.crate_version() == nil)"#)
+            .unwrap();
+    }
+
+    #[test]
+    fn generate_typescript_interface_renders_struct_tables() {
+        let exec = MockRtkLuaScriptExecutor::default();
+        let lua = RtkLua::new(exec.clone()).unwrap();
+
+        lua.execute(
+            r#"
+            local point = {
+                location = { crate_name = "geo", path = { "Point" } },
+                fields = {
+                    {
+                        name = "x",
+                        attributes = {},
+                        value = { variant_name = "F64" },
+                        is_optional = false,
+                        has_serde_skip = false,
+                    },
+                    {
+                        name = "label",
+                        attributes = {},
+                        value = { variant_name = "Option", variant_data = { variant_name = "String" } },
+                        is_optional = true,
+                        has_serde_skip = false,
+                    },
+                },
+                attributes = {},
+                kind = { variant_name = "Named" },
+                derives = {},
+                is_non_exhaustive = false,
+            }
+            rtk.emit(rtk.generate_typescript_interface(point))
+        "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            exec.emitted(),
+            "export interface Point {\n    x: number;\n    label?: string;\n}\n"
+        );
+    }
+
+    #[test]
+    fn generate_typescript_type_takes_tag_overrides() {
+        let exec = MockRtkLuaScriptExecutor::default();
+        let lua = RtkLua::new(exec.clone()).unwrap();
+
+        lua.execute(
+            r#"
+            local status = {
+                location = { crate_name = "app", path = { "Status" } },
+                variants = {
+                    { name = "Active", attributes = {} },
+                    { name = "Banned", attributes = {} },
+                },
+                attributes = {},
+                is_c_like = true,
+                derives = {},
+                is_non_exhaustive = false,
+            }
+            rtk.emit(rtk.generate_typescript_type(status))
+            rtk.emit(rtk.generate_typescript_type(status, { tag_field = "state" }))
+        "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            exec.emitted(),
+            "export type Status =\n    | \"Active\"\n    | \"Banned\";\n\
+             export type Status =\n    | { state: \"Active\" }\n    | { state: \"Banned\" };\n"
+        );
+    }
+
+    #[test]
+    fn memory_limit_stops_runaway_scripts() {
+        let lua = RtkLua::new_with_memory_limit(MockRtkLuaScriptExecutor::default(), 1024 * 1024)
+            .unwrap();
+
+        let err = lua
+            .execute(r#"local s = string.rep("x", 16 * 1024 * 1024)"#)
+            .unwrap_err()
+            .to_string();
+
+        assert!(err.contains("ran out of memory"), "unexpected error: {err}");
+        assert!(
+            err.contains(MEMORY_LIMIT_ENV_VAR),
+            "unexpected error: {err}"
+        );
+    }
+
+    #[test]
+    fn memory_limit_env_var_is_parsed_as_megabytes() {
+        assert_eq!(parse_memory_limit_mb(None).unwrap(), None);
+        assert_eq!(
+            parse_memory_limit_mb(Some("2")).unwrap(),
+            Some(2 * 1024 * 1024)
+        );
+        assert!(parse_memory_limit_mb(Some("lots")).is_err());
+    }
+
+    #[test]
+    fn abort_if_errors_stops_before_emitting() {
+        let exec = MockRtkLuaScriptExecutor::default();
+        let lua = RtkLua::new(exec.clone()).unwrap();
+
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            lua.execute(
+                r#"
+                rtk.error("unsupported type")
+                rtk.abort_if_errors()
+                rtk.emit("partial output")
+            "#,
+            )
+        }));
+
+        assert!(result.is_err());
+        assert_eq!(exec.emitted(), "");
+    }
+
+    #[test]
+    fn set_empty_query_behavior_reaches_the_executor() {
+        let exec = MockRtkLuaScriptExecutor::default();
+        let lua = RtkLua::new(exec.clone()).unwrap();
+
+        assert_eq!(exec.query_empty_behavior(), QueryEmptyBehavior::Ignore);
+
+        lua.execute(r#"rtk.set_empty_query_behavior("warn")"#)
+            .unwrap();
+        assert_eq!(exec.query_empty_behavior(), QueryEmptyBehavior::WarnOnEmpty);
+
+        let err = lua
+            .execute(r#"rtk.set_empty_query_behavior("loud")"#)
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.contains("Invalid empty query behavior: loud"),
+            "unexpected error: {err}"
+        );
+    }
+
+    #[test]
+    fn trace_enable_and_disable_reach_the_executor() {
+        use std::sync::atomic::Ordering;
+
+        let exec = MockRtkLuaScriptExecutor::default();
+        let lua = RtkLua::new(exec.clone()).unwrap();
+
+        lua.execute("rtk.trace_enable()").unwrap();
+        assert!(exec.trace_enabled.load(Ordering::Relaxed));
+
+        lua.execute("rtk.trace_disable()").unwrap();
+        assert!(!exec.trace_enabled.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn empty_queries_only_error_when_asked() {
+        let exec = MockRtkLuaScriptExecutor::default();
+        let lua = RtkLua::new(exec.clone()).unwrap();
+        let typo = Location {
+            crate_name: "my_crate".to_string(),
+            path: vec!["rotues".to_string()],
+            impl_block_number: None,
+            is_local: false,
+        };
+
+        exec.report_empty_query("query_functions", &typo);
+        exec.abort_if_errors();
+
+        lua.execute(r#"rtk.set_empty_query_behavior("error")"#)
+            .unwrap();
+        exec.report_empty_query("query_functions", &typo);
+
+        let result =
+            std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| exec.abort_if_errors()));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn query_serde_fields_leaves_out_skipped_fields() {
+        // #[serde(rename_all = "camelCase")]
+        // struct User {
+        //     user_id: String,
+        //     #[serde(skip)]
+        //     password_hash: String,
+        //     #[serde(rename = "nick")]
+        //     nickname: String,
+        // }
+        let user = Location {
+            crate_name: "my_crate".to_string(),
+            path: vec!["User".to_string()],
+            impl_block_number: None,
+            is_local: true,
+        };
+        let field = |name: &str, attributes: Vec<Attribute>| StructTypeValueField {
+            has_serde_skip: StructTypeValueField::has_serde_skip_for(&attributes),
+            serde_rename: StructTypeValueField::serde_rename_for(&attributes),
+            attributes,
+            ..struct_field(Either::Right(name.to_string()), TypeValue::String)
+        };
+        let serde = |args: &str| vec![Attribute::new("serde".to_string(), Some(args.to_string()))];
+
+        let exec = MockRtkLuaScriptExecutor {
+            structs: vec![StructTypeValue {
+                fields: vec![
+                    field("user_id", vec![]),
+                    field("password_hash", serde("skip")),
+                    field("nickname", serde(r#"rename = "nick""#)),
+                ],
+                attributes: serde(r#"rename_all = "camelCase""#),
+                ..struct_type_value(user)
+            }],
+            ..Default::default()
+        };
+        let lua = RtkLua::new(exec.clone()).unwrap();
+
+        lua.execute(
+            r#"
+            local fields = rtk.query_serde_fields({ crate_name = "my_crate", path = { "User" } })
+            for _, f in ipairs(fields) do
+                rtk.emit(f.name .. "=" .. f.field.name .. ";")
+            end
+        "#,
+        )
+        .unwrap();
+
+        assert_eq!(exec.emitted(), "userId=user_id;nick=nickname;");
+    }
+
+    #[test]
+    fn query_constants_by_type_matches_structs_by_location() {
+        let location = |name: &str| Location {
+            crate_name: "my_crate".to_string(),
+            path: vec![name.to_string()],
+            impl_block_number: None,
+            is_local: true,
+        };
+        let error_code = StructTypeValue {
+            kind: StructKind::Tuple,
+            newtype_inner: Some(Box::new(TypeValue::U16)),
+            ..struct_type_value(location("ErrorCode"))
+        };
+        let constant = |name: &str, type_value: TypeValue| ConstantValue {
+            location: location(name),
+            type_value,
+            value: None,
+            doc_comment: None,
+            attributes: vec![],
+        };
+
+        let exec = MockRtkLuaScriptExecutor {
+            constants: vec![
+                constant("NOT_FOUND", TypeValue::Struct(error_code.clone())),
+                constant("MAX_RETRIES", TypeValue::U32),
+                constant("FORBIDDEN", TypeValue::Struct(error_code)),
+                constant("TIMEOUT_SECS", TypeValue::U64),
+            ],
+            ..Default::default()
+        };
+        let lua = RtkLua::new(exec.clone()).unwrap();
+
+        // the filter is declared differently from the constants' struct, only its location matches
+        lua.execute(
+            r#"
+            local error_code = {
+                variant_name = "Struct",
+                variant_data = {
+                    location = { crate_name = "my_crate", path = { "ErrorCode" } },
+                    fields = {},
+                    attributes = {},
+                    kind = { variant_name = "Named" },
+                    derives = {},
+                    is_non_exhaustive = false,
+                },
+            }
+            for _, c in ipairs(rtk.query_constants_by_type(error_code)) do
+                rtk.emit(c.location.path[1] .. ";")
+            end
+            for _, c in ipairs(rtk.query_constants_by_type({ variant_name = "U32" })) do
+                rtk.emit(c.location.path[1] .. ";")
+            end
+        "#,
+        )
+        .unwrap();
+
+        assert_eq!(exec.emitted(), "NOT_FOUND;FORBIDDEN;MAX_RETRIES;");
+    }
+
+    #[test]
+    fn abort_if_errors_without_errors_continues() {
+        let exec = MockRtkLuaScriptExecutor::default();
+        let lua = RtkLua::new(exec.clone()).unwrap();
+
+        lua.execute(
+            r#"
+            rtk.warn("just a warning")
+            rtk.abort_if_errors()
+            rtk.emit("output")
+        "#,
+        )
+        .unwrap();
+
+        assert_eq!(exec.emitted(), "output");
+    }
+
+    fn exec_with_type_attributes(attributes: Vec<Attribute>) -> MockRtkLuaScriptExecutor {
+        MockRtkLuaScriptExecutor {
+            trait_impls: vec![TraitImpl {
+                trait_location: Location {
+                    crate_name: "serde".to_string(),
+                    path: vec!["Serialize".to_string()],
+                    impl_block_number: None,
+                    is_local: false,
+                },
+                trait_generic_args: vec![],
+                for_type: TypeValue::Bool,
+                for_type_attributes: attributes,
+                functions: vec![],
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn find_attribute_returns_the_first_match() {
+        let exec = exec_with_type_attributes(vec![
+            Attribute::new("derive".to_string(), Some("Serialize".to_string())),
+            Attribute::new(
+                "serde".to_string(),
+                Some("rename_all=\"camelCase\"".to_string()),
+            ),
+            Attribute::new("serde".to_string(), Some("deny_unknown_fields".to_string())),
+        ]);
+        let lua = RtkLua::new(exec.clone()).unwrap();
+
+        lua.execute(
+            r#"
+            local serialize = { crate_name = "serde", path = { "Serialize" } }
+            local attributes = rtk.query_trait_impls(serialize)[1].for_type_attributes
+            rtk.emit(rtk.find_attribute(attributes, "serde").value_str)
+        "#,
+        )
+        .unwrap();
+
+        assert_eq!(exec.emitted(), "rename_all=\"camelCase\"");
+    }
+
+    #[test]
+    fn find_attribute_returns_nil_when_missing() {
+        let exec = exec_with_type_attributes(vec![Attribute::new(
+            "derive".to_string(),
+            Some("Clone".to_string()),
+        )]);
+        let lua = RtkLua::new(exec.clone()).unwrap();
+
+        lua.execute(
+            r#"
+            local serialize = { crate_name = "serde", path = { "Serialize" } }
+            local attributes = rtk.query_trait_impls(serialize)[1].for_type_attributes
+            assert(rtk.find_attribute(attributes, "serde") == nil)
+            assert(rtk.find_attribute({}, "serde") == nil)
+            rtk.emit(rtk.find_attribute(attributes, "derive").derived_traits[1])
+        "#,
+        )
+        .unwrap();
+
+        assert_eq!(exec.emitted(), "Clone");
+    }
+
+    #[test]
+    fn table_helpers_transform_arrays() {
+        let exec = MockRtkLuaScriptExecutor::default();
+        let lua = RtkLua::new(exec.clone()).unwrap();
+
+        lua.execute(
+            r#"
+            local names = { "id", "name", "created_at" }
+            local upper = rtk.map(names, function(name, i) return i .. ":" .. string.upper(name) end)
+            local short = rtk.filter(names, function(name) return #name <= 4 end)
+            local doubled = rtk.flat_map(names, function(name) return { name, name } end)
+
+            rtk.emit(rtk.join(", ", upper) .. "\n")
+            rtk.emit(rtk.join(", ", short) .. "\n")
+            rtk.emit(rtk.join(" ", doubled))
+        "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            exec.emitted(),
+            "1:ID, 2:NAME, 3:CREATED_AT\nid, name\nid id name name created_at created_at"
+        );
+    }
+
+    #[test]
+    fn join_rejects_non_string_elements() {
+        let lua = RtkLua::new(MockRtkLuaScriptExecutor::default()).unwrap();
+
+        assert!(lua.execute(r#"rtk.join(", ", { "a", {} })"#).is_err());
+    }
+
+    #[test]
+    fn query_function_calls_grouped_keys_groups_by_callee() {
+        let location = |path: &[&str]| Location {
+            crate_name: "my_crate".to_string(),
+            path: path.iter().map(|s| s.to_string()).collect(),
+            impl_block_number: None,
+            is_local: false,
+        };
+        let call = |path: &[&str], in_item_id: &str| FunctionCall {
+            location: location(path),
+            args: vec![],
+            in_item_id: in_item_id.to_string(),
+            is_in_test_module: false,
+        };
+
+        let exec = MockRtkLuaScriptExecutor {
+            module_function_calls: vec![
+                (location(&["handlers"]), call(&["db", "connect"], "a")),
+                (location(&["handlers"]), call(&["db", "close"], "b")),
+                (location(&["handlers"]), call(&["db", "connect"], "c")),
+                (location(&["routes"]), call(&["db", "connect"], "d")),
+            ],
+            ..Default::default()
+        };
+        let lua = RtkLua::new(exec.clone()).unwrap();
+
+        lua.execute(
+            r#"
+            local grouped = rtk.query_function_calls_grouped({ crate_name = "my_crate", path = { "handlers" } })
+            for _, callee in ipairs({ "my_crate::db::connect", "my_crate::db::close", "my_crate::routes::index" }) do
+                local sites = {}
+                for _, fc in ipairs(grouped[callee] or {}) do
+                    table.insert(sites, fc.in_item_id)
+                end
+                rtk.emit(callee .. "=" .. table.concat(sites, ",") .. ";")
+            end
+        "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            exec.emitted(),
+            "my_crate::db::connect=a,c;my_crate::db::close=b;my_crate::routes::index=;"
+        );
+    }
+
+    #[test]
+    fn query_function_calls_matches_associated_functions() {
+        // `Router::new()` sits in an impl block, so its calls carry the block's number
+        let router_new = Location {
+            crate_name: "axum".to_string(),
+            path: vec!["routing".to_string(), "new".to_string()],
+            impl_block_number: Some(2),
+            is_local: false,
+        };
+        let exec = MockRtkLuaScriptExecutor {
+            function_calls: vec![FunctionCall {
+                location: router_new,
+                args: vec![],
+                in_item_id: "main".to_string(),
+                is_in_test_module: false,
+            }],
+            ..Default::default()
+        };
+        let lua = RtkLua::new(exec.clone()).unwrap();
+
+        lua.execute(
+            r#"
+            local any_impl = rtk.query_function_calls({ crate_name = "axum", path = { "routing", "new" } })
+            local other_impl = rtk.query_function_calls({
+                crate_name = "axum",
+                path = { "routing", "new" },
+                impl_block_number = 1,
+            })
+
+            rtk.emit(#any_impl .. "," .. #other_impl .. ",")
+            rtk.emit(rtk.fmt_location(any_impl[1].location))
+        "#,
+        )
+        .unwrap();
+
+        assert_eq!(exec.emitted(), "1,0,axum::routing::new{impl#2}");
+    }
+
+    #[test]
+    fn query_function_calls_finds_calls_through_type_paths() {
+        let call = |crate_name: &str, path: &[&str], impl_block_number, args| FunctionCall {
+            location: Location {
+                crate_name: crate_name.to_string(),
+                path: path.iter().map(|s| s.to_string()).collect(),
+                impl_block_number,
+                is_local: crate_name == "my_crate",
+            },
+            args,
+            in_item_id: "main".to_string(),
+            is_in_test_module: false,
+        };
+
+        // `Vec::new()`, `String::from("x")` and `MyStruct::associated_fn(7)`, located the way the
+        // driver locates them
+        let exec = MockRtkLuaScriptExecutor {
+            function_calls: vec![
+                call("alloc", &["vec", "new"], Some(1), vec![]),
+                call(
+                    "core",
+                    &["convert", "From", "from"],
+                    None,
+                    vec![Value::StringLiteral("x".to_string())],
+                ),
+                call(
+                    "my_crate",
+                    &["models", "associated_fn"],
+                    Some(0),
+                    vec![Value::IntegerLiteral(7)],
+                ),
+            ],
+            ..Default::default()
+        };
+        let lua = RtkLua::new(exec.clone()).unwrap();
+
+        lua.execute(
+            r#"
+            local vec_new = rtk.query_function_calls({ crate_name = "alloc", path = { "vec", "new" } })
+            rtk.emit(#vec_new .. ";")
+
+            local from = rtk.query_function_calls({ crate_name = "core", path = { "convert", "From", "from" } })
+            local string_from = rtk.query_function_calls({ crate_name = "alloc", path = { "string", "from" } })
+            rtk.emit(#from .. "," .. #string_from .. "," .. from[1].args[1].variant_data .. ";")
+
+            local associated = rtk.query_function_calls({
+                crate_name = "my_crate",
+                path = { "models", "associated_fn" },
+            })
+            rtk.emit(rtk.fmt_location(associated[1].location) .. "(" .. associated[1].args[1].variant_data .. ")")
+        "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            exec.emitted(),
+            "1;1,0,x;my_crate::models::associated_fn{impl#0}(7)"
+        );
+    }
+
+    #[test]
+    fn query_macro_calls_returns_each_invocation() {
+        let call = |name: &str, text: &str| MacroCall {
+            name: name.to_string(),
+            text: text.to_string(),
+            args_text: MacroCall::args_text_of(text),
+            in_item_id: "router".to_string(),
+        };
+
+        let exec = MockRtkLuaScriptExecutor {
+            macro_calls: vec![
+                call("define_route", r#"define_route!("/users", get_users)"#),
+                call("vec", "vec![1, 2]"),
+                call("define_route", r#"define_route!("/posts", get_posts)"#),
+            ],
+            ..Default::default()
+        };
+        let lua = RtkLua::new(exec.clone()).unwrap();
+
+        lua.execute(
+            r#"
+            for _, mc in ipairs(rtk.query_macro_calls("define_route")) do
+                rtk.emit(mc.args_text .. ";")
+            end
+        "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            exec.emitted(),
+            r#""/users", get_users;"/posts", get_posts;"#
+        );
+    }
+
+    #[test]
+    fn query_struct_literals_returns_each_construction_site() {
+        let user = Location {
+            crate_name: "my_crate".to_string(),
+            path: vec!["User".to_string()],
+            impl_block_number: None,
+            is_local: true,
+        };
+        let site = |name: &str, in_item_id: &str| StructLiteralSite {
+            location: user.clone(),
+            fields: vec![
+                ("id".to_string(), Value::IntegerLiteral(1)),
+                ("name".to_string(), Value::StringLiteral(name.to_string())),
+            ],
+            in_item_id: in_item_id.to_string(),
+        };
+
+        let exec = MockRtkLuaScriptExecutor {
+            struct_literals: vec![site("jack", "create_user"), site("jill", "seed_users")],
+            ..Default::default()
+        };
+        let lua = RtkLua::new(exec.clone()).unwrap();
+
+        lua.execute(
+            r#"
+            for _, site in ipairs(rtk.query_struct_literals({ crate_name = "my_crate", path = { "User" } })) do
+                local name = site.fields[2]
+                rtk.emit(site.in_item_id .. ":" .. name.name .. "=" .. name.value.variant_data .. ";")
+            end
+        "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            exec.emitted(),
+            "create_user:name=jack;seed_users:name=jill;"
+        );
+    }
+
+    #[test]
+    fn is_primitive_and_unwrap_inner_from_lua() {
+        let exec = MockRtkLuaScriptExecutor::default();
+        let lua = RtkLua::new(exec.clone()).unwrap();
+
+        lua.execute(
+            r#"
+            local u32 = { variant_name = "U32" }
+            local tags = { variant_name = "Vec", variant_data = { variant_name = "String" } }
+            local maybe_id = { variant_name = "Option", variant_data = u32 }
+
+            rtk.emit(tostring(rtk.is_primitive(u32)) .. ",")
+            rtk.emit(tostring(rtk.is_primitive(tags)) .. ",")
+            rtk.emit(rtk.unwrap_inner(tags).variant_name .. ",")
+            rtk.emit(rtk.unwrap_inner(maybe_id).variant_name .. ",")
+            rtk.emit(rtk.unwrap_inner(u32).variant_name)
+        "#,
+        )
+        .unwrap();
+
+        assert_eq!(exec.emitted(), "true,false,String,U32,U32");
+    }
+
+    #[test]
+    fn function_calls_report_whether_they_are_in_test_modules() {
+        let connect = Location {
+            crate_name: "my_crate".to_string(),
+            path: vec!["db".to_string(), "connect".to_string()],
+            impl_block_number: None,
+            is_local: true,
+        };
+        let call = |in_item_id: &str, is_in_test_module: bool| FunctionCall {
+            location: connect.clone(),
+            args: vec![],
+            in_item_id: in_item_id.to_string(),
+            is_in_test_module,
+        };
+
+        let exec = MockRtkLuaScriptExecutor {
+            function_calls: vec![call("main", false), call("tests::connects", true)],
+            ..Default::default()
+        };
+        let lua = RtkLua::new(exec.clone()).unwrap();
+
+        lua.execute(
+            r#"
+            local calls = rtk.query_function_calls({ crate_name = "my_crate", path = { "db", "connect" } })
+            local production = rtk.filter(calls, function(fc) return not fc.is_in_test_module end)
+            for _, fc in ipairs(production) do
+                rtk.emit(fc.in_item_id)
+            end
+        "#,
+        )
+        .unwrap();
+
+        assert_eq!(exec.emitted(), "main");
+    }
 }
@@ -42,6 +42,53 @@ macro_rules! impl_into_lua {
     };
 }
 
+/// Implements `FromLua` for a struct from a Lua table keyed by field name, the inverse of
+/// `impl_into_lua!`. Fields needing a conversion must annotate the type read from the table.
+///
+/// ```rust,ignore
+/// impl_from_lua! {
+///     FunctionTypeValue {
+///         location,
+///         args_struct,
+///         // closure will apply to the value read from the table under the field name
+///         return_type: Option<TypeValue> => return_type.map(Box::new),
+///         item_id,
+///     }
+/// }
+/// ```
+#[macro_export]
+macro_rules! impl_from_lua {
+    (
+        $ty:ident {
+            $( $field:ident $(: $read_ty:ty)? $(=> $conv:expr)? ),* $(,)?
+        }
+    ) => {
+        impl ::mlua::FromLua for $ty {
+            fn from_lua(value: ::mlua::Value, _lua: &::mlua::Lua) -> ::mlua::Result<Self> {
+                let table = value
+                    .as_table()
+                    .ok_or_else(|| ::mlua::Error::FromLuaConversionError {
+                        from: value.type_name(),
+                        to: stringify!($ty).to_string(),
+                        message: Some("expected a table".to_string()),
+                    })?;
+
+                $(
+                    let $field $(: $read_ty)? = table.get(stringify!($field))?;
+                )*
+
+                Ok(Self {
+                    $( $field: impl_from_lua!(@convert $field $(=> $conv)?) ),*
+                })
+            }
+        }
+    };
+
+    (@convert $field:ident) => { $field };
+
+    (@convert $field:ident => $conv:expr) => { $conv };
+}
+
 /// Implements `IntoLua` for enums by mapping each variant to a Lua table formed like
 /// `{ variant_name, variant_data }`
 ///
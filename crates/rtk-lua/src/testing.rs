@@ -0,0 +1,373 @@
+//! Test helpers for exercising the Lua API without a rustc driver behind it. Outside of this
+//! crate these are available with the `testing` feature.
+
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+    },
+};
+
+#[doc(hidden)]
+pub use insta;
+
+use crate::{
+    ClosureTypeValue, ConstantValue, DEFAULT_EMIT_PRIORITY, Either, EmitBuffer, FunctionCall,
+    FunctionTypeValue, Location, MacroCall, MethodCall, MethodCallQuery, QueryEmptyBehavior,
+    RtkLuaScriptExecutor, RtkRustcDriverVersion, SerdeField, StructKind, StructLiteralSite,
+    StructTypeValue, StructTypeValueField, TraitDef, TraitImpl, TypeValue,
+};
+
+/// An executor with no crate behind it. Queries come back empty unless seeded, and the version and
+/// emitted text are recorded so tests can assert on them
+#[derive(Clone, Default)]
+pub struct MockRtkLuaScriptExecutor {
+    pub version: Arc<Mutex<Option<RtkRustcDriverVersion>>>,
+    /// Answered by `primary_crate_name`, which gives `unknown` when this is unset
+    pub crate_name: Option<String>,
+    /// Answered as-is by `primary_crate_version`
+    pub crate_version: Option<String>,
+    /// Method calls answered by `query_method_calls`, matched on their `origin`
+    pub method_calls: Vec<MethodCall>,
+    /// Trait impls answered by `query_trait_impls` and `query_all_trait_impls`, matched on their
+    /// trait location
+    pub trait_impls: Vec<TraitImpl>,
+    /// `(type, trait)` pairs answered by `query_impls_for_type`
+    pub impls_for_type: Vec<(Location, Location)>,
+    /// Traits answered by `query_trait_defs`, matched on their location
+    pub trait_defs: Vec<TraitDef>,
+    /// Functions answered by `query_functions` and `query_functions_in_module`, matched on their
+    /// location. Hidden ones are left out unless `include_hidden` is set, as the driver does.
+    /// Seeded functions have no type behind them, so `query_functions` folds every function
+    /// sharing a path into the `overloads` of the first
+    pub functions: Vec<FunctionTypeValue>,
+    /// Function calls answered by `query_function_calls`, matched on the location of the function
+    /// called the same way the driver matches them
+    pub function_calls: Vec<FunctionCall>,
+    /// `(module, call)` pairs answered by `query_function_calls_grouped`, matched on the module the
+    /// call is made from
+    pub module_function_calls: Vec<(Location, FunctionCall)>,
+    /// Struct literals answered by `query_struct_literals`, matched on the struct's location
+    pub struct_literals: Vec<StructLiteralSite>,
+    /// Macro calls answered by `query_macro_calls`, matched on their name
+    pub macro_calls: Vec<MacroCall>,
+    /// Structs whose fields answer `query_serde_fields`, matched on their location
+    pub structs: Vec<StructTypeValue>,
+    /// Constants answered by `query_constants_by_type`, matched on their type
+    pub constants: Vec<ConstantValue>,
+    /// `(function, captured variable, closure)` triples answered by `query_closures_with_capture`
+    pub closures: Vec<(Location, String, ClosureTypeValue)>,
+    /// Answered as-is by `query_modules`
+    pub modules: Vec<Location>,
+    /// The behavior set with `rtk.set_empty_query_behavior`. Seeded queries are never reported as
+    /// empty, but `report_empty_query` acts on it
+    pub query_empty_behavior: Arc<Mutex<QueryEmptyBehavior>>,
+    /// Whether the script last asked for queries to be traced. Nothing is traced either way
+    pub trace_enabled: Arc<AtomicBool>,
+    /// Whether the script last asked for hidden items to be included in queries
+    pub include_hidden: Arc<AtomicBool>,
+    error_count: Arc<AtomicUsize>,
+    emit_buffer: Arc<Mutex<EmitBuffer>>,
+}
+
+impl MockRtkLuaScriptExecutor {
+    /// Everything emitted so far, in the order it would be written to the output file
+    pub fn emitted(&self) -> String {
+        self.emit_buffer.lock().unwrap().drain_ordered()
+    }
+
+    /// The seeded functions a query may answer with, given whether hidden ones are included
+    fn visible_functions(&self) -> impl Iterator<Item = &FunctionTypeValue> {
+        let include_hidden = self.include_hidden.load(Ordering::Relaxed);
+        self.functions
+            .iter()
+            .filter(move |f| include_hidden || !f.is_hidden())
+    }
+}
+
+impl RtkLuaScriptExecutor for MockRtkLuaScriptExecutor {
+    fn intake_version(&self, version: RtkRustcDriverVersion) {
+        self.version.lock().unwrap().replace(version);
+    }
+
+    fn primary_crate_name(&self) -> String {
+        self.crate_name
+            .clone()
+            .unwrap_or_else(|| "unknown".to_string())
+    }
+
+    fn primary_crate_version(&self) -> Option<String> {
+        self.crate_version.clone()
+    }
+
+    fn query_method_calls(&self, query: MethodCallQuery) -> Vec<MethodCall> {
+        self.method_calls
+            .iter()
+            .filter(|mc| mc.origin == query)
+            .cloned()
+            .collect()
+    }
+
+    fn query_method_calls_batch(&self, queries: Vec<MethodCallQuery>) -> Vec<Vec<MethodCall>> {
+        queries
+            .into_iter()
+            .map(|query| self.query_method_calls(query))
+            .collect()
+    }
+
+    fn query_trait_impls(&self, query: Location) -> Vec<TraitImpl> {
+        self.trait_impls
+            .iter()
+            .filter(|ti| ti.trait_location.same_item(&query))
+            .cloned()
+            .collect()
+    }
+
+    fn query_all_trait_impls(&self, query: Location) -> Vec<TraitImpl> {
+        self.query_trait_impls(query)
+    }
+
+    fn query_impls_for_type(&self, query: Location) -> Vec<Location> {
+        self.impls_for_type
+            .iter()
+            .filter(|(for_type, _)| for_type.same_item(&query))
+            .map(|(_, trait_location)| trait_location.clone())
+            .collect()
+    }
+
+    fn query_trait_defs(&self, query: Location) -> Vec<TraitDef> {
+        self.trait_defs
+            .iter()
+            .filter(|td| td.location.same_item(&query))
+            .cloned()
+            .collect()
+    }
+
+    fn query_functions(&self, query: Location) -> Vec<FunctionTypeValue> {
+        let functions = self
+            .visible_functions()
+            .filter(|f| f.location.matches_query(&query))
+            .map(|f| ((), f.clone()));
+
+        FunctionTypeValue::group_overloads(functions)
+    }
+
+    fn query_functions_in_module(&self, module: Location) -> Vec<FunctionTypeValue> {
+        self.visible_functions()
+            .filter(|f| f.location.is_within(&module))
+            .cloned()
+            .collect()
+    }
+
+    fn query_function_calls(&self, query: Location) -> Vec<FunctionCall> {
+        self.function_calls
+            .iter()
+            .filter(|fc| fc.location.matches_query(&query))
+            .cloned()
+            .collect()
+    }
+
+    fn query_struct_literals(&self, query: Location) -> Vec<StructLiteralSite> {
+        self.struct_literals
+            .iter()
+            .filter(|sl| sl.location.same_item(&query))
+            .cloned()
+            .collect()
+    }
+
+    fn query_macro_calls(&self, name: String) -> Vec<MacroCall> {
+        self.macro_calls
+            .iter()
+            .filter(|mc| mc.name == name)
+            .cloned()
+            .collect()
+    }
+
+    fn query_serde_fields(&self, query: Location) -> Vec<SerdeField> {
+        self.structs
+            .iter()
+            .find(|s| s.location.same_item(&query))
+            .map(SerdeField::of_struct)
+            .unwrap_or_default()
+    }
+
+    fn query_constants_by_type(&self, type_filter: TypeValue) -> Vec<ConstantValue> {
+        self.constants
+            .iter()
+            .filter(|c| c.type_value.matches_type_filter(&type_filter))
+            .cloned()
+            .collect()
+    }
+
+    fn query_function_calls_in_function(
+        &self,
+        _outer_location: Location,
+        _call_location: Location,
+    ) -> Vec<FunctionCall> {
+        vec![]
+    }
+
+    fn query_closures_with_capture(
+        &self,
+        within_location: Location,
+        capture_name: String,
+    ) -> Vec<ClosureTypeValue> {
+        self.closures
+            .iter()
+            .filter(|(within, captured, _)| {
+                within.same_item(&within_location) && *captured == capture_name
+            })
+            .map(|(_, _, closure)| closure.clone())
+            .collect()
+    }
+
+    fn query_function_calls_grouped(
+        &self,
+        module: Location,
+    ) -> HashMap<Location, Vec<FunctionCall>> {
+        let mut grouped: HashMap<Location, Vec<FunctionCall>> = HashMap::new();
+        for (_, fc) in self
+            .module_function_calls
+            .iter()
+            .filter(|(calling_module, _)| *calling_module == module)
+        {
+            grouped
+                .entry(fc.location.clone())
+                .or_default()
+                .push(fc.clone());
+        }
+
+        grouped
+    }
+
+    fn query_modules(&self) -> Vec<Location> {
+        self.modules.clone()
+    }
+
+    fn log_note(&self, _msg: String) {}
+
+    fn log_warn(&self, _msg: String) {}
+
+    fn log_error(&self, _msg: String) {
+        self.error_count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn log_fatal_error(&self, msg: String) -> ! {
+        panic!("fatal error hit in mock script execution: {msg}")
+    }
+
+    fn abort_if_errors(&self) {
+        let error_count = self.error_count.load(Ordering::Relaxed);
+        if error_count > 0 {
+            self.log_fatal_error(format!("aborting due to {error_count} previous error(s)"));
+        }
+    }
+
+    fn query_empty_behavior(&self) -> QueryEmptyBehavior {
+        *self.query_empty_behavior.lock().unwrap()
+    }
+
+    fn intake_query_empty_behavior(&self, behavior: QueryEmptyBehavior) {
+        *self.query_empty_behavior.lock().unwrap() = behavior;
+    }
+
+    fn intake_trace_enabled(&self, enabled: bool) {
+        self.trace_enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    fn intake_include_hidden(&self, include_hidden: bool) {
+        self.include_hidden.store(include_hidden, Ordering::Relaxed);
+    }
+
+    fn emit(&self, text: String) {
+        self.emit_with_priority(DEFAULT_EMIT_PRIORITY, text);
+    }
+
+    fn emit_with_priority(&self, priority: i64, text: String) {
+        self.emit_buffer.lock().unwrap().push(priority, text);
+    }
+
+    fn emit_unique(&self, text: String) {
+        self.emit_buffer
+            .lock()
+            .unwrap()
+            .push_unique(DEFAULT_EMIT_PRIORITY, text);
+    }
+}
+
+/// A named struct at `location` with nothing else set, for seeding queries. Tests fill in what they
+/// care about with struct update syntax, e.g. `StructTypeValue { fields, ..struct_type_value(l) }`
+pub fn struct_type_value(location: Location) -> StructTypeValue {
+    StructTypeValue {
+        location,
+        fields: vec![],
+        doc_comment: None,
+        attributes: vec![],
+        kind: StructKind::Named,
+        derives: vec![],
+        is_non_exhaustive: false,
+        newtype_inner: None,
+        has_custom_serialize: false,
+        has_custom_deserialize: false,
+        type_hash: 0,
+        size: None,
+        align: None,
+    }
+}
+
+/// A field called `name` holding `value`, with no attributes or doc comment
+pub fn struct_field(name: Either<usize, String>, value: TypeValue) -> StructTypeValueField {
+    StructTypeValueField {
+        name,
+        doc_comment: None,
+        attributes: vec![],
+        value,
+        is_optional: false,
+        has_serde_skip: false,
+        serde_rename: None,
+    }
+}
+
+/// A `pub fn` at `location` taking no args and returning nothing, overridden the same way as
+/// [`struct_type_value`]
+pub fn function_type_value(location: Location) -> FunctionTypeValue {
+    FunctionTypeValue {
+        args_struct: struct_type_value(location.clone()),
+        location,
+        return_type: None,
+        item_id: String::new(),
+        attributes: vec![],
+        doc_comment: None,
+        is_async: false,
+        where_clause_text: vec![],
+        is_test: false,
+        is_cfg_test: false,
+        lifetime_count: 0,
+        is_public: true,
+        visibility: "pub".to_string(),
+        overrides_trait_method: None,
+        overloads: vec![],
+        takes_no_args: true,
+    }
+}
+
+/// Runs `script` against a [`MockRtkLuaScriptExecutor`] and snapshots everything it emitted with
+/// `insta`, so full script outputs can be reviewed and updated with `cargo insta review`.
+///
+/// ```rust,ignore
+/// #[test]
+/// fn emits_greeting() {
+///     rtk_lua::assert_emit_snapshot!(MockRtkLuaScriptExecutor::default(), r#"rtk.emit("hi")"#);
+/// }
+/// ```
+#[macro_export]
+macro_rules! assert_emit_snapshot {
+    ($exec:expr, $script:expr $(,)?) => {{
+        let exec: $crate::testing::MockRtkLuaScriptExecutor = $exec;
+        let lua = $crate::RtkLua::new(exec.clone()).expect("failed to create the Lua instance");
+        lua.execute($script).expect("failed to execute the script");
+
+        $crate::testing::insta::assert_snapshot!(exec.emitted());
+    }};
+}
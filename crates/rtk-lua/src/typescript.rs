@@ -0,0 +1,570 @@
+//! Built-in TypeScript generation for the most common use of RTK, turning the types serde
+//! serializes into matching TypeScript declarations.
+
+use crate::{
+    Either, EnumTypeValue, StructKind, StructTypeValue, StructTypeValueField, TypeValue,
+    impl_from_lua,
+};
+
+/// What each level of a generated declaration is indented with, the same as `rtk.indent`
+const TS_INDENT: &str = "    ";
+
+/// The TypeScript type a value of `tv` serializes to with serde's default representation. Structs,
+/// enums and recursive references are named by the last segment of their path, so their own
+/// declarations are expected to be generated alongside
+pub(crate) fn type_value_to_ts(tv: &TypeValue) -> String {
+    match tv {
+        TypeValue::String => "string".to_string(),
+        TypeValue::Bool => "boolean".to_string(),
+        TypeValue::NonZero(_) => "number".to_string(),
+        tv if tv.is_primitive() => "number".to_string(),
+        TypeValue::Map { key, value, .. } => format!(
+            "Record<{}, {}>",
+            type_value_to_ts(key),
+            type_value_to_ts(value)
+        ),
+        TypeValue::Vec(inner) => format!("{}[]", parenthesize_union(type_value_to_ts(inner))),
+        TypeValue::Result(ok, err) => format!(
+            "{{ Ok: {} }} | {{ Err: {} }}",
+            type_value_to_ts(ok),
+            type_value_to_ts(err)
+        ),
+        TypeValue::Option(inner) => format!("{} | null", type_value_to_ts(inner)),
+        // a newtype serializes as the value it wraps
+        TypeValue::Struct(s) => match &s.newtype_inner {
+            Some(inner) => type_value_to_ts(inner),
+            None => ts_type_name(&s.location.path),
+        },
+        TypeValue::Enum(e) => ts_type_name(&e.location.path),
+        TypeValue::RecursiveRef(location) => ts_type_name(&location.path),
+        // `()` serializes as `null`
+        TypeValue::Tuple(elements) if elements.is_empty() => "null".to_string(),
+        TypeValue::Tuple(elements) => format!(
+            "[{}]",
+            elements
+                .iter()
+                .map(type_value_to_ts)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        TypeValue::Generic(name) => name.clone(),
+        _ => "unknown".to_string(),
+    }
+}
+
+/// Renders `s` as an exported TypeScript `interface`. Fields serde skips are left out and renamed
+/// fields use their serde name
+pub(crate) fn struct_to_ts_interface(s: &StructTypeValue) -> String {
+    let mut out = String::new();
+
+    if let Some(doc) = &s.doc_comment {
+        out.push_str(&jsdoc(doc, ""));
+    }
+
+    out.push_str(&format!(
+        "export interface {} {{\n",
+        ts_type_name(&s.location.path)
+    ));
+
+    for field in s.fields.iter().filter(|f| !f.has_serde_skip) {
+        if let Some(doc) = &field.doc_comment {
+            out.push_str(&jsdoc(doc, TS_INDENT));
+        }
+
+        out.push_str(&format!("{TS_INDENT}{};\n", ts_member(field)));
+    }
+
+    out.push_str("}\n");
+
+    out
+}
+
+/// Overrides for the fields `rtk.generate_typescript_type` tags enum variants with, in place of
+/// what the enum's serde attributes say
+#[derive(Clone, Debug, Default)]
+pub(crate) struct TsEnumOptions {
+    pub tag_field: Option<String>,
+    pub content_field: Option<String>,
+}
+
+impl_from_lua! {
+    TsEnumOptions {
+        tag_field,
+        content_field,
+    }
+}
+
+/// Renders `e` as an exported TypeScript union of its variants, following serde's enum
+/// representations. With a tag field each variant is a discriminated object, with its data either
+/// under the content field or, without one, merged in alongside the tag. Without a tag field
+/// variants are externally tagged, or just their data for `#[serde(untagged)]`
+pub(crate) fn enum_to_ts_type(e: &EnumTypeValue, options: &TsEnumOptions) -> String {
+    let tag = options.tag_field.clone().or_else(|| e.serde_tag.clone());
+    let content = options.content_field.clone().or_else(|| {
+        e.attributes
+            .iter()
+            .find_map(|attr| attr.serde_value("content"))
+    });
+    let is_untagged = e
+        .attributes
+        .iter()
+        .any(|attr| attr.serde_keys().any(|key| key == "untagged"));
+
+    let variants = e
+        .variants
+        .iter()
+        .filter(|variant| {
+            !variant.attributes.iter().any(|attr| {
+                attr.serde_keys()
+                    .any(|key| key == "skip" || key == "skip_serializing")
+            })
+        })
+        .map(|variant| {
+            let name = variant.serde_rename.as_deref().unwrap_or(&variant.name);
+            let payload = variant.value.as_ref().and_then(variant_payload);
+
+            match (&tag, &content, payload) {
+                (None, _, payload) if is_untagged => payload
+                    .map(VariantPayload::into_ts)
+                    .unwrap_or_else(|| "null".to_string()),
+                (None, _, None) => format!("{name:?}"),
+                (None, _, Some(payload)) => {
+                    format!("{{ {}: {} }}", ts_property_name(name), payload.into_ts())
+                }
+                (Some(tag), _, None) => format!("{{ {}: {name:?} }}", ts_property_name(tag)),
+                (Some(tag), Some(content), Some(payload)) => format!(
+                    "{{ {}: {name:?}; {}: {} }}",
+                    ts_property_name(tag),
+                    ts_property_name(content),
+                    payload.into_ts()
+                ),
+                (Some(tag), None, Some(VariantPayload::Members(mut members))) => {
+                    members.insert(0, format!("{}: {name:?}", ts_property_name(tag)));
+                    format!("{{ {} }}", members.join("; "))
+                }
+                // serde merges the tag into whatever the newtype holds
+                (Some(tag), None, Some(VariantPayload::Type(ts))) => {
+                    format!("{{ {}: {name:?} }} & {ts}", ts_property_name(tag))
+                }
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let mut out = String::new();
+
+    if let Some(doc) = &e.doc_comment {
+        out.push_str(&jsdoc(doc, ""));
+    }
+
+    let name = ts_type_name(&e.location.path);
+    if variants.is_empty() {
+        out.push_str(&format!("export type {name} = never;\n"));
+    } else {
+        let variants = variants
+            .iter()
+            .map(|variant| format!("{TS_INDENT}| {variant}"))
+            .collect::<Vec<_>>();
+        out.push_str(&format!("export type {name} =\n{};\n", variants.join("\n")));
+    }
+
+    out
+}
+
+/// The data a data-carrying enum variant serializes with
+enum VariantPayload {
+    /// The members of a struct variant, which an internally tagged enum merges in with its tag
+    Members(Vec<String>),
+    Type(String),
+}
+
+impl VariantPayload {
+    fn into_ts(self) -> String {
+        match self {
+            VariantPayload::Members(members) => format!("{{ {} }}", members.join("; ")),
+            VariantPayload::Type(ts) => ts,
+        }
+    }
+}
+
+fn variant_payload(value: &TypeValue) -> Option<VariantPayload> {
+    let TypeValue::Struct(s) = value else {
+        return Some(VariantPayload::Type(type_value_to_ts(value)));
+    };
+
+    match (s.kind, s.fields.as_slice()) {
+        (StructKind::Unit, _) | (StructKind::Tuple, []) => None,
+        (StructKind::Named, fields) => Some(VariantPayload::Members(
+            fields
+                .iter()
+                .filter(|f| !f.has_serde_skip)
+                .map(ts_member)
+                .collect(),
+        )),
+        (StructKind::Tuple, [field]) => Some(VariantPayload::Type(type_value_to_ts(&field.value))),
+        (StructKind::Tuple, fields) => Some(VariantPayload::Type(format!(
+            "[{}]",
+            fields
+                .iter()
+                .map(|f| type_value_to_ts(&f.value))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ))),
+    }
+}
+
+/// A field as an object type member, e.g. `nickname?: string`. Fields that can be left out are
+/// marked optional with `?` rather than being typed as `T | null`
+fn ts_member(field: &StructTypeValueField) -> String {
+    let name = match (&field.serde_rename, &field.name) {
+        (Some(rename), _) => rename.clone(),
+        (None, Either::Right(name)) => name.clone(),
+        (None, Either::Left(index)) => index.to_string(),
+    };
+
+    let (optional, value) = match (&field.value, field.is_optional) {
+        (TypeValue::Option(inner), _) => ("?", inner.as_ref()),
+        (value, true) => ("?", value),
+        (value, false) => ("", value),
+    };
+
+    format!(
+        "{}{optional}: {}",
+        ts_property_name(&name),
+        type_value_to_ts(value)
+    )
+}
+
+fn ts_type_name(path: &[String]) -> String {
+    path.last().cloned().unwrap_or_default()
+}
+
+/// Quotes `name` unless it is already a valid identifier, as serde renames like `user-id` are not
+fn ts_property_name(name: &str) -> String {
+    let is_identifier = name
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == '$')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$');
+
+    if is_identifier {
+        name.to_string()
+    } else {
+        format!("{name:?}")
+    }
+}
+
+/// Wraps a union type in parentheses so a suffix like `[]` applies to the whole of it
+fn parenthesize_union(ts: String) -> String {
+    if ts.contains(" | ") {
+        format!("({ts})")
+    } else {
+        ts
+    }
+}
+
+/// Renders a doc comment as a JSDoc block at the given indentation. Rust doc comments keep the
+/// space after `///`, so each line is trimmed
+fn jsdoc(doc: &str, indent: &str) -> String {
+    let lines = doc.lines().map(str::trim).collect::<Vec<_>>();
+
+    match lines.as_slice() {
+        [line] => format!("{indent}/** {line} */\n"),
+        lines => {
+            let mut out = format!("{indent}/**\n");
+            for line in lines {
+                if line.is_empty() {
+                    out.push_str(&format!("{indent} *\n"));
+                } else {
+                    out.push_str(&format!("{indent} * {line}\n"));
+                }
+            }
+            out.push_str(&format!("{indent} */\n"));
+
+            out
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Attribute, EnumTypeValueVariant, Location};
+
+    fn location(path: &str) -> Location {
+        Location {
+            crate_name: "my_crate".to_string(),
+            path: vec![path.to_string()],
+            impl_block_number: None,
+            is_local: true,
+        }
+    }
+
+    fn field(name: &str, value: TypeValue) -> StructTypeValueField {
+        StructTypeValueField {
+            name: Either::Right(name.to_string()),
+            doc_comment: None,
+            attributes: vec![],
+            is_optional: matches!(value, TypeValue::Option(_)),
+            value,
+            has_serde_skip: false,
+            serde_rename: None,
+        }
+    }
+
+    fn user() -> StructTypeValue {
+        StructTypeValue {
+            location: location("User"),
+            fields: vec![
+                StructTypeValueField {
+                    doc_comment: Some(" The user's id".to_string()),
+                    ..field("id", TypeValue::U64)
+                },
+                StructTypeValueField {
+                    serde_rename: Some("displayName".to_string()),
+                    ..field("display_name", TypeValue::String)
+                },
+                field("nickname", TypeValue::Option(Box::new(TypeValue::String))),
+                StructTypeValueField {
+                    has_serde_skip: true,
+                    attributes: vec![Attribute::new(
+                        "serde".to_string(),
+                        Some("skip".to_string()),
+                    )],
+                    ..field("password_hash", TypeValue::String)
+                },
+                field(
+                    "roles",
+                    TypeValue::Vec(Box::new(TypeValue::Struct(StructTypeValue {
+                        location: location("Role"),
+                        fields: vec![],
+                        doc_comment: None,
+                        attributes: vec![],
+                        kind: StructKind::Named,
+                        derives: vec![],
+                        is_non_exhaustive: false,
+                        newtype_inner: None,
+                        has_custom_serialize: false,
+                        has_custom_deserialize: false,
+                        type_hash: 0,
+                        size: None,
+                        align: None,
+                    }))),
+                ),
+            ],
+            doc_comment: Some(" A registered user.\n\n Created on sign up.".to_string()),
+            attributes: vec![],
+            kind: StructKind::Named,
+            derives: vec![],
+            is_non_exhaustive: false,
+            newtype_inner: None,
+            has_custom_serialize: false,
+            has_custom_deserialize: false,
+            type_hash: 0,
+            size: None,
+            align: None,
+        }
+    }
+
+    #[test]
+    fn struct_renders_as_interface() {
+        assert_eq!(
+            struct_to_ts_interface(&user()),
+            "/**\n\
+             \x20* A registered user.\n\
+             \x20*\n\
+             \x20* Created on sign up.\n\
+             \x20*/\n\
+             export interface User {\n\
+             \x20   /** The user's id */\n\
+             \x20   id: number;\n\
+             \x20   displayName: string;\n\
+             \x20   nickname?: string;\n\
+             \x20   roles: Role[];\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn type_values_render_as_ts_types() {
+        let cases = [
+            (
+                TypeValue::Map {
+                    key: Box::new(TypeValue::String),
+                    value: Box::new(TypeValue::Bool),
+                    is_ordered: false,
+                },
+                "Record<string, boolean>",
+            ),
+            (
+                TypeValue::Vec(Box::new(TypeValue::Option(Box::new(TypeValue::U8)))),
+                "(number | null)[]",
+            ),
+            (
+                TypeValue::Tuple(vec![TypeValue::String, TypeValue::I32]),
+                "[string, number]",
+            ),
+            (TypeValue::Tuple(vec![]), "null"),
+            (
+                TypeValue::Result(Box::new(TypeValue::String), Box::new(TypeValue::U32)),
+                "{ Ok: string } | { Err: number }",
+            ),
+            (TypeValue::RecursiveRef(location("Node")), "Node"),
+        ];
+
+        for (tv, expected) in cases {
+            assert_eq!(type_value_to_ts(&tv), expected);
+        }
+    }
+
+    #[test]
+    fn newtypes_render_as_their_inner_type() {
+        let user_id = TypeValue::Struct(StructTypeValue {
+            location: location("UserId"),
+            fields: vec![],
+            doc_comment: None,
+            attributes: vec![],
+            kind: StructKind::Tuple,
+            derives: vec![],
+            is_non_exhaustive: false,
+            newtype_inner: Some(Box::new(TypeValue::U64)),
+            has_custom_serialize: false,
+            has_custom_deserialize: false,
+            type_hash: 0,
+            size: None,
+            align: None,
+        });
+
+        assert_eq!(type_value_to_ts(&user_id), "number");
+    }
+
+    #[test]
+    fn renamed_fields_that_are_not_identifiers_are_quoted() {
+        assert_eq!(ts_property_name("user_id"), "user_id");
+        assert_eq!(ts_property_name("user-id"), "\"user-id\"");
+        assert_eq!(ts_property_name("0"), "\"0\"");
+    }
+
+    fn shape(attributes: Vec<Attribute>) -> EnumTypeValue {
+        // enum Shape { Circle { radius: f64 }, Square(f64), Empty }
+        let variant = |name: &str, value: Option<TypeValue>| EnumTypeValueVariant {
+            name: name.to_string(),
+            value,
+            doc_comment: None,
+            attributes: vec![],
+            serde_rename: None,
+        };
+        let payload = |kind: StructKind, fields: Vec<StructTypeValueField>| {
+            Some(TypeValue::Struct(StructTypeValue {
+                location: location("Shape"),
+                newtype_inner: StructTypeValue::newtype_inner_of(kind, &fields),
+                has_custom_serialize: false,
+                has_custom_deserialize: false,
+                type_hash: 0,
+                size: None,
+                align: None,
+                fields,
+                doc_comment: None,
+                attributes: vec![],
+                kind,
+                derives: vec![],
+                is_non_exhaustive: false,
+            }))
+        };
+
+        EnumTypeValue {
+            location: location("Shape"),
+            variants: vec![
+                variant(
+                    "Circle",
+                    payload(StructKind::Named, vec![field("radius", TypeValue::F64)]),
+                ),
+                variant(
+                    "Square",
+                    payload(
+                        StructKind::Tuple,
+                        vec![StructTypeValueField {
+                            name: Either::Left(0),
+                            ..field("", TypeValue::F64)
+                        }],
+                    ),
+                ),
+                variant("Empty", payload(StructKind::Unit, vec![])),
+            ],
+            doc_comment: None,
+            serde_tag: EnumTypeValue::serde_tag_for(&attributes),
+            attributes,
+            is_c_like: false,
+            type_hash: 0,
+            derives: vec![],
+            is_non_exhaustive: false,
+        }
+    }
+
+    fn serde(args: &str) -> Attribute {
+        Attribute::new("serde".to_string(), Some(args.to_string()))
+    }
+
+    #[test]
+    fn externally_tagged_enum_renders_as_union() {
+        assert_eq!(
+            enum_to_ts_type(&shape(vec![]), &TsEnumOptions::default()),
+            "export type Shape =\n\
+             \x20   | { Circle: { radius: number } }\n\
+             \x20   | { Square: number }\n\
+             \x20   | \"Empty\";\n"
+        );
+    }
+
+    #[test]
+    fn internally_tagged_enum_merges_fields_with_the_tag() {
+        assert_eq!(
+            enum_to_ts_type(
+                &shape(vec![serde(r#"tag = "type""#)]),
+                &TsEnumOptions::default()
+            ),
+            "export type Shape =\n\
+             \x20   | { type: \"Circle\"; radius: number }\n\
+             \x20   | { type: \"Square\" } & number\n\
+             \x20   | { type: \"Empty\" };\n"
+        );
+    }
+
+    #[test]
+    fn adjacently_tagged_enum_puts_data_under_content() {
+        let expected = "export type Shape =\n\
+                        \x20   | { kind: \"Circle\"; data: { radius: number } }\n\
+                        \x20   | { kind: \"Square\"; data: number }\n\
+                        \x20   | { kind: \"Empty\" };\n";
+
+        assert_eq!(
+            enum_to_ts_type(
+                &shape(vec![serde(r#"tag = "kind", content = "data""#)]),
+                &TsEnumOptions::default()
+            ),
+            expected
+        );
+        assert_eq!(
+            enum_to_ts_type(
+                &shape(vec![]),
+                &TsEnumOptions {
+                    tag_field: Some("kind".to_string()),
+                    content_field: Some("data".to_string()),
+                }
+            ),
+            expected
+        );
+    }
+
+    #[test]
+    fn untagged_enum_renders_only_the_data() {
+        assert_eq!(
+            enum_to_ts_type(&shape(vec![serde("untagged")]), &TsEnumOptions::default()),
+            "export type Shape =\n\
+             \x20   | { radius: number }\n\
+             \x20   | number\n\
+             \x20   | null;\n"
+        );
+    }
+}
@@ -18,6 +18,11 @@ pub enum RtkRustcDriverVersion {
     /// A local version of the driver.
     #[rtk_meta(override = string)]
     Local { path: PathBuf },
+
+    /// The driver built from a git repository at a specific revision, written as
+    /// `git:<repo>#<rev>` in scripts.
+    #[rtk_meta(override = string)]
+    Git { repo: String, rev: String },
 }
 
 impl FromLua for RtkRustcDriverVersion {
@@ -30,6 +35,22 @@ impl FromLua for RtkRustcDriverVersion {
                 let path = PathBuf::from(path_str);
                 Ok(RtkRustcDriverVersion::Local { path })
             }
+            git if git.starts_with("git:") => {
+                let (repo, rev) = git
+                    .trim_start_matches("git:")
+                    .split_once('#')
+                    .filter(|(repo, rev)| !repo.is_empty() && !rev.is_empty())
+                    .ok_or_else(|| {
+                        mlua::Error::external(format!(
+                            "Invalid git version format: {git}. Expected format: git:<repo>#<rev>",
+                        ))
+                    })?;
+
+                Ok(RtkRustcDriverVersion::Git {
+                    repo: repo.to_string(),
+                    rev: rev.to_string(),
+                })
+            }
             crates_io => {
                 let parts: Vec<&str> = crates_io.split('.').collect();
                 if parts.len() != 3 {
@@ -72,6 +93,7 @@ impl Display for RtkRustcDriverVersion {
                 write!(f, "{major}.{minor}.{patch}")
             }
             RtkRustcDriverVersion::Local { path } => write!(f, "local:{}", path.display()),
+            RtkRustcDriverVersion::Git { repo, rev } => write!(f, "git:{repo}#{rev}"),
         }
     }
 }
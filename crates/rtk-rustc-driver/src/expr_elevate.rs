@@ -1,18 +1,32 @@
 use rustc_ast::LitKind;
 use rustc_data_structures::fx::FxHashSet;
-use rustc_hir::ExprKind;
+use rustc_hir::{
+    ExprKind,
+    def::{CtorKind, CtorOf, DefKind},
+    def_id::DefId,
+};
 use rustc_middle::ty::{TyCtxt, TyKind};
 use rustc_span::source_map::Spanned;
 
 use crate::{
     path::{self, def_path_of_expr},
+    queries,
     rtk::HirIdItemIdExt,
-    type_elevate::type_as_rtk_lua_type_value,
+    type_elevate::{is_cfg_test_for_did, type_as_rtk_lua_type_value},
 };
 
 /// Given a rustc expr, elevate it into its simpler, lua form. This is the crux of this crate and
 /// where I'd imagine most complexity lies!
 pub fn as_rtk_lua_value(tcx: TyCtxt<'_>, expr: &rustc_hir::Expr<'_>) -> Option<rtk_lua::Value> {
+    // a const standing in for a string literal, like a route path, is as good as the literal
+    if let Some(literal) = const_str_literal(tcx, expr) {
+        return Some(rtk_lua::Value::StringLiteral(literal));
+    }
+
+    if let Some(variant_did) = variant_of_ctor_path(tcx, expr, CtorKind::Const) {
+        return Some(enum_variant_literal(tcx, variant_did, None));
+    }
+
     match expr.kind {
         ExprKind::Lit(Spanned {
             node: LitKind::Str(sym, _cooked_or_raw),
@@ -28,19 +42,32 @@ pub fn as_rtk_lua_value(tcx: TyCtxt<'_>, expr: &rustc_hir::Expr<'_>) -> Option<r
 
             let def_path = def_path_of_expr(tcx, expr)?;
 
+            let origin = rtk_lua::MethodCallQuery {
+                location: path::def_path_to_rtk_location(tcx, &def_path),
+                parent,
+                receiver_type_filter: None,
+            };
+
             Some(rtk_lua::Value::MethodCall(rtk_lua::MethodCall {
-                origin: rtk_lua::MethodCallQuery {
-                    location: path::def_path_to_rtk_location(tcx, &def_path),
-                    parent,
-                },
+                chain_depth: queries::chain_depth_of_expr(expr),
+                origin,
                 args: args
                     .iter()
                     .filter_map(|arg| as_rtk_lua_value(tcx, arg))
                     .collect(),
                 in_item_id: expr.hir_id.rtk_item_id(),
+                is_in_test_module: is_cfg_test_for_did(tcx, expr.hir_id.owner.to_def_id()),
             }))
         }
         ExprKind::Call(call_expr, args) => {
+            if let Some(variant_did) = variant_of_ctor_path(tcx, call_expr, CtorKind::Fn) {
+                let inner = match args {
+                    [arg] => as_rtk_lua_value(tcx, arg),
+                    _ => None,
+                };
+                return Some(enum_variant_literal(tcx, variant_did, inner));
+            }
+
             let def_path = def_path_of_expr(tcx, call_expr)?;
             Some(rtk_lua::Value::FunctionCall(rtk_lua::FunctionCall {
                 location: path::def_path_to_rtk_location(tcx, &def_path),
@@ -49,8 +76,50 @@ pub fn as_rtk_lua_value(tcx: TyCtxt<'_>, expr: &rustc_hir::Expr<'_>) -> Option<r
                     .filter_map(|arg| as_rtk_lua_value(tcx, arg))
                     .collect(),
                 in_item_id: expr.hir_id.rtk_item_id(),
+                is_in_test_module: is_cfg_test_for_did(tcx, expr.hir_id.owner.to_def_id()),
             }))
         }
+        ExprKind::Struct(qpath, fields, _base) => {
+            let typeck = tcx.typeck(expr.hir_id.owner);
+
+            // the type decides which struct is built, as the path can just as well be `Self` or a
+            // type alias. Only enums need the path, to pick out the variant
+            let adt = typeck.expr_ty(expr).ty_adt_def()?;
+            let did = if adt.is_enum() {
+                adt.variant_of_res(typeck.qpath_res(qpath, expr.hir_id))
+                    .def_id
+            } else {
+                adt.did()
+            };
+
+            Some(rtk_lua::Value::StructLiteral {
+                location: path::def_path_to_rtk_location(tcx, &tcx.def_path(did)),
+                fields: fields
+                    .iter()
+                    .filter_map(|field| {
+                        let value = as_rtk_lua_value(tcx, field.expr)?;
+                        Some((field.ident.to_string(), value))
+                    })
+                    .collect(),
+            })
+        }
+        ExprKind::Array(elements) => Some(rtk_lua::Value::ArrayLiteral(
+            elements
+                .iter()
+                .filter_map(|element| {
+                    // anything without a more specific form already falls back to its type, so
+                    // this only misses elements whose type can't be elevated either
+                    let value = as_rtk_lua_value(tcx, element);
+                    if value.is_none() {
+                        tcx.dcx().span_warn(
+                            element.span,
+                            "array element could not be elevated, skipping it",
+                        );
+                    }
+                    value
+                })
+                .collect(),
+        )),
         ExprKind::Closure(closure) => {
             let closure_ty = tcx.type_of(closure.def_id.to_def_id());
 
@@ -91,3 +160,66 @@ pub fn as_rtk_lua_value(tcx: TyCtxt<'_>, expr: &rustc_hir::Expr<'_>) -> Option<r
         }
     }
 }
+
+/// The string literal a path to a local `const` is initialized with, following consts that are
+/// themselves initialized with another const. Consts from other crates have no HIR body to read, so
+/// they are left to fall back to their type
+fn const_str_literal(tcx: TyCtxt<'_>, expr: &rustc_hir::Expr<'_>) -> Option<String> {
+    let ExprKind::Path(qpath) = expr.kind else {
+        return None;
+    };
+
+    let did = tcx
+        .typeck(expr.hir_id.owner)
+        .qpath_res(&qpath, expr.hir_id)
+        .opt_def_id()?;
+    if !matches!(tcx.def_kind(did), DefKind::Const | DefKind::AssocConst) {
+        return None;
+    }
+
+    let body_id = tcx.hir_node_by_def_id(did.as_local()?).body_id()?;
+    let init = tcx.hir_body(body_id).value;
+
+    match init.kind {
+        ExprKind::Lit(Spanned {
+            node: LitKind::Str(sym, _cooked_or_raw),
+            ..
+        }) => Some(sym.to_string()),
+        ExprKind::Path(_) => const_str_literal(tcx, init),
+        _ => None,
+    }
+}
+
+/// The enum variant a path to one of its constructors names, like `Status::Inactive` for a unit
+/// variant (`CtorKind::Const`) or the `Status::Active` called in `Status::Active(42)`
+/// (`CtorKind::Fn`)
+fn variant_of_ctor_path(
+    tcx: TyCtxt<'_>,
+    expr: &rustc_hir::Expr<'_>,
+    ctor_kind: CtorKind,
+) -> Option<DefId> {
+    let ExprKind::Path(qpath) = expr.kind else {
+        return None;
+    };
+
+    let did = tcx
+        .typeck(expr.hir_id.owner)
+        .qpath_res(&qpath, expr.hir_id)
+        .opt_def_id()?;
+
+    (tcx.def_kind(did) == DefKind::Ctor(CtorOf::Variant, ctor_kind)).then(|| tcx.parent(did))
+}
+
+fn enum_variant_literal(
+    tcx: TyCtxt<'_>,
+    variant_did: DefId,
+    inner: Option<rtk_lua::Value>,
+) -> rtk_lua::Value {
+    let enum_did = tcx.parent(variant_did);
+
+    rtk_lua::Value::EnumVariantLiteral {
+        location: path::def_path_to_rtk_location(tcx, &tcx.def_path(enum_did)),
+        variant: tcx.item_name(variant_did).to_string(),
+        inner: inner.map(Box::new),
+    }
+}
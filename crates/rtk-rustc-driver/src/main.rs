@@ -40,6 +40,8 @@ fn main() -> ExitCode {
     let early_dcx = EarlyDiagCtxt::new(ErrorOutputType::default());
 
     rustc_driver::init_rustc_env_logger(&early_dcx);
+    // rustc logs through `RUSTC_LOG`, this is for our own logs under `RUST_LOG` like the cli's
+    env_logger::init();
 
     let exit_code = catch_with_exit_code(move || {
         let mut args = rustc_driver::args::raw_args(&early_dcx);
@@ -55,11 +57,15 @@ fn main() -> ExitCode {
                 "missing `RTK_OUT_FILE` env var, you are likely not running through the cli",
             );
 
+            // set by the cli for `--deny-warnings`
+            let deny_warnings = std::env::var("RTK_DENY_WARNINGS").is_ok();
+
             run_compiler(
                 &args,
                 &mut rtk::RtkCallbacks {
                     lua_script_path,
                     out_file_path,
+                    deny_warnings,
                 },
             );
         } else {
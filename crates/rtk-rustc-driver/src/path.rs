@@ -1,4 +1,7 @@
-use rustc_hir::definitions::DefPath;
+use std::hash::{Hash, Hasher};
+
+use rustc_hash::FxHasher;
+use rustc_hir::{def_id::LOCAL_CRATE, definitions::DefPath};
 use rustc_middle::ty::TyCtxt;
 
 /// From an expr, typecheck the owner and derive the full def path
@@ -12,8 +15,9 @@ pub fn def_path_of_expr(tcx: TyCtxt<'_>, expr: &rustc_hir::Expr<'_>) -> Option<D
                 return None;
             };
 
+            // paths to locals, like a closure bound to a variable, have no def path to give
             let qpath_res = typeck.qpath_res(&qpath, expr.hir_id);
-            Some(tcx.def_path(qpath_res.def_id()))
+            Some(tcx.def_path(qpath_res.opt_def_id()?))
         }
     }
 }
@@ -29,6 +33,10 @@ pub fn def_path_to_rtk_location(tcx: TyCtxt<'_>, dp: &DefPath) -> rtk_lua::Locat
                 tcx.dcx()
                     .fatal("deeply nested impl blocks currently unsupported");
             }
+            // tuple struct and variant constructors live beneath the item they construct, as a
+            // `{{constructor}}` segment no script could write a query for. Calling one is a call
+            // to that item as far as scripts are concerned, so `Point(1, 2)` is reported at `Point`
+            rustc_hir::definitions::DefPathData::Ctor => (module_path, impl_block_number),
             _ => {
                 module_path.push(segment.data.to_string());
                 (module_path, impl_block_number)
@@ -40,15 +48,15 @@ pub fn def_path_to_rtk_location(tcx: TyCtxt<'_>, dp: &DefPath) -> rtk_lua::Locat
         crate_name: tcx.crate_name(dp.krate).to_string(),
         path,
         impl_block_number,
+        is_local: dp.krate == LOCAL_CRATE,
     }
 }
 
-pub fn fmt_rtk_location(loc: &rtk_lua::Location) -> String {
-    let impl_block = if let Some(impl_block_number) = loc.impl_block_number {
-        format!("{{impl#{impl_block_number}}}")
-    } else {
-        String::new()
-    };
-
-    format!("{}::{}{}", loc.crate_name, loc.path.join("::"), impl_block)
+/// The `type_hash` of the struct or enum at `location`. `FxHasher` is unseeded, so the same
+/// location hashes the same on every run. The top bit is dropped to keep the hash within a Lua
+/// integer, which would otherwise turn it into a lossy float
+pub fn type_hash_of_location(location: &rtk_lua::Location) -> u64 {
+    let mut hasher = FxHasher::default();
+    location.hash(&mut hasher);
+    hasher.finish() >> 1
 }
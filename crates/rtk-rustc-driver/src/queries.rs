@@ -1,57 +1,129 @@
 use rustc_data_structures::fx::FxHashSet;
-use rustc_hir::{ExprKind, ImplItemKind, ItemKind};
-use rustc_middle::ty::TyCtxt;
+use rustc_hir::{
+    ExprKind, GenericParamKind, ImplItemKind, ItemKind, PatKind, def::DefKind, def_id::DefId,
+};
+use rustc_middle::ty::{AssocKind, Ty, TyCtxt};
+use rustc_span::{
+    BytePos, Span,
+    hygiene::{ExpnId, ExpnKind, MacroKind},
+};
 
 use crate::{
-    expr_elevate,
-    path::{self, fmt_rtk_location},
+    expr_elevate, path,
     rtk::HirIdItemIdExt,
-    type_elevate::{attributes_for_did, doc_comment_for_did, hir_type_as_rtk_lua_type_value},
+    type_elevate::{
+        attributes_for_did, awaited_output, doc_comment_for_did, hir_type_as_rtk_lua_type_value,
+        impl_trait_arg, is_cfg_test_for_did, is_test_for_did, lifetime_count_for_did,
+        overrides_trait_method_for_did, type_as_rtk_lua_type_value, visibility_text_for_did,
+        where_clause_text_for_did,
+    },
 };
 
+/// Where the call at `span` sits in the source, so calls can be put in source order rather than
+/// HIR walk order. Calls expanded from a macro are placed where the macro was called
+pub fn call_site_order(tcx: TyCtxt<'_>, span: Span) -> (String, BytePos) {
+    let lo = span.source_callsite().lo();
+    let file = tcx.sess.source_map().lookup_source_file(lo);
+    (file.name.prefer_local().to_string(), lo)
+}
+
 pub fn method_call_from_expr(
     tcx: TyCtxt<'_>,
     mc: &rtk_lua::MethodCallQuery,
     expr: &rustc_hir::Expr<'_>,
 ) -> Option<rtk_lua::MethodCall> {
-    let (reciever, args, _span) = match expr.kind {
-        ExprKind::MethodCall(_path_seg, rx, args, span) => (*rx, args.iter().copied(), span),
-        _ => return None,
+    if !method_call_matches(tcx, mc, expr) {
+        return None;
+    }
+
+    let ExprKind::MethodCall(_path_seg, _rx, args, _span) = expr.kind else {
+        return None;
+    };
+
+    let args = args
+        .iter()
+        .copied()
+        .filter_map(|arg| expr_elevate::as_rtk_lua_value(tcx, &arg))
+        .collect();
+
+    let mc = rtk_lua::MethodCall {
+        origin: mc.clone(),
+        args,
+        in_item_id: expr.hir_id.rtk_item_id(),
+        is_in_test_module: is_cfg_test_for_did(tcx, expr.hir_id.owner.to_def_id()),
+        chain_depth: chain_depth_of_expr(expr),
+    };
+
+    Some(mc)
+}
+
+/// How many method calls the method call `expr` is chained onto, counted through its receivers.
+/// The query that matched it may only name the last few links of the chain, so this is read from
+/// the expression rather than the query
+pub fn chain_depth_of_expr(expr: &rustc_hir::Expr<'_>) -> usize {
+    let mut depth = 0;
+    let mut current = expr;
+    while let ExprKind::MethodCall(_path_seg, receiver, _args, _span) = current.kind {
+        if !matches!(receiver.kind, ExprKind::MethodCall(..)) {
+            break;
+        }
+
+        depth += 1;
+        current = receiver;
+    }
+
+    depth
+}
+
+/// Whether `expr` is a method call matching the query, without elevating any of its arguments
+pub fn method_call_matches(
+    tcx: TyCtxt<'_>,
+    mc: &rtk_lua::MethodCallQuery,
+    expr: &rustc_hir::Expr<'_>,
+) -> bool {
+    let ExprKind::MethodCall(_path_seg, reciever, _args, _span) = expr.kind else {
+        return false;
     };
 
     if let Some(mcq) = &mc.parent {
         // TODO: this needs to walk up the call chain, currently this just enforces direct parents
-        let _ = method_call_from_expr(tcx, mcq, &reciever)?;
+        if !method_call_matches(tcx, mcq, reciever) {
+            return false;
+        }
     }
 
-    let def_path = path::def_path_of_expr(tcx, expr)?;
+    let Some(def_path) = path::def_path_of_expr(tcx, expr) else {
+        return false;
+    };
     let def_path_loc = path::def_path_to_rtk_location(tcx, &def_path);
 
-    if def_path_loc != mc.location {
+    if !def_path_loc.same_item(&mc.location) {
         if def_path_loc.path.last() == mc.location.path.last() {
             tcx.dcx().warn(
                 format!(
                     "query for `{}` likely intended to match against `{}`, consider changing the impl block number",
-                    fmt_rtk_location(&mc.location),
-                    fmt_rtk_location(&def_path_loc),
+                    mc.location,
+                    def_path_loc,
                 ),
             );
         }
 
-        return None;
+        return false;
     }
 
-    let args = args
-        .filter_map(|arg| expr_elevate::as_rtk_lua_value(tcx, &arg))
-        .collect();
+    if let Some(receiver_type_filter) = &mc.receiver_type_filter {
+        let receiver_ty = tcx.typeck(expr.hir_id.owner).expr_ty(reciever).peel_refs();
+        let Some(receiver_adt) = receiver_ty.ty_adt_def() else {
+            return false;
+        };
 
-    let mc = rtk_lua::MethodCall {
-        origin: mc.clone(),
-        args,
-        in_item_id: expr.hir_id.rtk_item_id(),
-    };
+        let receiver_loc = path::def_path_to_rtk_location(tcx, &tcx.def_path(receiver_adt.did()));
+        if !receiver_loc.same_item(receiver_type_filter) {
+            return false;
+        }
+    }
 
-    Some(mc)
+    true
 }
 
 pub fn trait_impl_from_item<'tcx>(
@@ -66,7 +138,7 @@ pub fn trait_impl_from_item<'tcx>(
     let of_trait = i.of_trait?;
     let def_path = tcx.def_path(of_trait.trait_def_id().unwrap());
 
-    if &path::def_path_to_rtk_location(tcx, &def_path) != location {
+    if !path::def_path_to_rtk_location(tcx, &def_path).same_item(location) {
         return None;
     }
 
@@ -107,11 +179,119 @@ pub fn trait_impl_from_item<'tcx>(
 
     Some(rtk_lua::TraitImpl {
         trait_location: location.clone(),
+        trait_generic_args: trait_generic_args_of_impl(tcx, item.owner_id.to_def_id()),
         for_type,
+        for_type_attributes: for_type_attributes_of_impl(tcx, item.owner_id.to_def_id()),
         functions: functions.collect(),
     })
 }
 
+/// Every impl of the trait at `location`, whichever crate of the compilation it sits in.
+/// Dependencies have no HIR, so unlike `trait_impl_from_item` everything is elevated from the
+/// impl's types rather than its source
+pub fn all_trait_impls_for_location(
+    tcx: TyCtxt<'_>,
+    location: &rtk_lua::Location,
+) -> Vec<rtk_lua::TraitImpl> {
+    let Some(trait_did) = tcx
+        .all_traits()
+        .find(|did| path::def_path_to_rtk_location(tcx, &tcx.def_path(*did)).same_item(location))
+    else {
+        return vec![];
+    };
+
+    tcx.all_impls(trait_did)
+        .filter_map(|impl_did| {
+            let self_ty = tcx.type_of(impl_did).instantiate_identity();
+            let Some(for_type) =
+                type_as_rtk_lua_type_value(tcx, &self_ty, &mut FxHashSet::default())
+            else {
+                tcx.dcx().warn(format!(
+                    "failed to convert the self type of `{}`",
+                    tcx.def_path_str(impl_did)
+                ));
+                return None;
+            };
+
+            let functions = tcx
+                .associated_items(impl_did)
+                .in_definition_order()
+                .filter(|item| item.kind == AssocKind::Fn)
+                .filter_map(|item| {
+                    let fn_ty = tcx.type_of(item.def_id).instantiate_identity();
+                    match type_as_rtk_lua_type_value(tcx, &fn_ty, &mut FxHashSet::default())? {
+                        rtk_lua::TypeValue::Function(f) => Some(f),
+                        _ => None,
+                    }
+                })
+                .collect();
+
+            Some(rtk_lua::TraitImpl {
+                trait_location: location.clone(),
+                trait_generic_args: trait_generic_args_of_impl(tcx, impl_did),
+                for_type,
+                for_type_attributes: for_type_attributes_of_impl(tcx, impl_did),
+                functions,
+            })
+        })
+        .collect()
+}
+
+fn trait_generic_args_of_impl(tcx: TyCtxt<'_>, impl_did: DefId) -> Vec<rtk_lua::TypeValue> {
+    // the first arg of the trait ref is always `Self`, which we already have as `for_type`
+    tcx.impl_trait_ref(impl_did)
+        .map(|trait_ref| {
+            trait_ref
+                .instantiate_identity()
+                .args
+                .types()
+                .skip(1)
+                // kept as unsupported rather than dropped, so every arg stays in its position
+                .map(|ty| {
+                    type_as_rtk_lua_type_value(tcx, &ty, &mut FxHashSet::default()).unwrap_or_else(
+                        || rtk_lua::TypeValue::Unsupported {
+                            rust_type: ty.to_string(),
+                        },
+                    )
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn for_type_attributes_of_impl(tcx: TyCtxt<'_>, impl_did: DefId) -> Vec<rtk_lua::Attribute> {
+    tcx.type_of(impl_did)
+        .instantiate_identity()
+        .peel_refs()
+        .ty_adt_def()
+        .map(|adt| attributes_for_did(tcx, adt.did()))
+        .unwrap_or_default()
+}
+
+/// If `item` is a trait impl for the type at `location`, returns the location of the trait
+pub fn impl_trait_for_type_from_item<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    location: &rtk_lua::Location,
+    item: &rustc_hir::Item<'tcx>,
+) -> Option<rtk_lua::Location> {
+    let ItemKind::Impl(i) = item.kind else {
+        return None;
+    };
+
+    let of_trait = i.of_trait?;
+
+    let self_ty = tcx.type_of(item.owner_id).instantiate_identity();
+    let self_adt = self_ty.ty_adt_def()?;
+    let self_location = path::def_path_to_rtk_location(tcx, &tcx.def_path(self_adt.did()));
+
+    if !self_location.same_item(location) {
+        return None;
+    }
+
+    let trait_def_path = tcx.def_path(of_trait.trait_def_id()?);
+    Some(path::def_path_to_rtk_location(tcx, &trait_def_path))
+}
+
 pub fn function_from_item<'tcx>(
     tcx: TyCtxt<'tcx>,
     location: &rtk_lua::Location,
@@ -127,10 +307,14 @@ pub fn function_from_item<'tcx>(
         return None;
     };
 
-    if !generics.params.is_empty() {
+    if generics
+        .params
+        .iter()
+        .any(|param| is_user_declared_generic(&param.kind))
+    {
         tcx.dcx().span_warn(
             item.span,
-            "function generic parameters will be ignored (may be elided lifetimes or synthetic impl generics)",
+            "function type and const generic parameters will be ignored",
         );
     }
 
@@ -141,13 +325,341 @@ pub fn function_from_item<'tcx>(
     }
 
     let def_path = tcx.def_path(item.owner_id.def_id.to_def_id());
-    if &path::def_path_to_rtk_location(tcx, &def_path) != location {
+    if !path::def_path_to_rtk_location(tcx, &def_path).same_item(location) {
         return None;
     }
 
     fn_sig_into_rtk_function_value_type(tcx, item.owner_id, &body, location, &sig)
 }
 
+/// If `impl_item` is a function in an inherent impl block matching `location`, returns the type
+/// the block is for. Functions in trait impls are left to `query_trait_impls`. A type's generic
+/// args are left as its own params, since functions of the same name can only be defined across
+/// blocks for different args, e.g. `impl Wrapper<u8>` and `impl Wrapper<u32>`
+pub fn inherent_impl_fn_self_ty<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    location: &rtk_lua::Location,
+    impl_item: &rustc_hir::ImplItem<'tcx>,
+) -> Option<Ty<'tcx>> {
+    let ImplItemKind::Fn(..) = impl_item.kind else {
+        return None;
+    };
+
+    let did = impl_item.owner_id.def_id.to_def_id();
+    let impl_did = tcx.parent(did);
+    if tcx.trait_id_of_impl(impl_did).is_some() {
+        return None;
+    }
+
+    let def_path = tcx.def_path(did);
+    if !path::def_path_to_rtk_location(tcx, &def_path).matches_query(location) {
+        return None;
+    }
+
+    let self_ty = tcx.type_of(impl_did).instantiate_identity();
+    Some(match self_ty.ty_adt_def() {
+        Some(adt_def) => tcx.type_of(adt_def.did()).instantiate_identity(),
+        None => self_ty,
+    })
+}
+
+/// Like `function_from_item`, for a function in an inherent impl block. Any of the type's impl
+/// blocks match when `location` has no `impl_block_number`, so the type the function is defined
+/// on comes back with it for grouping overloads
+pub fn function_from_impl_item<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    location: &rtk_lua::Location,
+    impl_item: &rustc_hir::ImplItem<'tcx>,
+) -> Option<(Ty<'tcx>, rtk_lua::FunctionTypeValue)> {
+    let self_ty = inherent_impl_fn_self_ty(tcx, location, impl_item)?;
+    let ImplItemKind::Fn(sig, body_id) = impl_item.kind else {
+        return None;
+    };
+
+    let def_path = tcx.def_path(impl_item.owner_id.def_id.to_def_id());
+    let fn_location = path::def_path_to_rtk_location(tcx, &def_path);
+    let function =
+        fn_sig_into_rtk_function_value_type(tcx, impl_item.owner_id, &body_id, &fn_location, &sig)?;
+
+    Some((self_ty, function))
+}
+
+/// Whether a generic parameter is a type or const generic written out by the user. Lifetimes are
+/// reported through `lifetime_count` instead, and the synthetic params `impl Trait` arguments
+/// desugar into are already elevated as part of the argument types
+fn is_user_declared_generic(kind: &GenericParamKind<'_>) -> bool {
+    match kind {
+        GenericParamKind::Lifetime { .. } => false,
+        GenericParamKind::Type { synthetic, .. } => !synthetic,
+        GenericParamKind::Const { .. } => true,
+    }
+}
+
+/// Like `function_from_item`, but matching any function defined beneath the module at `module`
+pub fn function_in_module_from_item<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    module: &rtk_lua::Location,
+    item: &rustc_hir::Item<'tcx>,
+) -> Option<rtk_lua::FunctionTypeValue> {
+    let ItemKind::Fn {
+        sig,
+        body,
+        has_body: true,
+        ..
+    } = item.kind
+    else {
+        return None;
+    };
+
+    let def_path = tcx.def_path(item.owner_id.def_id.to_def_id());
+    let location = path::def_path_to_rtk_location(tcx, &def_path);
+    if !location.is_within(module) {
+        return None;
+    }
+
+    fn_sig_into_rtk_function_value_type(tcx, item.owner_id, &body, &location, &sig)
+}
+
+/// Whether `item` is the function at `location`, without elevating its signature
+pub fn function_item_matches(
+    tcx: TyCtxt<'_>,
+    location: &rtk_lua::Location,
+    item: &rustc_hir::Item<'_>,
+) -> bool {
+    if !matches!(item.kind, ItemKind::Fn { has_body: true, .. }) {
+        return false;
+    }
+
+    let def_path = tcx.def_path(item.owner_id.def_id.to_def_id());
+    path::def_path_to_rtk_location(tcx, &def_path).same_item(location)
+}
+
+/// Whether `item` is left out of item queries unless the script asks for hidden items, being either
+/// `#[doc(hidden)]` or not plainly `pub`. Only the item itself is left out, visitors still walk
+/// beneath it, so a `pub` item in a private module is found like any other
+pub fn is_hidden_item(tcx: TyCtxt<'_>, item: &rustc_hir::Item<'_>) -> bool {
+    // impl blocks and extern blocks have no visibility of their own, their items carry it
+    if matches!(item.kind, ItemKind::Impl(_) | ItemKind::ForeignMod { .. }) {
+        return false;
+    }
+
+    is_hidden_did(tcx, item.owner_id.def_id.to_def_id())
+}
+
+/// Like `is_hidden_item`, for anything with a visibility of its own such as an impl item
+pub fn is_hidden_did(tcx: TyCtxt<'_>, did: DefId) -> bool {
+    !tcx.visibility(did).is_public()
+        || rtk_lua::Attribute::is_doc_hidden(&attributes_for_did(tcx, did))
+}
+
+/// If `item` is the trait declared at `location`, elevates its methods, associated types and
+/// associated consts in the order they are declared
+pub fn trait_def_from_item(
+    tcx: TyCtxt<'_>,
+    location: &rtk_lua::Location,
+    item: &rustc_hir::Item<'_>,
+) -> Option<rtk_lua::TraitDef> {
+    let ItemKind::Trait(..) = item.kind else {
+        return None;
+    };
+
+    let did = item.owner_id.def_id.to_def_id();
+    let trait_location = path::def_path_to_rtk_location(tcx, &tcx.def_path(did));
+    if !trait_location.same_item(location) {
+        return None;
+    }
+
+    let mut functions = vec![];
+    let mut associated_types = vec![];
+    let mut associated_consts = vec![];
+    for assoc in tcx.associated_items(did).in_definition_order() {
+        // associated types without a default have no type to ask for, so this is only asked of
+        // fns and consts
+        let assoc_ty = || tcx.type_of(assoc.def_id).instantiate_identity();
+        match assoc.kind {
+            AssocKind::Fn => {
+                match type_as_rtk_lua_type_value(tcx, &assoc_ty(), &mut FxHashSet::default()) {
+                    Some(rtk_lua::TypeValue::Function(f)) => functions.push(f),
+                    _ => tcx.dcx().span_warn(
+                        tcx.def_span(assoc.def_id),
+                        "failed to convert the signature of trait method",
+                    ),
+                }
+            }
+            AssocKind::Type => associated_types.push(assoc.name.to_string()),
+            AssocKind::Const => {
+                let Some(type_value) =
+                    type_as_rtk_lua_type_value(tcx, &assoc_ty(), &mut FxHashSet::default())
+                else {
+                    tcx.dcx().span_warn(
+                        tcx.def_span(assoc.def_id),
+                        "failed to convert the type of associated const",
+                    );
+                    continue;
+                };
+
+                // only consts given a default in the trait have a body to read a value from
+                let value = assoc
+                    .def_id
+                    .as_local()
+                    .and_then(|local| tcx.hir_node_by_def_id(local).body_id())
+                    .and_then(|body_id| {
+                        expr_elevate::as_rtk_lua_value(tcx, tcx.hir_body(body_id).value)
+                    });
+
+                associated_consts.push(rtk_lua::ConstantValue {
+                    location: path::def_path_to_rtk_location(tcx, &tcx.def_path(assoc.def_id)),
+                    type_value,
+                    value,
+                    doc_comment: doc_comment_for_did(tcx, assoc.def_id),
+                    attributes: attributes_for_did(tcx, assoc.def_id),
+                });
+            }
+        }
+    }
+
+    Some(rtk_lua::TraitDef {
+        location: trait_location,
+        doc_comment: doc_comment_for_did(tcx, did),
+        attributes: attributes_for_did(tcx, did),
+        functions,
+        associated_types,
+        associated_consts,
+    })
+}
+
+/// If `item` is a module, returns its location
+pub fn module_from_item(tcx: TyCtxt<'_>, item: &rustc_hir::Item<'_>) -> Option<rtk_lua::Location> {
+    let ItemKind::Mod(_) = item.kind else {
+        return None;
+    };
+
+    let def_path = tcx.def_path(item.owner_id.def_id.to_def_id());
+    Some(path::def_path_to_rtk_location(tcx, &def_path))
+}
+
+/// If `item` is a `const` whose type matches `type_filter`, elevates it along with its value
+pub fn constant_from_item(
+    tcx: TyCtxt<'_>,
+    type_filter: &rtk_lua::TypeValue,
+    item: &rustc_hir::Item<'_>,
+) -> Option<rtk_lua::ConstantValue> {
+    let ItemKind::Const(_, _, body_id) = item.kind else {
+        return None;
+    };
+
+    let did = item.owner_id.def_id.to_def_id();
+    let ty = tcx.type_of(did).instantiate_identity();
+    let type_value = type_as_rtk_lua_type_value(tcx, &ty, &mut FxHashSet::default())?;
+    if !type_value.matches_type_filter(type_filter) {
+        return None;
+    }
+
+    let def_path = tcx.def_path(did);
+    Some(rtk_lua::ConstantValue {
+        location: path::def_path_to_rtk_location(tcx, &def_path),
+        type_value,
+        value: expr_elevate::as_rtk_lua_value(tcx, tcx.hir_body(body_id).value),
+        doc_comment: doc_comment_for_did(tcx, did),
+        attributes: attributes_for_did(tcx, did),
+    })
+}
+
+/// Whether `location` points into a dependency rather than the crate being analyzed
+pub fn is_extern_location(tcx: TyCtxt<'_>, location: &rtk_lua::Location) -> bool {
+    tcx.crate_name(rustc_hir::def_id::LOCAL_CRATE).as_str() != location.crate_name
+}
+
+/// Finds the item at `location` in a dependency. Dependencies have no HIR for the visitors to
+/// walk, so this walks the dependency's module tree from its crate root instead
+pub fn extern_def_id_for_location(tcx: TyCtxt<'_>, location: &rtk_lua::Location) -> Option<DefId> {
+    let krate = tcx
+        .crates(())
+        .iter()
+        .copied()
+        .find(|cnum| tcx.crate_name(*cnum).as_str() == location.crate_name)?;
+
+    let mut to_visit = vec![krate.as_def_id()];
+    let mut visited = FxHashSet::default();
+
+    while let Some(did) = to_visit.pop() {
+        if !visited.insert(did) {
+            continue;
+        }
+
+        if path::def_path_to_rtk_location(tcx, &tcx.def_path(did)).same_item(location) {
+            return Some(did);
+        }
+
+        match tcx.def_kind(did) {
+            DefKind::Mod => to_visit.extend(
+                tcx.module_children(did)
+                    .iter()
+                    .filter_map(|child| child.res.opt_def_id())
+                    // re-exports can point into other crates, which we'd never match in
+                    .filter(|child_did| child_did.krate == krate),
+            ),
+            DefKind::Struct | DefKind::Enum => to_visit.extend(
+                tcx.inherent_impls(did)
+                    .iter()
+                    .flat_map(|impl_did| tcx.associated_item_def_ids(*impl_did).iter().copied()),
+            ),
+            _ => {}
+        }
+    }
+
+    None
+}
+
+/// Finds the item at `location`, whether it is in the crate or one of its dependencies
+pub fn def_id_for_location(tcx: TyCtxt<'_>, location: &rtk_lua::Location) -> Option<DefId> {
+    if is_extern_location(tcx, location) {
+        return extern_def_id_for_location(tcx, location);
+    }
+
+    tcx.hir_crate_items(())
+        .definitions()
+        .map(|did| did.to_def_id())
+        .find(|did| path::def_path_to_rtk_location(tcx, &tcx.def_path(*did)).same_item(location))
+}
+
+/// The fields serde serializes for the struct at `location`. The struct is elevated like any other
+/// type, which reads the serde attributes on each of its fields
+pub fn serde_fields_for_location(
+    tcx: TyCtxt<'_>,
+    location: &rtk_lua::Location,
+) -> Vec<rtk_lua::SerdeField> {
+    let Some(did) = def_id_for_location(tcx, location) else {
+        return vec![];
+    };
+    if tcx.def_kind(did) != DefKind::Struct {
+        return vec![];
+    }
+
+    let ty = tcx.type_of(did).instantiate_identity();
+    match type_as_rtk_lua_type_value(tcx, &ty, &mut FxHashSet::default()) {
+        Some(rtk_lua::TypeValue::Struct(s)) => rtk_lua::SerdeField::of_struct(&s),
+        _ => vec![],
+    }
+}
+
+/// The extern equivalent of `function_from_item`, elevating the function from its signature
+pub fn extern_function_for_location(
+    tcx: TyCtxt<'_>,
+    location: &rtk_lua::Location,
+) -> Option<rtk_lua::FunctionTypeValue> {
+    let did = extern_def_id_for_location(tcx, location)?;
+    if !matches!(tcx.def_kind(did), DefKind::Fn | DefKind::AssocFn) {
+        return None;
+    }
+
+    let fn_ty = tcx.type_of(did).instantiate_identity();
+    match type_as_rtk_lua_type_value(tcx, &fn_ty, &mut FxHashSet::default())? {
+        rtk_lua::TypeValue::Function(f) => Some(f),
+        _ => None,
+    }
+}
+
 // TODO: consolidate this better with the type elevation module
 fn fn_sig_into_rtk_function_value_type<'tcx>(
     tcx: TyCtxt<'tcx>,
@@ -156,19 +668,26 @@ fn fn_sig_into_rtk_function_value_type<'tcx>(
     loc: &rtk_lua::Location,
     sig: &rustc_hir::FnSig<'tcx>,
 ) -> Option<rtk_lua::FunctionTypeValue> {
-    let is_async = tcx.asyncness(owner_id.def_id.to_def_id()).is_async();
-    let args_struct_fields = sig
-        .decl
-        .inputs
+    // the types are read from the signature, since `type_of` on an argument's HIR type only gives
+    // back the function that owns it
+    let fn_sig = tcx
+        .fn_sig(owner_id.def_id.to_def_id())
+        .instantiate_identity()
+        .skip_binder();
+    let input_tys = fn_sig.inputs();
+    let args_struct_fields = input_tys
         .iter()
         .enumerate()
-        .filter_map(|(i, arg)| {
-            let value =
-                hir_type_as_rtk_lua_type_value(tcx, arg, is_async, &mut FxHashSet::default())?;
+        .filter_map(|(i, ty)| {
+            let value = impl_trait_arg(tcx, owner_id.def_id.to_def_id(), *ty)
+                .or_else(|| type_as_rtk_lua_type_value(tcx, ty, &mut FxHashSet::default()))?;
 
             Some(rtk_lua::StructTypeValueField {
                 name: rtk_lua::Either::Left(i),
                 attributes: vec![],
+                is_optional: rtk_lua::StructTypeValueField::is_optional_for(&value, &[]),
+                has_serde_skip: false,
+                serde_rename: None,
                 value,
                 doc_comment: None,
             })
@@ -180,6 +699,16 @@ fn fn_sig_into_rtk_function_value_type<'tcx>(
         fields: args_struct_fields,
         attributes: attributes_for_did(tcx, owner_id.def_id.to_def_id()),
         doc_comment: doc_comment_for_did(tcx, owner_id.def_id.to_def_id()),
+        // only the positions of the args are known here, not their names
+        kind: rtk_lua::StructKind::Tuple,
+        derives: vec![],
+        is_non_exhaustive: false,
+        newtype_inner: None,
+        has_custom_serialize: false,
+        has_custom_deserialize: false,
+        type_hash: path::type_hash_of_location(&loc),
+        size: None,
+        align: None,
     };
 
     let function_def_path = tcx.def_path(owner_id.def_id.to_def_id());
@@ -188,9 +717,8 @@ fn fn_sig_into_rtk_function_value_type<'tcx>(
     let is_async = tcx.asyncness(owner_id.def_id.to_def_id()).is_async();
     let return_type = match sig.decl.output {
         rustc_hir::FnRetTy::DefaultReturn(_) => None,
-        rustc_hir::FnRetTy::Return(ty) => {
-            hir_type_as_rtk_lua_type_value(tcx, ty, is_async, &mut FxHashSet::default())
-        }
+        rustc_hir::FnRetTy::Return(_) => awaited_output(tcx, fn_sig.output(), is_async)
+            .and_then(|ty| type_as_rtk_lua_type_value(tcx, &ty, &mut FxHashSet::default())),
     }
     .map(Box::new);
 
@@ -202,13 +730,155 @@ fn fn_sig_into_rtk_function_value_type<'tcx>(
         item_id: body_id.hir_id.rtk_item_id(),
         attributes: attributes_for_did(tcx, owner_id.def_id.to_def_id()),
         doc_comment: doc_comment_for_did(tcx, owner_id.def_id.to_def_id()),
+        where_clause_text: where_clause_text_for_did(tcx, owner_id.def_id.to_def_id()),
+        is_test: is_test_for_did(tcx, owner_id.def_id.to_def_id()),
+        is_cfg_test: is_cfg_test_for_did(tcx, owner_id.def_id.to_def_id()),
+        lifetime_count: lifetime_count_for_did(tcx, owner_id.def_id.to_def_id()),
+        is_public: tcx.visibility(owner_id.def_id).is_public(),
+        visibility: visibility_text_for_did(tcx, owner_id.def_id.to_def_id()),
+        takes_no_args: sig.decl.inputs.is_empty(),
+        overrides_trait_method: overrides_trait_method_for_did(tcx, owner_id.def_id.to_def_id()),
+        overloads: vec![],
     })
 }
 
+/// Finds the body of the function (or any other body owner) at `location` in the crate
+pub fn body_id_for_location(
+    tcx: TyCtxt<'_>,
+    location: &rtk_lua::Location,
+) -> Option<rustc_hir::BodyId> {
+    tcx.hir_body_owners()
+        .find(|did| {
+            path::def_path_to_rtk_location(tcx, &tcx.def_path(did.to_def_id())).same_item(location)
+        })
+        .and_then(|did| tcx.hir_node_by_def_id(did).body_id())
+}
+
 pub fn function_call_from_expr(
     tcx: TyCtxt<'_>,
     loc: &rtk_lua::Location,
     expr: &rustc_hir::Expr<'_>,
+) -> Option<rtk_lua::FunctionCall> {
+    function_call_matching_from_expr(tcx, expr, |callee| callee.matches_query(loc))
+}
+
+pub fn struct_literal_from_expr(
+    tcx: TyCtxt<'_>,
+    loc: &rtk_lua::Location,
+    expr: &rustc_hir::Expr<'_>,
+) -> Option<rtk_lua::StructLiteralSite> {
+    if !matches!(expr.kind, ExprKind::Struct(..)) {
+        return None;
+    }
+
+    // struct expressions always elevate to a literal, which resolves the path to the struct
+    let rtk_lua::Value::StructLiteral { location, fields } =
+        expr_elevate::as_rtk_lua_value(tcx, expr)?
+    else {
+        return None;
+    };
+
+    if !location.same_item(loc) {
+        return None;
+    }
+
+    Some(rtk_lua::StructLiteralSite {
+        location,
+        fields,
+        in_item_id: expr.hir_id.rtk_item_id(),
+    })
+}
+
+/// If the code at `span` was expanded from an invocation of the bang macro `name`, returns that
+/// expansion and the invocation. Expansions are followed outwards, so invocations written inside
+/// the arguments of other macros are found too
+pub fn macro_call_from_span(
+    tcx: TyCtxt<'_>,
+    name: &str,
+    span: Span,
+    hir_id: rustc_hir::HirId,
+) -> Option<(ExpnId, rtk_lua::MacroCall)> {
+    let mut ctxt = span.ctxt();
+    while !ctxt.is_root() {
+        let expn = ctxt.outer_expn();
+        let expn_data = expn.expn_data();
+
+        if let ExpnKind::Macro(MacroKind::Bang, macro_name) = expn_data.kind {
+            if macro_name.as_str() == name {
+                let text = tcx
+                    .sess
+                    .source_map()
+                    .span_to_snippet(expn_data.call_site)
+                    .ok()?;
+
+                return Some((
+                    expn,
+                    rtk_lua::MacroCall {
+                        name: name.to_string(),
+                        args_text: rtk_lua::MacroCall::args_text_of(&text),
+                        text,
+                        in_item_id: hir_id.rtk_item_id(),
+                    },
+                ));
+            }
+        }
+
+        ctxt = expn_data.call_site.ctxt();
+    }
+
+    None
+}
+
+/// If `expr` is a closure capturing a variable called `capture_name`, elevates the closure
+pub fn closure_with_capture_from_expr(
+    tcx: TyCtxt<'_>,
+    capture_name: &str,
+    expr: &rustc_hir::Expr<'_>,
+) -> Option<rtk_lua::ClosureTypeValue> {
+    let ExprKind::Closure(closure) = expr.kind else {
+        return None;
+    };
+
+    // captures are resolved to the binding of the variable captured, which carries its name
+    let captures_name = tcx.closure_captures(closure.def_id).iter().any(|place| {
+        matches!(
+            tcx.hir_node(place.get_root_variable()),
+            rustc_hir::Node::Pat(rustc_hir::Pat {
+                kind: PatKind::Binding(_, _, ident, _),
+                ..
+            }) if ident.name.as_str() == capture_name
+        )
+    });
+    if !captures_name {
+        return None;
+    }
+
+    let closure_ty = tcx.type_of(closure.def_id).instantiate_identity();
+    match type_as_rtk_lua_type_value(tcx, &closure_ty, &mut FxHashSet::default())? {
+        rtk_lua::TypeValue::Closure(c) => Some(c),
+        _ => None,
+    }
+}
+
+/// Like `function_call_from_expr`, but matching calls to any function made from an item beneath
+/// the module at `module`, wherever the function called is
+pub fn function_call_in_module_from_expr(
+    tcx: TyCtxt<'_>,
+    module: &rtk_lua::Location,
+    expr: &rustc_hir::Expr<'_>,
+) -> Option<rtk_lua::FunctionCall> {
+    let caller = path::def_path_to_rtk_location(tcx, &tcx.def_path(expr.hir_id.owner.to_def_id()));
+    if !caller.is_within(module) {
+        return None;
+    }
+
+    function_call_matching_from_expr(tcx, expr, |_callee| true)
+}
+
+fn function_call_matching_from_expr(
+    tcx: TyCtxt<'_>,
+    expr: &rustc_hir::Expr<'_>,
+    matches: impl FnOnce(&rtk_lua::Location) -> bool,
 ) -> Option<rtk_lua::FunctionCall> {
     let ExprKind::Call(call_expr, args) = expr.kind else {
         return None;
@@ -217,7 +887,7 @@ pub fn function_call_from_expr(
     let def_path = path::def_path_of_expr(tcx, call_expr)?;
     let def_path_loc = path::def_path_to_rtk_location(tcx, &def_path);
 
-    if &def_path_loc != loc {
+    if !matches(&def_path_loc) {
         return None;
     }
 
@@ -230,5 +900,6 @@ pub fn function_call_from_expr(
         location: def_path_loc,
         args,
         in_item_id: expr.hir_id.rtk_item_id(),
+        is_in_test_module: is_cfg_test_for_did(tcx, expr.hir_id.owner.to_def_id()),
     })
 }
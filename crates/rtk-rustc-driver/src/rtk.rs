@@ -1,18 +1,34 @@
-use std::{io::Write, sync::Arc};
+use std::{
+    collections::HashMap,
+    io::Write,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+    },
+    time::Instant,
+};
 
-use rtk_lua::{MethodCallQuery, RtkLua, RtkLuaScriptExecutor};
+use rtk_lua::{
+    DEFAULT_EMIT_PRIORITY, EmitBuffer, MethodCallQuery, QueryEmptyBehavior, RtkLua,
+    RtkLuaScriptExecutor,
+};
+use rustc_data_structures::fx::FxHashSet;
 use rustc_driver::{Callbacks, Compilation};
 use rustc_hir::{
     Expr,
+    def_id::LOCAL_CRATE,
     intravisit::{Visitor, nested_filter::NestedFilter},
 };
-use rustc_middle::ty::TyCtxt;
+use rustc_middle::ty::{Ty, TyCtxt};
+use rustc_span::{BytePos, Span, hygiene::ExpnId};
 
 use crate::queries;
 
 pub struct RtkCallbacks {
     pub lua_script_path: String,
     pub out_file_path: String,
+    /// Whether warnings logged by the script fail the run, as errors would
+    pub deny_warnings: bool,
 }
 
 impl Callbacks for RtkCallbacks {
@@ -21,13 +37,13 @@ impl Callbacks for RtkCallbacks {
         _compiler: &rustc_interface::interface::Compiler,
         tcx: rustc_middle::ty::TyCtxt<'_>,
     ) -> rustc_driver::Compilation {
-        let out_file_handle = match std::fs::OpenOptions::new()
+        let mut out_file_handle = match std::fs::OpenOptions::new()
             .write(true)
             .create(true)
             .truncate(true)
             .open(&self.out_file_path)
         {
-            Ok(handle) => Arc::new(parking_lot::Mutex::new(handle)),
+            Ok(handle) => handle,
             Err(e) => {
                 tcx.dcx().fatal(format!(
                     "failed to open output file '{}': {e}",
@@ -36,13 +52,20 @@ impl Callbacks for RtkCallbacks {
             }
         };
 
+        let emit_buffer = Arc::new(parking_lot::Mutex::new(EmitBuffer::default()));
+        let warning_count = Arc::new(AtomicUsize::new(0));
+
         let lua = RtkLua::new(unsafe {
             std::mem::transmute::<
                 RtkLuaScriptVisitorExecutor<'_>,
                 RtkLuaScriptVisitorExecutor<'static>,
             >(RtkLuaScriptVisitorExecutor {
                 tcx,
-                out_file_handle,
+                emit_buffer: emit_buffer.clone(),
+                query_empty_behavior: Arc::default(),
+                warning_count: warning_count.clone(),
+                trace_enabled: Arc::default(),
+                include_hidden: Arc::default(),
             })
         })
         .unwrap();
@@ -57,11 +80,26 @@ impl Callbacks for RtkCallbacks {
             }
         };
 
-        if let Err(err) = lua.execute(&lua_script) {
+        if let Err(err) = lua.execute_named(&lua_script, &self.lua_script_path) {
             tcx.dcx()
                 .fatal(format!("Lua script execution failed: {err}"));
         }
 
+        let warning_count = warning_count.load(Ordering::Relaxed);
+        if self.deny_warnings && warning_count > 0 {
+            tcx.dcx().err(format!(
+                "the script logged {warning_count} warning(s), which are denied by `--deny-warnings`"
+            ));
+            tcx.dcx().abort_if_errors();
+        }
+
+        // emits are buffered so they can be reordered by priority, so only once the script has
+        // finished can we actually write them out
+        let output = emit_buffer.lock().drain_ordered();
+        if let Err(e) = out_file_handle.write_all(output.as_bytes()) {
+            tcx.dcx().fatal(format!("failed to write to out file: {e}"));
+        }
+
         Compilation::Stop
     }
 }
@@ -75,33 +113,148 @@ impl<'tcx> NestedFilter<'tcx> for VisitorFilter {
     const INTRA: bool = true;
 }
 
+/// Collects the calls to the function at `location` in whatever it walks, be it the whole crate or
+/// a single body
+struct FCVisitor<'tcx> {
+    tcx: TyCtxt<'tcx>,
+    calls: Vec<rtk_lua::FunctionCall>,
+    location: rtk_lua::Location,
+}
+
+impl<'tcx> Visitor<'tcx> for FCVisitor<'tcx> {
+    type NestedFilter = VisitorFilter;
+
+    fn visit_expr(&mut self, ex: &'tcx Expr<'tcx>) {
+        if let Some(fc) = queries::function_call_from_expr(self.tcx, &self.location, ex) {
+            self.calls.push(fc);
+        }
+
+        rustc_hir::intravisit::walk_expr(self, ex);
+    }
+
+    fn maybe_tcx(&mut self) -> Self::MaybeTyCtxt {
+        self.tcx
+    }
+}
+
 #[derive(Clone)]
 struct RtkLuaScriptVisitorExecutor<'tcx> {
     tcx: TyCtxt<'tcx>,
-    out_file_handle: Arc<parking_lot::Mutex<std::fs::File>>,
+    emit_buffer: Arc<parking_lot::Mutex<EmitBuffer>>,
+    query_empty_behavior: Arc<parking_lot::Mutex<QueryEmptyBehavior>>,
+    /// How many times the script has called `log_warn`, so `--deny-warnings` can fail the run
+    warning_count: Arc<AtomicUsize>,
+    /// Whether the script asked for queries to be traced with `rtk.trace_enable()`
+    trace_enabled: Arc<AtomicBool>,
+    /// Whether the script asked for `#[doc(hidden)]` and non-public items to be queried too with
+    /// `rtk.set_include_hidden(true)`
+    include_hidden: Arc<AtomicBool>,
+}
+
+/// A query being timed because the script enabled tracing
+struct QueryTrace {
+    query_name: &'static str,
+    params: String,
+    start: Instant,
+}
+
+impl QueryTrace {
+    fn finish(self, result_count: usize) {
+        log::debug!(
+            "{}({}) returned {result_count} result(s) in {:?}",
+            self.query_name,
+            self.params,
+            self.start.elapsed()
+        );
+    }
 }
 
 unsafe impl Send for RtkLuaScriptVisitorExecutor<'_> {}
 unsafe impl Sync for RtkLuaScriptVisitorExecutor<'_> {}
 
+impl RtkLuaScriptVisitorExecutor<'static> {
+    /// Hands back `results` untouched, reporting the query first if they are empty
+    fn reported_if_empty<T>(
+        &self,
+        query_name: &str,
+        query: &rtk_lua::Location,
+        results: Vec<T>,
+    ) -> Vec<T> {
+        if results.is_empty() {
+            self.report_empty_query(query_name, query);
+        }
+
+        results
+    }
+
+    /// Starts timing the `query_name` query if the script enabled tracing. `params` is only
+    /// formatted when it is
+    fn start_trace(
+        &self,
+        query_name: &'static str,
+        params: impl FnOnce() -> String,
+    ) -> Option<QueryTrace> {
+        self.trace_enabled
+            .load(Ordering::Relaxed)
+            .then(|| QueryTrace {
+                query_name,
+                params: params(),
+                start: Instant::now(),
+            })
+    }
+
+    /// Hands back `results` untouched, logging them first if the query was traced
+    fn traced<T>(&self, trace: Option<QueryTrace>, results: Vec<T>) -> Vec<T> {
+        if let Some(trace) = trace {
+            trace.finish(results.len());
+        }
+
+        results
+    }
+}
+
 impl RtkLuaScriptExecutor for RtkLuaScriptVisitorExecutor<'static> {
     fn intake_version(&self, _version: rtk_lua::RtkRustcDriverVersion) {
         // TODO: assert version matches self in here
     }
 
+    fn primary_crate_name(&self) -> String {
+        self.tcx.crate_name(LOCAL_CRATE).to_string()
+    }
+
+    fn primary_crate_version(&self) -> Option<String> {
+        // cargo sets this for every rustc invocation, so it is read at runtime rather than with
+        // `env!`, which would give the driver's own version
+        std::env::var("CARGO_PKG_VERSION").ok()
+    }
+
     fn query_method_calls(&self, query: MethodCallQuery) -> Vec<rtk_lua::MethodCall> {
+        self.query_method_calls_batch(vec![query])
+            .pop()
+            .unwrap_or_default()
+    }
+
+    fn query_method_calls_batch(
+        &self,
+        queries: Vec<MethodCallQuery>,
+    ) -> Vec<Vec<rtk_lua::MethodCall>> {
+        let trace = self.start_trace("query_method_calls", || format!("{queries:?}"));
+
         struct MCVisitor<'tcx> {
             tcx: TyCtxt<'tcx>,
-            calls: Vec<rtk_lua::MethodCall>,
-            query: MethodCallQuery,
+            /// Each call along with its `queries::call_site_order`
+            calls: Vec<Vec<((String, BytePos), rtk_lua::MethodCall)>>,
+            queries: Vec<MethodCallQuery>,
         }
 
         impl<'tcx> Visitor<'tcx> for MCVisitor<'tcx> {
             type NestedFilter = VisitorFilter;
 
             fn visit_expr(&mut self, ex: &'tcx Expr<'tcx>) {
-                if let Some(mc) = queries::method_call_from_expr(self.tcx, &self.query, ex) {
-                    self.calls.push(mc);
+                for (query, calls) in self.queries.iter().zip(self.calls.iter_mut()) {
+                    if let Some(mc) = queries::method_call_from_expr(self.tcx, query, ex) {
+                        calls.push((queries::call_site_order(self.tcx, ex.span), mc));
+                    }
                 }
 
                 rustc_hir::intravisit::walk_expr(self, ex)
@@ -114,16 +267,38 @@ impl RtkLuaScriptExecutor for RtkLuaScriptVisitorExecutor<'static> {
 
         let mut mc_visitor = MCVisitor {
             tcx: self.tcx,
-            calls: Vec::new(),
-            query,
+            calls: vec![Vec::new(); queries.len()],
+            queries,
         };
 
         self.tcx.hir_walk_toplevel_module(&mut mc_visitor);
 
-        mc_visitor.calls
+        let calls: Vec<_> = mc_visitor
+            .queries
+            .iter()
+            .zip(mc_visitor.calls)
+            .map(|(query, mut calls)| {
+                // the walk visits modules in declaration order, not the order of their files, and
+                // macros can expand their arguments in any order, so calls are sorted into source
+                // order. The sort is stable, so calls sharing a start, like those expanded from
+                // the same macro, keep their walk order
+                calls.sort_by(|(a_site, _), (b_site, _)| a_site.cmp(b_site));
+                let calls = calls.into_iter().map(|(_, mc)| mc).collect();
+
+                self.reported_if_empty("query_method_calls", &query.location, calls)
+            })
+            .collect();
+
+        if let Some(trace) = trace {
+            trace.finish(calls.iter().map(Vec::len).sum());
+        }
+
+        calls
     }
 
     fn query_trait_impls(&self, query: rtk_lua::Location) -> Vec<rtk_lua::TraitImpl> {
+        let trace = self.start_trace("query_trait_impls", || query.to_string());
+
         struct TIVisitor<'tcx> {
             tcx: TyCtxt<'tcx>,
             traits: Vec<rtk_lua::TraitImpl>,
@@ -154,13 +329,118 @@ impl RtkLuaScriptExecutor for RtkLuaScriptVisitorExecutor<'static> {
 
         self.tcx.hir_walk_toplevel_module(&mut ti_visitor);
 
-        ti_visitor.traits
+        let impls =
+            self.reported_if_empty("query_trait_impls", &ti_visitor.location, ti_visitor.traits);
+        self.traced(trace, impls)
+    }
+
+    fn query_all_trait_impls(&self, query: rtk_lua::Location) -> Vec<rtk_lua::TraitImpl> {
+        let trace = self.start_trace("query_all_trait_impls", || query.to_string());
+
+        let impls = queries::all_trait_impls_for_location(self.tcx, &query);
+        let impls = self.reported_if_empty("query_all_trait_impls", &query, impls);
+        self.traced(trace, impls)
+    }
+
+    fn query_impls_for_type(&self, query: rtk_lua::Location) -> Vec<rtk_lua::Location> {
+        let trace = self.start_trace("query_impls_for_type", || query.to_string());
+
+        struct IFTVisitor<'tcx> {
+            tcx: TyCtxt<'tcx>,
+            traits: Vec<rtk_lua::Location>,
+            location: rtk_lua::Location,
+        }
+
+        impl<'tcx> Visitor<'tcx> for IFTVisitor<'tcx> {
+            type NestedFilter = VisitorFilter;
+
+            fn visit_item(&mut self, i: &'tcx rustc_hir::Item<'tcx>) -> Self::Result {
+                if let Some(t) = queries::impl_trait_for_type_from_item(self.tcx, &self.location, i)
+                {
+                    self.traits.push(t);
+                }
+
+                rustc_hir::intravisit::walk_item(self, i);
+            }
+
+            fn maybe_tcx(&mut self) -> Self::MaybeTyCtxt {
+                self.tcx
+            }
+        }
+
+        let mut ift_visitor = IFTVisitor {
+            tcx: self.tcx,
+            traits: Vec::new(),
+            location: query,
+        };
+
+        self.tcx.hir_walk_toplevel_module(&mut ift_visitor);
+
+        let traits = self.reported_if_empty(
+            "query_impls_for_type",
+            &ift_visitor.location,
+            ift_visitor.traits,
+        );
+        self.traced(trace, traits)
+    }
+
+    fn query_trait_defs(&self, query: rtk_lua::Location) -> Vec<rtk_lua::TraitDef> {
+        let trace = self.start_trace("query_trait_defs", || query.to_string());
+
+        struct TDVisitor<'tcx> {
+            tcx: TyCtxt<'tcx>,
+            trait_defs: Vec<rtk_lua::TraitDef>,
+            location: rtk_lua::Location,
+        }
+
+        impl<'tcx> Visitor<'tcx> for TDVisitor<'tcx> {
+            type NestedFilter = VisitorFilter;
+
+            fn visit_item(&mut self, i: &'tcx rustc_hir::Item<'tcx>) -> Self::Result {
+                if let Some(td) = queries::trait_def_from_item(self.tcx, &self.location, i) {
+                    self.trait_defs.push(td);
+                }
+
+                rustc_hir::intravisit::walk_item(self, i);
+            }
+
+            fn maybe_tcx(&mut self) -> Self::MaybeTyCtxt {
+                self.tcx
+            }
+        }
+
+        let mut td_visitor = TDVisitor {
+            tcx: self.tcx,
+            trait_defs: Vec::new(),
+            location: query,
+        };
+
+        self.tcx.hir_walk_toplevel_module(&mut td_visitor);
+
+        let trait_defs = self.reported_if_empty(
+            "query_trait_defs",
+            &td_visitor.location,
+            td_visitor.trait_defs,
+        );
+        self.traced(trace, trait_defs)
     }
 
     fn query_functions(&self, query: rtk_lua::Location) -> Vec<rtk_lua::FunctionTypeValue> {
+        let trace = self.start_trace("query_functions", || query.to_string());
+
+        if queries::is_extern_location(self.tcx, &query) {
+            let functions = queries::extern_function_for_location(self.tcx, &query)
+                .into_iter()
+                .collect();
+            let functions = self.reported_if_empty("query_functions", &query, functions);
+            return self.traced(trace, functions);
+        }
+
         struct FVisitor<'tcx> {
             tcx: TyCtxt<'tcx>,
-            functions: Vec<rtk_lua::FunctionTypeValue>,
+            include_hidden: bool,
+            /// Each function along with the type it is defined on, if it is in an impl block
+            functions: Vec<(Option<Ty<'tcx>>, rtk_lua::FunctionTypeValue)>,
             location: rtk_lua::Location,
         }
 
@@ -168,13 +448,28 @@ impl RtkLuaScriptExecutor for RtkLuaScriptVisitorExecutor<'static> {
             type NestedFilter = VisitorFilter;
 
             fn visit_item(&mut self, i: &'tcx rustc_hir::Item<'tcx>) -> Self::Result {
-                if let Some(ti) = queries::function_from_item(self.tcx, &self.location, i) {
-                    self.functions.push(ti);
+                if self.include_hidden || !queries::is_hidden_item(self.tcx, i) {
+                    if let Some(ti) = queries::function_from_item(self.tcx, &self.location, i) {
+                        self.functions.push((None, ti));
+                    }
                 }
 
                 rustc_hir::intravisit::walk_item(self, i);
             }
 
+            fn visit_impl_item(&mut self, ii: &'tcx rustc_hir::ImplItem<'tcx>) -> Self::Result {
+                let did = ii.owner_id.def_id.to_def_id();
+                if self.include_hidden || !queries::is_hidden_did(self.tcx, did) {
+                    if let Some((self_ty, f)) =
+                        queries::function_from_impl_item(self.tcx, &self.location, ii)
+                    {
+                        self.functions.push((Some(self_ty), f));
+                    }
+                }
+
+                rustc_hir::intravisit::walk_impl_item(self, ii);
+            }
+
             fn maybe_tcx(&mut self) -> Self::MaybeTyCtxt {
                 self.tcx
             }
@@ -182,30 +477,162 @@ impl RtkLuaScriptExecutor for RtkLuaScriptVisitorExecutor<'static> {
 
         let mut f_visitor = FVisitor {
             tcx: self.tcx,
+            include_hidden: self.include_hidden.load(Ordering::Relaxed),
             functions: Vec::new(),
             location: query,
         };
 
         self.tcx.hir_walk_toplevel_module(&mut f_visitor);
 
-        f_visitor.functions
+        // with an `impl_block_number` only one block can match, so nothing is grouped
+        let functions = rtk_lua::FunctionTypeValue::group_overloads(f_visitor.functions);
+        let functions = self.reported_if_empty("query_functions", &f_visitor.location, functions);
+        self.traced(trace, functions)
+    }
+
+    fn query_functions_in_module(
+        &self,
+        module: rtk_lua::Location,
+    ) -> Vec<rtk_lua::FunctionTypeValue> {
+        let trace = self.start_trace("query_functions_in_module", || module.to_string());
+
+        struct FIMVisitor<'tcx> {
+            tcx: TyCtxt<'tcx>,
+            include_hidden: bool,
+            functions: Vec<rtk_lua::FunctionTypeValue>,
+            module: rtk_lua::Location,
+        }
+
+        impl<'tcx> Visitor<'tcx> for FIMVisitor<'tcx> {
+            type NestedFilter = VisitorFilter;
+
+            fn visit_item(&mut self, i: &'tcx rustc_hir::Item<'tcx>) -> Self::Result {
+                if self.include_hidden || !queries::is_hidden_item(self.tcx, i) {
+                    if let Some(f) =
+                        queries::function_in_module_from_item(self.tcx, &self.module, i)
+                    {
+                        self.functions.push(f);
+                    }
+                }
+
+                rustc_hir::intravisit::walk_item(self, i);
+            }
+
+            fn maybe_tcx(&mut self) -> Self::MaybeTyCtxt {
+                self.tcx
+            }
+        }
+
+        let mut fim_visitor = FIMVisitor {
+            tcx: self.tcx,
+            include_hidden: self.include_hidden.load(Ordering::Relaxed),
+            functions: Vec::new(),
+            module,
+        };
+
+        self.tcx.hir_walk_toplevel_module(&mut fim_visitor);
+
+        let functions = self.reported_if_empty(
+            "query_functions_in_module",
+            &fim_visitor.module,
+            fim_visitor.functions,
+        );
+        self.traced(trace, functions)
     }
 
     fn query_function_calls(&self, query: rtk_lua::Location) -> Vec<rtk_lua::FunctionCall> {
-        struct FCVisitor<'tcx> {
+        let trace = self.start_trace("query_function_calls", || query.to_string());
+
+        let mut fc_visitor = FCVisitor {
+            tcx: self.tcx,
+            calls: Vec::new(),
+            location: query,
+        };
+
+        self.tcx.hir_walk_toplevel_module(&mut fc_visitor);
+
+        let calls = self.reported_if_empty(
+            "query_function_calls",
+            &fc_visitor.location,
+            fc_visitor.calls,
+        );
+        self.traced(trace, calls)
+    }
+
+    fn query_struct_literals(&self, query: rtk_lua::Location) -> Vec<rtk_lua::StructLiteralSite> {
+        let trace = self.start_trace("query_struct_literals", || query.to_string());
+
+        struct SLVisitor<'tcx> {
             tcx: TyCtxt<'tcx>,
-            calls: Vec<rtk_lua::FunctionCall>,
+            literals: Vec<rtk_lua::StructLiteralSite>,
             location: rtk_lua::Location,
         }
 
-        impl<'tcx> Visitor<'tcx> for FCVisitor<'tcx> {
+        impl<'tcx> Visitor<'tcx> for SLVisitor<'tcx> {
             type NestedFilter = VisitorFilter;
 
             fn visit_expr(&mut self, ex: &'tcx Expr<'tcx>) {
-                if let Some(fc) = queries::function_call_from_expr(self.tcx, &self.location, ex) {
-                    self.calls.push(fc);
+                if let Some(sl) = queries::struct_literal_from_expr(self.tcx, &self.location, ex) {
+                    self.literals.push(sl);
+                }
+
+                rustc_hir::intravisit::walk_expr(self, ex);
+            }
+
+            fn maybe_tcx(&mut self) -> Self::MaybeTyCtxt {
+                self.tcx
+            }
+        }
+
+        let mut sl_visitor = SLVisitor {
+            tcx: self.tcx,
+            literals: Vec::new(),
+            location: query,
+        };
+
+        self.tcx.hir_walk_toplevel_module(&mut sl_visitor);
+
+        let literals = self.reported_if_empty(
+            "query_struct_literals",
+            &sl_visitor.location,
+            sl_visitor.literals,
+        );
+        self.traced(trace, literals)
+    }
+
+    fn query_macro_calls(&self, name: String) -> Vec<rtk_lua::MacroCall> {
+        let trace = self.start_trace("query_macro_calls", || name.clone());
+
+        struct MacVisitor<'tcx> {
+            tcx: TyCtxt<'tcx>,
+            calls: Vec<rtk_lua::MacroCall>,
+            // one invocation expands to many nodes, so each is only recorded the first time
+            seen: FxHashSet<ExpnId>,
+            name: String,
+        }
+
+        impl MacVisitor<'_> {
+            fn record(&mut self, span: Span, hir_id: rustc_hir::HirId) {
+                if let Some((expn, mc)) =
+                    queries::macro_call_from_span(self.tcx, &self.name, span, hir_id)
+                {
+                    if self.seen.insert(expn) {
+                        self.calls.push(mc);
+                    }
                 }
+            }
+        }
 
+        impl<'tcx> Visitor<'tcx> for MacVisitor<'tcx> {
+            type NestedFilter = VisitorFilter;
+
+            fn visit_item(&mut self, i: &'tcx rustc_hir::Item<'tcx>) -> Self::Result {
+                self.record(i.span, i.hir_id());
+                rustc_hir::intravisit::walk_item(self, i);
+            }
+
+            fn visit_expr(&mut self, ex: &'tcx Expr<'tcx>) {
+                self.record(ex.span, ex.hir_id);
                 rustc_hir::intravisit::walk_expr(self, ex);
             }
 
@@ -214,15 +641,366 @@ impl RtkLuaScriptExecutor for RtkLuaScriptVisitorExecutor<'static> {
             }
         }
 
+        let mut mac_visitor = MacVisitor {
+            tcx: self.tcx,
+            calls: Vec::new(),
+            seen: FxHashSet::default(),
+            name,
+        };
+
+        self.tcx.hir_walk_toplevel_module(&mut mac_visitor);
+
+        self.traced(trace, mac_visitor.calls)
+    }
+
+    fn query_serde_fields(&self, query: rtk_lua::Location) -> Vec<rtk_lua::SerdeField> {
+        let trace = self.start_trace("query_serde_fields", || query.to_string());
+
+        let fields = queries::serde_fields_for_location(self.tcx, &query);
+        let fields = self.reported_if_empty("query_serde_fields", &query, fields);
+        self.traced(trace, fields)
+    }
+
+    fn query_constants_by_type(
+        &self,
+        type_filter: rtk_lua::TypeValue,
+    ) -> Vec<rtk_lua::ConstantValue> {
+        let trace = self.start_trace("query_constants_by_type", || format!("{type_filter:?}"));
+
+        struct ConstVisitor<'tcx> {
+            tcx: TyCtxt<'tcx>,
+            include_hidden: bool,
+            constants: Vec<rtk_lua::ConstantValue>,
+            type_filter: rtk_lua::TypeValue,
+        }
+
+        impl<'tcx> Visitor<'tcx> for ConstVisitor<'tcx> {
+            type NestedFilter = VisitorFilter;
+
+            fn visit_item(&mut self, i: &'tcx rustc_hir::Item<'tcx>) -> Self::Result {
+                if self.include_hidden || !queries::is_hidden_item(self.tcx, i) {
+                    if let Some(c) = queries::constant_from_item(self.tcx, &self.type_filter, i) {
+                        self.constants.push(c);
+                    }
+                }
+
+                rustc_hir::intravisit::walk_item(self, i);
+            }
+
+            fn maybe_tcx(&mut self) -> Self::MaybeTyCtxt {
+                self.tcx
+            }
+        }
+
+        let mut const_visitor = ConstVisitor {
+            tcx: self.tcx,
+            include_hidden: self.include_hidden.load(Ordering::Relaxed),
+            constants: Vec::new(),
+            type_filter,
+        };
+
+        self.tcx.hir_walk_toplevel_module(&mut const_visitor);
+
+        // a crate without constants of a type isn't a mistyped location, so this isn't reported
+        // when empty
+        self.traced(trace, const_visitor.constants)
+    }
+
+    fn query_function_calls_in_function(
+        &self,
+        outer_location: rtk_lua::Location,
+        call_location: rtk_lua::Location,
+    ) -> Vec<rtk_lua::FunctionCall> {
+        let trace = self.start_trace("query_function_calls_in_function", || {
+            format!("{outer_location}, {call_location}")
+        });
+
+        let Some(body_id) = queries::body_id_for_location(self.tcx, &outer_location) else {
+            self.log_warn(format!(
+                "no function body found at `{outer_location}` to query calls within"
+            ));
+            return self.traced(trace, vec![]);
+        };
+
         let mut fc_visitor = FCVisitor {
             tcx: self.tcx,
             calls: Vec::new(),
+            location: call_location,
+        };
+
+        // only the one body is walked, rather than the whole crate
+        fc_visitor.visit_body(self.tcx.hir_body(body_id));
+
+        let calls = self.reported_if_empty(
+            "query_function_calls_in_function",
+            &fc_visitor.location,
+            fc_visitor.calls,
+        );
+        self.traced(trace, calls)
+    }
+
+    fn query_closures_with_capture(
+        &self,
+        within_location: rtk_lua::Location,
+        capture_name: String,
+    ) -> Vec<rtk_lua::ClosureTypeValue> {
+        let trace = self.start_trace("query_closures_with_capture", || {
+            format!("{within_location}, {capture_name}")
+        });
+
+        struct CWCVisitor<'tcx> {
+            tcx: TyCtxt<'tcx>,
+            closures: Vec<rtk_lua::ClosureTypeValue>,
+            capture_name: String,
+        }
+
+        impl<'tcx> Visitor<'tcx> for CWCVisitor<'tcx> {
+            type NestedFilter = VisitorFilter;
+
+            fn visit_expr(&mut self, ex: &'tcx Expr<'tcx>) {
+                if let Some(c) =
+                    queries::closure_with_capture_from_expr(self.tcx, &self.capture_name, ex)
+                {
+                    self.closures.push(c);
+                }
+
+                rustc_hir::intravisit::walk_expr(self, ex);
+            }
+
+            fn maybe_tcx(&mut self) -> Self::MaybeTyCtxt {
+                self.tcx
+            }
+        }
+
+        let Some(body_id) = queries::body_id_for_location(self.tcx, &within_location) else {
+            self.log_warn(format!(
+                "no function body found at `{within_location}` to query closures within"
+            ));
+            return self.traced(trace, vec![]);
+        };
+
+        let mut cwc_visitor = CWCVisitor {
+            tcx: self.tcx,
+            closures: Vec::new(),
+            capture_name,
+        };
+
+        // closure bodies are nested in the function's, so walking it reaches closures in closures
+        cwc_visitor.visit_body(self.tcx.hir_body(body_id));
+
+        let closures = self.reported_if_empty(
+            "query_closures_with_capture",
+            &within_location,
+            cwc_visitor.closures,
+        );
+        self.traced(trace, closures)
+    }
+
+    fn query_function_calls_grouped(
+        &self,
+        module: rtk_lua::Location,
+    ) -> HashMap<rtk_lua::Location, Vec<rtk_lua::FunctionCall>> {
+        let trace = self.start_trace("query_function_calls_grouped", || module.to_string());
+
+        struct FCGVisitor<'tcx> {
+            tcx: TyCtxt<'tcx>,
+            /// Each call along with its `queries::call_site_order`, grouped by the function called
+            calls: HashMap<rtk_lua::Location, Vec<((String, BytePos), rtk_lua::FunctionCall)>>,
+            module: rtk_lua::Location,
+        }
+
+        impl<'tcx> Visitor<'tcx> for FCGVisitor<'tcx> {
+            type NestedFilter = VisitorFilter;
+
+            fn visit_expr(&mut self, ex: &'tcx Expr<'tcx>) {
+                if let Some(fc) =
+                    queries::function_call_in_module_from_expr(self.tcx, &self.module, ex)
+                {
+                    let site = queries::call_site_order(self.tcx, ex.span);
+                    self.calls
+                        .entry(fc.location.clone())
+                        .or_default()
+                        .push((site, fc));
+                }
+
+                rustc_hir::intravisit::walk_expr(self, ex);
+            }
+
+            fn maybe_tcx(&mut self) -> Self::MaybeTyCtxt {
+                self.tcx
+            }
+        }
+
+        let mut fcg_visitor = FCGVisitor {
+            tcx: self.tcx,
+            calls: HashMap::new(),
+            module,
+        };
+
+        self.tcx.hir_walk_toplevel_module(&mut fcg_visitor);
+
+        // as with method calls, each group is put in source order rather than HIR walk order
+        let calls: HashMap<_, _> = fcg_visitor
+            .calls
+            .into_iter()
+            .map(|(callee, mut calls)| {
+                calls.sort_by(|(a_site, _), (b_site, _)| a_site.cmp(b_site));
+                (
+                    callee,
+                    calls.into_iter().map(|(_, fc)| fc).collect::<Vec<_>>(),
+                )
+            })
+            .collect();
+
+        if calls.is_empty() {
+            self.report_empty_query("query_function_calls_grouped", &fcg_visitor.module);
+        }
+
+        if let Some(trace) = trace {
+            trace.finish(calls.values().map(Vec::len).sum());
+        }
+
+        calls
+    }
+
+    fn query_modules(&self) -> Vec<rtk_lua::Location> {
+        let trace = self.start_trace("query_modules", String::new);
+
+        struct ModVisitor<'tcx> {
+            tcx: TyCtxt<'tcx>,
+            include_hidden: bool,
+            modules: Vec<rtk_lua::Location>,
+        }
+
+        impl<'tcx> Visitor<'tcx> for ModVisitor<'tcx> {
+            type NestedFilter = VisitorFilter;
+
+            fn visit_item(&mut self, i: &'tcx rustc_hir::Item<'tcx>) -> Self::Result {
+                if self.include_hidden || !queries::is_hidden_item(self.tcx, i) {
+                    if let Some(m) = queries::module_from_item(self.tcx, i) {
+                        self.modules.push(m);
+                    }
+                }
+
+                rustc_hir::intravisit::walk_item(self, i);
+            }
+
+            fn maybe_tcx(&mut self) -> Self::MaybeTyCtxt {
+                self.tcx
+            }
+        }
+
+        // the crate root isn't an item of its own, so it won't be visited
+        let crate_root = rtk_lua::Location {
+            crate_name: self.tcx.crate_name(LOCAL_CRATE).to_string(),
+            path: vec![],
+            impl_block_number: None,
+            is_local: true,
+        };
+
+        let mut mod_visitor = ModVisitor {
+            tcx: self.tcx,
+            include_hidden: self.include_hidden.load(Ordering::Relaxed),
+            modules: vec![crate_root],
+        };
+
+        self.tcx.hir_walk_toplevel_module(&mut mod_visitor);
+
+        self.traced(trace, mod_visitor.modules)
+    }
+
+    fn count_functions(&self, query: rtk_lua::Location) -> usize {
+        if queries::is_extern_location(self.tcx, &query) {
+            return self.query_functions(query).len();
+        }
+
+        struct FCountVisitor<'tcx> {
+            tcx: TyCtxt<'tcx>,
+            include_hidden: bool,
+            count: usize,
+            /// The types seen with a matching function, as their overloads are counted only once
+            self_tys: Vec<Ty<'tcx>>,
+            location: rtk_lua::Location,
+        }
+
+        impl<'tcx> Visitor<'tcx> for FCountVisitor<'tcx> {
+            type NestedFilter = VisitorFilter;
+
+            fn visit_item(&mut self, i: &'tcx rustc_hir::Item<'tcx>) -> Self::Result {
+                if (self.include_hidden || !queries::is_hidden_item(self.tcx, i))
+                    && queries::function_item_matches(self.tcx, &self.location, i)
+                {
+                    self.count += 1;
+                }
+
+                rustc_hir::intravisit::walk_item(self, i);
+            }
+
+            fn visit_impl_item(&mut self, ii: &'tcx rustc_hir::ImplItem<'tcx>) -> Self::Result {
+                let did = ii.owner_id.def_id.to_def_id();
+                if self.include_hidden || !queries::is_hidden_did(self.tcx, did) {
+                    let self_ty = queries::inherent_impl_fn_self_ty(self.tcx, &self.location, ii);
+                    if let Some(self_ty) =
+                        self_ty.filter(|self_ty| !self.self_tys.contains(self_ty))
+                    {
+                        self.self_tys.push(self_ty);
+                        self.count += 1;
+                    }
+                }
+
+                rustc_hir::intravisit::walk_impl_item(self, ii);
+            }
+
+            fn maybe_tcx(&mut self) -> Self::MaybeTyCtxt {
+                self.tcx
+            }
+        }
+
+        let mut f_count_visitor = FCountVisitor {
+            tcx: self.tcx,
+            include_hidden: self.include_hidden.load(Ordering::Relaxed),
+            count: 0,
+            self_tys: Vec::new(),
             location: query,
         };
 
-        self.tcx.hir_walk_toplevel_module(&mut fc_visitor);
+        self.tcx.hir_walk_toplevel_module(&mut f_count_visitor);
 
-        fc_visitor.calls
+        f_count_visitor.count
+    }
+
+    fn count_method_calls(&self, query: MethodCallQuery) -> usize {
+        struct MCCountVisitor<'tcx> {
+            tcx: TyCtxt<'tcx>,
+            count: usize,
+            query: MethodCallQuery,
+        }
+
+        impl<'tcx> Visitor<'tcx> for MCCountVisitor<'tcx> {
+            type NestedFilter = VisitorFilter;
+
+            fn visit_expr(&mut self, ex: &'tcx Expr<'tcx>) {
+                if queries::method_call_matches(self.tcx, &self.query, ex) {
+                    self.count += 1;
+                }
+
+                rustc_hir::intravisit::walk_expr(self, ex)
+            }
+
+            fn maybe_tcx(&mut self) -> Self::MaybeTyCtxt {
+                self.tcx
+            }
+        }
+
+        let mut mc_count_visitor = MCCountVisitor {
+            tcx: self.tcx,
+            count: 0,
+            query,
+        };
+
+        self.tcx.hir_walk_toplevel_module(&mut mc_count_visitor);
+
+        mc_count_visitor.count
     }
 
     fn log_note(&self, msg: String) {
@@ -230,6 +1008,7 @@ impl RtkLuaScriptExecutor for RtkLuaScriptVisitorExecutor<'static> {
     }
 
     fn log_warn(&self, msg: String) {
+        self.warning_count.fetch_add(1, Ordering::Relaxed);
         self.tcx.dcx().warn(msg);
     }
 
@@ -241,16 +1020,38 @@ impl RtkLuaScriptExecutor for RtkLuaScriptVisitorExecutor<'static> {
         self.tcx.dcx().fatal(msg);
     }
 
+    fn abort_if_errors(&self) {
+        self.tcx.dcx().abort_if_errors();
+    }
+
+    fn query_empty_behavior(&self) -> QueryEmptyBehavior {
+        *self.query_empty_behavior.lock()
+    }
+
+    fn intake_query_empty_behavior(&self, behavior: QueryEmptyBehavior) {
+        *self.query_empty_behavior.lock() = behavior;
+    }
+
+    fn intake_trace_enabled(&self, enabled: bool) {
+        self.trace_enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    fn intake_include_hidden(&self, include_hidden: bool) {
+        self.include_hidden.store(include_hidden, Ordering::Relaxed);
+    }
+
     fn emit(&self, text: String) {
-        let mut handle = self.out_file_handle.lock();
-        match handle.write_all(text.as_bytes()) {
-            Ok(_) => {}
-            Err(e) => {
-                self.tcx
-                    .dcx()
-                    .fatal(format!("failed to write to out file: {e}",));
-            }
-        }
+        self.emit_with_priority(DEFAULT_EMIT_PRIORITY, text);
+    }
+
+    fn emit_with_priority(&self, priority: i64, text: String) {
+        self.emit_buffer.lock().push(priority, text);
+    }
+
+    fn emit_unique(&self, text: String) {
+        self.emit_buffer
+            .lock()
+            .push_unique(DEFAULT_EMIT_PRIORITY, text);
     }
 }
 
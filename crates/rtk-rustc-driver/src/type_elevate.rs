@@ -1,10 +1,13 @@
-use rustc_ast::tokenstream::TokenTree;
+use std::sync::OnceLock;
+
+use rustc_ast::{token::Delimiter, tokenstream::TokenTree};
 use rustc_data_structures::fx::FxHashSet;
-use rustc_hir::def_id::DefId;
+use rustc_hir::{def::DefKind, def_id::DefId};
 use rustc_middle::{
     query::Key,
-    ty::{Ty, TyCtxt, TyKind},
+    ty::{BoundVariableKind, GenericParamDefKind, Ty, TyCtxt, TyKind, TypeVisitableExt, TypingEnv},
 };
+use rustc_span::sym;
 use rustc_type_ir::{AliasTyKind, FloatTy, IntTy, UintTy};
 
 use crate::path;
@@ -17,7 +20,7 @@ pub fn hir_type_as_rtk_lua_type_value<'tcx>(
 ) -> Option<rtk_lua::TypeValue> {
     let ty = tcx.type_of(ty.hir_id.owner);
     let ty = if is_async {
-        peel_future_output(tcx, &ty.skip_binder())
+        peel_future_output(tcx, &ty.skip_binder())?
     } else {
         ty.skip_binder()
     };
@@ -46,14 +49,23 @@ pub fn type_as_rtk_lua_type_value<'tcx>(
         TyKind::Uint(UintTy::U128) => Some(rtk_lua::TypeValue::U128),
         TyKind::Uint(UintTy::Usize) => Some(rtk_lua::TypeValue::Usize),
 
+        TyKind::Float(FloatTy::F16) => Some(rtk_lua::TypeValue::F16),
         TyKind::Float(FloatTy::F32) => Some(rtk_lua::TypeValue::F32),
         TyKind::Float(FloatTy::F64) => Some(rtk_lua::TypeValue::F64),
+        TyKind::Float(FloatTy::F128) => Some(rtk_lua::TypeValue::F128),
 
         // if we have a reference, we just peel the reference back and then recurse on ourselves.
         // probably will be worth adding a mode for detecting references, though, but for now i
         // can't think of a great reason or need for this
         TyKind::Ref(_, ty, _) => type_as_rtk_lua_type_value(tcx, ty, visited),
 
+        // unlike references, raw pointers are kept, since they are mostly seen in ffi types where
+        // the pointer itself is what has to be generated
+        TyKind::RawPtr(ty, mutability) => Some(rtk_lua::TypeValue::RawPointer {
+            mutable: mutability.is_mut(),
+            inner: Box::new(type_as_rtk_lua_type_value(tcx, ty, visited)?),
+        }),
+
         TyKind::Tuple(tys) => Some(rtk_lua::TypeValue::Tuple(
             tys.iter()
                 .filter_map(|ty| type_as_rtk_lua_type_value(tcx, &ty, visited))
@@ -95,18 +107,16 @@ pub fn type_as_rtk_lua_type_value<'tcx>(
 
             let is_async = tcx.asyncness(fn_def_id).is_async();
 
-            let o = if is_async {
-                peel_future_output(tcx, &o.skip_binder())
-            } else {
-                o.skip_binder()
-            };
+            let o = awaited_output(tcx, o.skip_binder(), is_async)?;
 
             let arg_names = tcx.fn_arg_names(fn_def_id);
+            let location = path::def_path_to_rtk_location(tcx, &tcx.def_path(*fn_def_id));
 
             Some(rtk_lua::TypeValue::Function(rtk_lua::FunctionTypeValue {
                 is_async,
                 args_struct: rtk_lua::StructTypeValue {
-                    location: path::def_path_to_rtk_location(tcx, &tcx.def_path(*fn_def_id)),
+                    location: location.clone(),
+                    type_hash: path::type_hash_of_location(&location),
                     fields: i
                         .iter()
                         .enumerate()
@@ -116,35 +126,65 @@ pub fn type_as_rtk_lua_type_value<'tcx>(
                                 .map(|name| rtk_lua::Either::Right(name.to_string()))
                                 .unwrap_or_else(|| rtk_lua::Either::Left(i));
 
+                            let attributes = value
+                                .skip_binder()
+                                .key_as_def_id()
+                                .map(|did| attributes_for_did(tcx, did))
+                                .unwrap_or_default();
+                            let value = impl_trait_arg(tcx, *fn_def_id, value.skip_binder())
+                                .or_else(|| {
+                                    type_as_rtk_lua_type_value(tcx, value.skip_binder(), visited)
+                                })?;
+
                             Some(rtk_lua::StructTypeValueField {
                                 name,
                                 // function args can't have doc comments or else clippy yells at
                                 // you, so its not even worth checking!
                                 doc_comment: None,
-                                value: type_as_rtk_lua_type_value(
-                                    tcx,
-                                    value.skip_binder(),
-                                    visited,
-                                )?,
-                                attributes: value
-                                    .skip_binder()
-                                    .key_as_def_id()
-                                    .map(|did| attributes_for_did(tcx, did))
-                                    .unwrap_or_default(),
+                                is_optional: rtk_lua::StructTypeValueField::is_optional_for(
+                                    &value,
+                                    &attributes,
+                                ),
+                                // args are never serialized as fields, so serde can't touch them
+                                has_serde_skip: false,
+                                serde_rename: None,
+                                value,
+                                attributes,
                             })
                         })
                         .collect(),
                     attributes: attributes_for_did(tcx, *fn_def_id),
                     doc_comment: doc_comment_for_did(tcx, *fn_def_id),
+                    kind: rtk_lua::StructKind::Named,
+                    derives: vec![],
+                    is_non_exhaustive: false,
+                    newtype_inner: None,
+                    has_custom_serialize: false,
+                    has_custom_deserialize: false,
+                    size: None,
+                    align: None,
                 },
-                location: path::def_path_to_rtk_location(tcx, &tcx.def_path(*fn_def_id)),
+                location,
                 return_type: type_as_rtk_lua_type_value(tcx, &o, visited).map(Box::new),
                 item_id: String::new(),
                 attributes: attributes_for_did(tcx, *fn_def_id),
                 doc_comment: doc_comment_for_did(tcx, *fn_def_id),
+                where_clause_text: where_clause_text_for_did(tcx, *fn_def_id),
+                is_test: is_test_for_did(tcx, *fn_def_id),
+                is_cfg_test: is_cfg_test_for_did(tcx, *fn_def_id),
+                lifetime_count: lifetime_count_for_did(tcx, *fn_def_id),
+                is_public: tcx.visibility(*fn_def_id).is_public(),
+                visibility: visibility_text_for_did(tcx, *fn_def_id),
+                takes_no_args: i.is_empty(),
+                overrides_trait_method: overrides_trait_method_for_did(tcx, *fn_def_id),
+                overloads: vec![],
             }))
         }
 
+        // the impl's own generics, e.g. `T` when elevating the self type of
+        // `impl<T> Trait for Wrapper<T>`, stay as named parameters
+        TyKind::Param(param) => Some(rtk_lua::TypeValue::Generic(param.name.to_string())),
+
         _ty => None,
     }
 }
@@ -157,7 +197,7 @@ fn adt_type_as_rtk_lua_type_value<'tcx>(
 ) -> Option<rtk_lua::TypeValue> {
     let def_path = tcx.def_path(adt_def.did());
     let def_path = path::def_path_to_rtk_location(tcx, &def_path);
-    let fmt_def_path = path::fmt_rtk_location(&def_path);
+    let fmt_def_path = def_path.to_string();
 
     if let Some(known_type) =
         maybe_resolve_known_def_path(tcx, &fmt_def_path, generic_args, visited)
@@ -182,6 +222,8 @@ fn adt_type_as_rtk_lua_type_value<'tcx>(
         struct_type_as_rtk_lua_type_value(
             tcx,
             adt_def.all_fields(),
+            struct_kind_of_variant(adt_def.non_enum_variant()),
+            adt_def.non_enum_variant().is_field_list_non_exhaustive(),
             adt_def.did(),
             generic_args,
             visited,
@@ -199,36 +241,52 @@ fn enum_type_as_rtk_lua_type_value<'tcx>(
 
     let location = path::def_path_to_rtk_location(tcx, &tcx.def_path(adt_def.did()));
 
+    // variants are indexed by `VariantIdx`, which rustc hands out in declaration order, so they
+    // reach scripts in source order without sorting
     for variant in adt_def.variants() {
         let variant_fields_as_struct = struct_type_as_rtk_lua_type_value(
             tcx,
             variant.fields.iter(),
+            struct_kind_of_variant(variant),
+            variant.is_field_list_non_exhaustive(),
             adt_def.did(),
             generic_args,
             visited,
         );
 
+        let variant_attributes = attributes_for_did(tcx, variant.def_id);
+
         let rtk_lua_variant = rtk_lua::EnumTypeValueVariant {
             value: variant_fields_as_struct,
             name: variant.name.to_string(),
-            attributes: attributes_for_did(tcx, variant.def_id),
+            serde_rename: rtk_lua::EnumTypeValueVariant::serde_rename_for(&variant_attributes),
+            attributes: variant_attributes,
             doc_comment: doc_comment_for_did(tcx, variant.def_id),
         };
 
         rtk_lua_variants.push(rtk_lua_variant);
     }
 
+    let attributes = attributes_for_did(tcx, adt_def.did());
+
     Some(rtk_lua::TypeValue::Enum(rtk_lua::EnumTypeValue {
-        location,
         variants: rtk_lua_variants,
-        attributes: attributes_for_did(tcx, adt_def.did()),
+        derives: rtk_lua::Attribute::derives_of(&attributes),
+        serde_tag: rtk_lua::EnumTypeValue::serde_tag_for(&attributes),
+        is_non_exhaustive: adt_def.is_variant_list_non_exhaustive(),
+        attributes,
         doc_comment: doc_comment_for_did(tcx, adt_def.did()),
+        is_c_like: adt_def.variants().iter().all(|v| v.fields.is_empty()),
+        type_hash: path::type_hash_of_location(&location),
+        location,
     }))
 }
 
 fn struct_type_as_rtk_lua_type_value<'tcx>(
     tcx: TyCtxt<'tcx>,
     fields: impl Iterator<Item = &'tcx rustc_middle::ty::FieldDef>,
+    kind: rtk_lua::StructKind,
+    is_non_exhaustive: bool,
     did: DefId,
     generic_args: &rustc_middle::ty::GenericArgsRef<'tcx>,
     visited: &mut FxHashSet<(DefId, &rustc_middle::ty::GenericArgsRef<'tcx>)>,
@@ -245,36 +303,158 @@ fn struct_type_as_rtk_lua_type_value<'tcx>(
 
         let field_ty = field.ty(tcx, generic_args);
 
-        match type_as_rtk_lua_type_value(tcx, &field_ty, visited) {
-            Some(value) => {
-                let rtk_lua_field = rtk_lua::StructTypeValueField {
-                    name: field_ident,
-                    value,
-                    attributes: attributes_for_did(tcx, field.did),
-                    doc_comment: doc_comment_for_did(tcx, field.did),
-                };
+        let value = type_as_rtk_lua_type_value(tcx, &field_ty, visited).unwrap_or_else(|| {
+            tcx.dcx().warn(format!(
+                "encountered an unsupported field type `{field_ty}` in a query, \
+                 it will be given to the script as `Unsupported`."
+            ));
 
-                rtk_lua_fields.push(rtk_lua_field);
-            }
-            None => {
-                tcx.dcx().warn(
-                    format!(
-                        "encountered an field type `{field_ty:#?}` in a query, \
-                         the rest of the fields will still be attempted but this one will be skipped."
-                    ),
-                );
+            rtk_lua::TypeValue::Unsupported {
+                rust_type: field_ty.to_string(),
             }
-        }
+        });
+
+        let attributes = attributes_for_did(tcx, field.did);
+        rtk_lua_fields.push(rtk_lua::StructTypeValueField {
+            name: field_ident,
+            is_optional: rtk_lua::StructTypeValueField::is_optional_for(&value, &attributes),
+            has_serde_skip: rtk_lua::StructTypeValueField::has_serde_skip_for(&attributes),
+            serde_rename: rtk_lua::StructTypeValueField::serde_rename_for(&attributes),
+            value,
+            attributes,
+            doc_comment: field_doc_comment_for_did(tcx, field.did),
+        });
     }
 
+    let attributes = attributes_for_did(tcx, did);
+    let newtype_inner = rtk_lua::StructTypeValue::newtype_inner_of(kind, &rtk_lua_fields);
+
+    // enum variants are elevated as structs under the enum's def id, but the enum's derives, impls
+    // and layout are its own rather than the variant's
+    let is_enum_variant = tcx.adt_def(did).is_enum();
+
+    let derives = if is_enum_variant {
+        vec![]
+    } else {
+        rtk_lua::Attribute::derives_of(&attributes)
+    };
+
+    // derived impls are impls all the same, so whether one is custom comes down to whether it was
+    // derived
+    let has_custom_serialize = !is_enum_variant
+        && rtk_lua::StructTypeValue::is_custom_impl(
+            &derives,
+            "Serialize",
+            has_impl_of_trait(tcx, did, &SerdeTraits::get(tcx).serialize),
+        );
+    let has_custom_deserialize = !is_enum_variant
+        && rtk_lua::StructTypeValue::is_custom_impl(
+            &derives,
+            "Deserialize",
+            has_impl_of_trait(tcx, did, &SerdeTraits::get(tcx).deserialize),
+        );
+
+    let location = path::def_path_to_rtk_location(tcx, &tcx.def_path(did));
+    let layout = if is_enum_variant {
+        None
+    } else {
+        struct_layout(tcx, did, generic_args)
+    };
+
     Some(rtk_lua::TypeValue::Struct(rtk_lua::StructTypeValue {
-        location: path::def_path_to_rtk_location(tcx, &tcx.def_path(did)),
+        type_hash: path::type_hash_of_location(&location),
+        size: layout.map(|(size, _)| size),
+        align: layout.map(|(_, align)| align),
+        location,
         fields: rtk_lua_fields,
-        attributes: attributes_for_did(tcx, did),
+        derives,
+        attributes,
         doc_comment: doc_comment_for_did(tcx, did),
+        kind,
+        is_non_exhaustive,
+        newtype_inner,
+        has_custom_serialize,
+        has_custom_deserialize,
     }))
 }
 
+/// The size and alignment in bytes of the struct at `did` instantiated with `generic_args`, or
+/// `None` while it is still generic over a parameter or unsized
+fn struct_layout<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    did: DefId,
+    generic_args: &rustc_middle::ty::GenericArgsRef<'tcx>,
+) -> Option<(u64, u64)> {
+    let ty = tcx.type_of(did).instantiate(tcx, *generic_args);
+    let typing_env = TypingEnv::fully_monomorphized();
+    if ty.has_param() || !ty.is_sized(tcx, typing_env) {
+        return None;
+    }
+
+    let layout = tcx.layout_of(typing_env.as_query_input(ty)).ok()?;
+    Some((layout.size.bytes(), layout.align.abi.bytes()))
+}
+
+/// serde's `Serialize` and `Deserialize` traits, as every crate in the dependency graph defining
+/// them. serde itself only reexports them from `serde_core` since 1.0.220, so both crates are
+/// looked in
+struct SerdeTraits {
+    serialize: Vec<DefId>,
+    deserialize: Vec<DefId>,
+}
+
+impl SerdeTraits {
+    /// Finding the traits means going through every trait the crate can see, so it's only done
+    /// the first time they're needed. The driver checks a single crate per process, so this holds
+    /// for the executor's whole run
+    fn get(tcx: TyCtxt<'_>) -> &'static SerdeTraits {
+        static SERDE_TRAITS: OnceLock<SerdeTraits> = OnceLock::new();
+
+        SERDE_TRAITS.get_or_init(|| {
+            let mut traits = SerdeTraits {
+                serialize: vec![],
+                deserialize: vec![],
+            };
+
+            for trait_did in tcx.all_traits() {
+                let location = path::def_path_to_rtk_location(tcx, &tcx.def_path(trait_did));
+                if !matches!(location.crate_name.as_str(), "serde" | "serde_core") {
+                    continue;
+                }
+
+                if location.path == ["ser", "Serialize"] {
+                    traits.serialize.push(trait_did);
+                } else if location.path == ["de", "Deserialize"] {
+                    traits.deserialize.push(trait_did);
+                }
+            }
+
+            traits
+        })
+    }
+}
+
+/// Whether the type at `did` implements any of `trait_dids`, wherever the impl is written. Impls
+/// for any instantiation of a generic type count
+fn has_impl_of_trait(tcx: TyCtxt<'_>, did: DefId, trait_dids: &[DefId]) -> bool {
+    trait_dids.iter().any(|trait_did| {
+        tcx.all_impls(*trait_did).any(|impl_did| {
+            tcx.type_of(impl_did)
+                .instantiate_identity()
+                .ty_adt_def()
+                .is_some_and(|adt| adt.did() == did)
+        })
+    })
+}
+
+fn struct_kind_of_variant(variant: &rustc_middle::ty::VariantDef) -> rtk_lua::StructKind {
+    match variant.ctor_kind() {
+        None => rtk_lua::StructKind::Named,
+        Some(rustc_hir::def::CtorKind::Fn) => rtk_lua::StructKind::Tuple,
+        Some(rustc_hir::def::CtorKind::Const) => rtk_lua::StructKind::Unit,
+    }
+}
+
 fn maybe_resolve_known_def_path<'tcx>(
     tcx: TyCtxt<'tcx>,
     def_path: &str,
@@ -306,18 +486,29 @@ fn maybe_resolve_known_def_path<'tcx>(
             Some(rtk_lua::TypeValue::Result(ok_type, err_type))
         }
         "hashbrown::map::HashMap" | "std::collections::hash::map::HashMap" => {
-            let mut generic_args = generic_args.iter();
-            let key_type = generic_args
-                .next()
-                .and_then(|arg| type_as_rtk_lua_type_value(tcx, &arg.expect_ty(), visited))
-                .map(Box::new)?;
-            let value_type = generic_args
-                .next()
-                .and_then(|arg| type_as_rtk_lua_type_value(tcx, &arg.expect_ty(), visited))
-                .map(Box::new)?;
-
-            Some(rtk_lua::TypeValue::HashMap(key_type, value_type))
+            map(tcx, generic_args, false, visited)
         }
+        "alloc::collections::btree::map::BTreeMap" => map(tcx, generic_args, true, visited),
+        // since 1.79 the `NonZero*` types are aliases of the generic `NonZero<T>`, so the inner
+        // primitive comes from the generic args. the concrete paths cover older toolchains
+        "core::num::nonzero::NonZero" => generic_args
+            .iter()
+            .next()
+            .and_then(|arg| type_as_rtk_lua_type_value(tcx, &arg.expect_ty(), visited))
+            .map(Box::new)
+            .map(rtk_lua::TypeValue::NonZero),
+        "core::num::nonzero::NonZeroU8" => non_zero(rtk_lua::TypeValue::U8),
+        "core::num::nonzero::NonZeroU16" => non_zero(rtk_lua::TypeValue::U16),
+        "core::num::nonzero::NonZeroU32" => non_zero(rtk_lua::TypeValue::U32),
+        "core::num::nonzero::NonZeroU64" => non_zero(rtk_lua::TypeValue::U64),
+        "core::num::nonzero::NonZeroU128" => non_zero(rtk_lua::TypeValue::U128),
+        "core::num::nonzero::NonZeroUsize" => non_zero(rtk_lua::TypeValue::Usize),
+        "core::num::nonzero::NonZeroI8" => non_zero(rtk_lua::TypeValue::I8),
+        "core::num::nonzero::NonZeroI16" => non_zero(rtk_lua::TypeValue::I16),
+        "core::num::nonzero::NonZeroI32" => non_zero(rtk_lua::TypeValue::I32),
+        "core::num::nonzero::NonZeroI64" => non_zero(rtk_lua::TypeValue::I64),
+        "core::num::nonzero::NonZeroI128" => non_zero(rtk_lua::TypeValue::I128),
+        "core::num::nonzero::NonZeroIsize" => non_zero(rtk_lua::TypeValue::Isize),
         "alloc::string::String" => Some(rtk_lua::TypeValue::String),
         "alloc::vec::Vec" => {
             // vecs have two args, with the second being the allocator. we only care about the
@@ -333,6 +524,34 @@ fn maybe_resolve_known_def_path<'tcx>(
     }
 }
 
+/// Elevates a `HashMap` or `BTreeMap` from its generic args, where the key and value come first
+fn map<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    generic_args: &rustc_middle::ty::GenericArgsRef<'tcx>,
+    is_ordered: bool,
+    visited: &mut FxHashSet<(DefId, &rustc_middle::ty::GenericArgsRef<'tcx>)>,
+) -> Option<rtk_lua::TypeValue> {
+    let mut generic_args = generic_args.iter();
+    let key = generic_args
+        .next()
+        .and_then(|arg| type_as_rtk_lua_type_value(tcx, &arg.expect_ty(), visited))
+        .map(Box::new)?;
+    let value = generic_args
+        .next()
+        .and_then(|arg| type_as_rtk_lua_type_value(tcx, &arg.expect_ty(), visited))
+        .map(Box::new)?;
+
+    Some(rtk_lua::TypeValue::Map {
+        key,
+        value,
+        is_ordered,
+    })
+}
+
+fn non_zero(inner: rtk_lua::TypeValue) -> Option<rtk_lua::TypeValue> {
+    Some(rtk_lua::TypeValue::NonZero(Box::new(inner)))
+}
+
 pub fn attributes_for_did(tcx: TyCtxt, did: DefId) -> Vec<rtk_lua::Attribute> {
     let attrs = tcx.get_attrs_unchecked(did);
 
@@ -355,15 +574,54 @@ pub fn attributes_for_did(tcx: TyCtxt, did: DefId) -> Vec<rtk_lua::Attribute> {
             }
         };
 
-        proc_macro_attributes.push(rtk_lua::Attribute {
-            name,
-            value_str: Some(value_str),
-        });
+        proc_macro_attributes.push(rtk_lua::Attribute::new(name, Some(value_str)));
+    }
+
+    if let Some(derive) = derive_attribute_for_did(tcx, did) {
+        proc_macro_attributes.push(derive);
     }
 
     proc_macro_attributes
 }
 
+/// Rebuilds the `#[derive(...)]` of the struct, enum or union at `did` from the impls it generated,
+/// which are marked `#[automatically_derived]`. Expansion consumes the attribute itself, so it never
+/// makes it into the HIR. Each trait is given by its path, e.g. `serde::Serialize`, rather than as
+/// it was written
+fn derive_attribute_for_did(tcx: TyCtxt, did: DefId) -> Option<rtk_lua::Attribute> {
+    if !matches!(
+        tcx.def_kind(did),
+        DefKind::Struct | DefKind::Enum | DefKind::Union
+    ) {
+        return None;
+    }
+
+    let derived_traits = tcx
+        .trait_impls_in_crate(did.krate)
+        .iter()
+        .filter(|impl_did| tcx.is_automatically_derived(**impl_did))
+        .filter(|impl_did| {
+            tcx.type_of(**impl_did)
+                .instantiate_identity()
+                .ty_adt_def()
+                .is_some_and(|adt_def| adt_def.did() == did)
+        })
+        .filter_map(|impl_did| tcx.trait_id_of_impl(*impl_did))
+        // `#[derive(PartialEq)]` also implements this unstable marker, which no one writes
+        .filter(|trait_did| Some(*trait_did) != tcx.lang_items().structural_peq_trait())
+        .map(|trait_did| tcx.def_path_str(trait_did))
+        .collect::<Vec<_>>();
+
+    if derived_traits.is_empty() {
+        return None;
+    }
+
+    Some(rtk_lua::Attribute::new(
+        "derive".to_string(),
+        Some(derived_traits.join(", ")),
+    ))
+}
+
 fn pretty_print_delimited_token_stream(toks: &rustc_ast::tokenstream::TokenStream) -> String {
     toks.iter()
         .map(|token| match token {
@@ -374,10 +632,19 @@ fn pretty_print_delimited_token_stream(toks: &rustc_ast::tokenstream::TokenStrea
                 rustc_ast::token::TokenKind::Comma => ",".to_string(),
                 rustc_ast::token::TokenKind::Colon => ":".to_string(),
                 rustc_ast::token::TokenKind::Semi => ";".to_string(),
+                // keeps paths like `serde::Serialize` intact in derive lists
+                rustc_ast::token::TokenKind::PathSep => "::".to_string(),
                 _ => String::new(),
             },
-            TokenTree::Delimited(_span, _spacing, _delim, ts) => {
-                pretty_print_delimited_token_stream(ts)
+            TokenTree::Delimited(_span, _spacing, delim, ts) => {
+                let inner = pretty_print_delimited_token_stream(ts);
+                match delim {
+                    Delimiter::Parenthesis => format!("({inner})"),
+                    Delimiter::Bracket => format!("[{inner}]"),
+                    Delimiter::Brace => format!("{{{inner}}}"),
+                    // invisible groups from macro expansion have no delimiters in the source
+                    _ => inner,
+                }
             }
         })
         .collect::<Vec<_>>()
@@ -402,20 +669,222 @@ pub fn doc_comment_for_did(tcx: TyCtxt, did: DefId) -> Option<String> {
     if doc.is_empty() { None } else { Some(doc) }
 }
 
-pub fn peel_future_output<'tcx>(tcx: TyCtxt<'tcx>, ty: &Ty<'tcx>) -> Ty<'tcx> {
+/// Collects the doc comment for a struct field. Unlike [`doc_comment_for_did`] this also covers
+/// `#[doc = "..."]` attributes, since fields generated by macros will typically use those
+pub fn field_doc_comment_for_did(tcx: TyCtxt, did: DefId) -> Option<String> {
+    let doc = tcx
+        .get_attrs_unchecked(did)
+        .iter()
+        .filter_map(|attr| attr.doc_str().map(|sym| sym.to_string()))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    if doc.is_empty() { None } else { Some(doc) }
+}
+
+/// Whether the item is a `#[test]` function. The test harness consumes `#[test]`, leaving a const
+/// of the same name beside the function marked `#[rustc_test_marker]`, so that is looked for
+/// instead. Test functions only exist when building tests, so this is always false otherwise
+pub fn is_test_for_did(tcx: TyCtxt, did: DefId) -> bool {
+    let Some(local_did) = did.as_local() else {
+        return false;
+    };
+
+    let name = tcx.item_name(did);
+    let module = tcx.parent_module_from_def_id(local_did);
+    tcx.hir_module_items(module).free_items().any(|item_id| {
+        let item_did = item_id.owner_id.to_def_id();
+        tcx.def_kind(item_did) == DefKind::Const
+            && tcx.item_name(item_did) == name
+            && tcx.has_attr(item_did, sym::rustc_test_marker)
+    })
+}
+
+/// Whether the item or any of its ancestors carries `#[cfg(test)]`. Outside of test builds those
+/// items are configured out entirely, so this can only be true when building tests
+pub fn is_cfg_test_for_did(tcx: TyCtxt, did: DefId) -> bool {
+    let mut current = Some(did);
+    while let Some(did) = current {
+        let is_cfg_test = attributes_for_did(tcx, did)
+            .iter()
+            .any(|attr| attr.name == "cfg" && attr.value_str.as_deref() == Some("test"));
+        if is_cfg_test {
+            return true;
+        }
+
+        current = tcx.opt_parent(did);
+    }
+
+    false
+}
+
+/// The visibility of the item as it would be written in source, e.g. `pub(crate)`. Private items
+/// are restricted to the module they sit in, and give an empty string
+pub fn visibility_text_for_did(tcx: TyCtxt, did: DefId) -> String {
+    let restricted_to = match tcx.visibility(did) {
+        rustc_middle::ty::Visibility::Public => return "pub".to_string(),
+        rustc_middle::ty::Visibility::Restricted(module) => module,
+    };
+
+    let mut enclosing_module = tcx.opt_parent(did);
+    while let Some(parent) = enclosing_module {
+        if tcx.def_kind(parent) == DefKind::Mod {
+            break;
+        }
+
+        enclosing_module = tcx.opt_parent(parent);
+    }
+
+    if enclosing_module == Some(restricted_to) {
+        String::new()
+    } else if restricted_to.is_crate_root() {
+        "pub(crate)".to_string()
+    } else if enclosing_module.and_then(|module| tcx.opt_parent(module)) == Some(restricted_to) {
+        "pub(super)".to_string()
+    } else {
+        let module = path::def_path_to_rtk_location(tcx, &tcx.def_path(restricted_to));
+        format!("pub(in crate::{})", module.path.join("::"))
+    }
+}
+
+/// For a function in a trait impl, the location of the trait method it implements
+pub fn overrides_trait_method_for_did(tcx: TyCtxt, did: DefId) -> Option<rtk_lua::Location> {
+    let trait_item_did = tcx.opt_associated_item(did)?.trait_item_def_id?;
+    Some(path::def_path_to_rtk_location(
+        tcx,
+        &tcx.def_path(trait_item_did),
+    ))
+}
+
+/// Pretty prints each predicate declared on the item itself (not its parent), i.e. the where clause
+/// and any inline bounds on its generics
+pub fn where_clause_text_for_did(tcx: TyCtxt, did: DefId) -> Vec<String> {
+    tcx.predicates_of(did)
+        .predicates
+        .iter()
+        .map(|(clause, _span)| clause.to_string())
+        .collect()
+}
+
+/// The number of lifetimes the function at `did` is generic over. Early bound lifetimes are listed
+/// in its generics, while late bound ones (including elided lifetimes) only show up as bound
+/// variables of its signature, so both are counted
+pub fn lifetime_count_for_did(tcx: TyCtxt, did: DefId) -> usize {
+    let early_bound = tcx
+        .generics_of(did)
+        .own_params
+        .iter()
+        .filter(|param| matches!(param.kind, GenericParamDefKind::Lifetime))
+        .count();
+    let late_bound = tcx
+        .fn_sig(did)
+        .skip_binder()
+        .bound_vars()
+        .iter()
+        .filter(|var| matches!(var, BoundVariableKind::Region(_)))
+        .count();
+
+    early_bound + late_bound
+}
+
+/// Elevates an argument of the function at `fn_did` as a [`rtk_lua::TypeValue::ImplTrait`] if its
+/// type is the synthetic generic param an `impl Trait` argument desugars into. The first trait the
+/// param is bounded by other than the implicit `Sized` is taken as its bound
+pub fn impl_trait_arg(tcx: TyCtxt<'_>, fn_did: DefId, ty: Ty<'_>) -> Option<rtk_lua::TypeValue> {
+    let TyKind::Param(param_ty) = ty.kind() else {
+        return None;
+    };
+
+    let param = tcx
+        .generics_of(fn_did)
+        .param_at(param_ty.index as usize, tcx);
+    if !matches!(
+        param.kind,
+        GenericParamDefKind::Type {
+            synthetic: true,
+            ..
+        }
+    ) {
+        return None;
+    }
+
+    let sized = tcx.lang_items().sized_trait();
+    let bound = tcx
+        .predicates_of(fn_did)
+        .predicates
+        .iter()
+        .filter_map(|(clause, _span)| clause.as_trait_clause())
+        .filter(|trait_clause| {
+            matches!(
+                trait_clause.self_ty().skip_binder().kind(),
+                TyKind::Param(p) if p.index == param_ty.index
+            )
+        })
+        .map(|trait_clause| trait_clause.def_id())
+        .find(|did| Some(*did) != sized)?;
+
+    Some(rtk_lua::TypeValue::ImplTrait {
+        bound: path::def_path_to_rtk_location(tcx, &tcx.def_path(bound)),
+    })
+}
+
+/// What calling a function with the `output` return type gives once awaited: the output of its
+/// future for async functions and manually async ones returning a boxed future, or `output` itself
+/// otherwise. `None` if an async function's future has a shape we don't know how to read
+pub fn awaited_output<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    output: Ty<'tcx>,
+    is_async: bool,
+) -> Option<Ty<'tcx>> {
+    if is_async {
+        peel_future_output(tcx, &output)
+    } else {
+        // manually async functions return a boxed future, which we treat the same as an async fn
+        // returning its output
+        Some(boxed_future_output(tcx, &output).unwrap_or(output))
+    }
+}
+
+/// Finds the output type of a future. If the shape of the future isn't one we know how to read,
+/// a warning is emitted and `None` is returned so the caller can skip it rather than abort
+pub fn peel_future_output<'tcx>(tcx: TyCtxt<'tcx>, ty: &Ty<'tcx>) -> Option<Ty<'tcx>> {
     match ty.kind() {
         TyKind::Alias(AliasTyKind::Opaque, alias_ty) => {
-            let ty = tcx.type_of_opaque(alias_ty.def_id).unwrap();
+            let ty = tcx.type_of_opaque(alias_ty.def_id).ok()?;
             peel_future_output(tcx, &ty.skip_binder())
         }
         TyKind::Coroutine(_, generic_args) => {
             // first three args are coroutine bootstrapping, fourth is the output, and 5 + 6 hold the body
             // and input args
-            let fut_output = generic_args.get(3).unwrap();
-            fut_output.expect_ty()
+            let fut_output = generic_args.get(3)?;
+            fut_output.as_type()
         }
-        _ => tcx
-            .dcx()
-            .fatal(format!("expected coroutine type, found `{ty:#?}`")),
+        _ => boxed_future_output(tcx, ty).or_else(|| {
+            tcx.dcx().warn(format!(
+                "expected a future type, found `{ty:#?}`, it will be skipped as its output could not be determined"
+            ));
+            None
+        }),
+    }
+}
+
+/// Reads `T` out of a `Box<dyn Future<Output = T>>` or `Pin<Box<dyn Future<Output = T>>>`
+fn boxed_future_output<'tcx>(tcx: TyCtxt<'tcx>, ty: &Ty<'tcx>) -> Option<Ty<'tcx>> {
+    match ty.kind() {
+        TyKind::Adt(adt_def, generic_args)
+            if adt_def.is_box() || tcx.is_lang_item(adt_def.did(), rustc_hir::LangItem::Pin) =>
+        {
+            let inner = generic_args.types().next()?;
+            boxed_future_output(tcx, &inner)
+        }
+        TyKind::Dynamic(predicates, _, _) => {
+            let future_output = tcx.lang_items().future_output()?;
+            predicates
+                .projection_bounds()
+                .map(|projection| projection.skip_binder())
+                .find(|projection| projection.def_id == future_output)
+                .and_then(|projection| projection.term.as_type())
+        }
+        _ => None,
     }
 }
@@ -0,0 +1,2149 @@
+//! Runs the driver over small fixture crates the same way the cli does, through `cargo check` with
+//! the driver as `RUSTC_WRAPPER`, and checks what their scripts emit
+
+use std::{
+    path::Path,
+    process::{Command, Output},
+};
+
+const FIXTURE_MANIFEST: &str = r#"
+[package]
+name = "fixture"
+version = "0.1.0"
+edition = "2024"
+
+[workspace]
+"#;
+
+/// Runs `script` over a `fixture` crate whose `src/lib.rs` is `lib_rs`, returning everything the
+/// script emitted
+fn run_script(test_name: &str, lib_rs: &str, script: &str) -> String {
+    run_script_over(
+        test_name,
+        &[("Cargo.toml", FIXTURE_MANIFEST), ("src/lib.rs", lib_rs)],
+        script,
+        &[],
+    )
+}
+
+/// Runs `script` over the crate made up of `files`, given as paths relative to its root alongside
+/// their contents, with `cargo_args` forwarded to `cargo check`. Returns everything the script
+/// emitted
+fn run_script_over(
+    test_name: &str,
+    files: &[(&str, &str)],
+    script: &str,
+    cargo_args: &[&str],
+) -> String {
+    run_script_over_with_stderr(test_name, files, script, cargo_args).0
+}
+
+/// Like [`run_script_over`], also returning what `cargo check` wrote to stderr, which is where the
+/// driver's warnings end up
+fn run_script_over_with_stderr(
+    test_name: &str,
+    files: &[(&str, &str)],
+    script: &str,
+    cargo_args: &[&str],
+) -> (String, String) {
+    let (emitted, output) = check_fixture(test_name, files, script, cargo_args, &[]);
+
+    let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+    assert!(output.status.success(), "cargo check failed:\n{stderr}");
+    (
+        emitted.expect("the driver didn't write the out file"),
+        stderr,
+    )
+}
+
+/// Runs `script` over a `fixture` crate whose `src/lib.rs` is `lib_rs`, with `env` set for the
+/// driver, expecting the run to fail. Returns what `cargo check` wrote to stderr
+fn run_failing_script(test_name: &str, lib_rs: &str, script: &str, env: &[(&str, &str)]) -> String {
+    let (_, output) = check_fixture(
+        test_name,
+        &[("Cargo.toml", FIXTURE_MANIFEST), ("src/lib.rs", lib_rs)],
+        script,
+        &[],
+        env,
+    );
+
+    let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+    assert!(!output.status.success(), "cargo check passed:\n{stderr}");
+    stderr
+}
+
+/// Writes out the crate made up of `files` and runs `cargo check` over it with the driver running
+/// `script`, returning the out file if the driver wrote one along with cargo's output
+fn check_fixture(
+    test_name: &str,
+    files: &[(&str, &str)],
+    script: &str,
+    cargo_args: &[&str],
+    env: &[(&str, &str)],
+) -> (Option<String>, Output) {
+    let dir = std::env::temp_dir().join(format!(
+        "rtk-driver-test-{}-{test_name}",
+        std::process::id()
+    ));
+    for (path, contents) in files {
+        write_file(&dir.join(path), contents);
+    }
+
+    let script_path = dir.join("script.lua");
+    write_file(&script_path, script);
+    let out_file = dir.join("out.txt");
+
+    let output = Command::new(std::env::var_os("CARGO").unwrap_or_else(|| "cargo".into()))
+        .args(["check", "--quiet"])
+        .args(cargo_args)
+        .current_dir(&dir)
+        .env("RUSTC_WRAPPER", env!("CARGO_BIN_EXE_rtk-rustc-driver"))
+        .env("RTK_LUA_SCRIPT", &script_path)
+        .env("RTK_OUT_FILE", &out_file)
+        .env("CARGO_TARGET_DIR", dir.join("target"))
+        .envs(env.iter().copied())
+        .output()
+        .expect("failed to execute cargo check");
+    let emitted = std::fs::read_to_string(&out_file).ok();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    (emitted, output)
+}
+
+fn write_file(path: &Path, contents: &str) {
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(path, contents).unwrap();
+}
+
+#[test]
+fn struct_fields_carry_their_doc_comments() {
+    let emitted = run_script(
+        "field_docs",
+        r#"
+        pub struct Config {
+            /// The port to listen on
+            pub port: u16,
+            #[doc = "The host to bind to"]
+            pub host: String,
+            pub retries: u8,
+        }
+
+        pub trait Describe {}
+
+        impl Describe for Config {}
+        "#,
+        r#"
+        local describe = rtk.query_trait_impls({ crate_name = "fixture", path = { "Describe" } })
+        local config = describe[1].for_type.variant_data
+
+        for _, field in ipairs(config.fields) do
+            rtk.emit(field.name.variant_data .. "=" .. tostring(field.doc_comment) .. ";")
+        end
+        "#,
+    );
+
+    assert_eq!(
+        emitted,
+        "port= The port to listen on;host=The host to bind to;retries=nil;"
+    );
+}
+
+#[test]
+fn counts_match_the_length_of_the_full_queries() {
+    let emitted = run_script(
+        "counts",
+        r#"
+        pub struct Client;
+
+        impl Client {
+            pub fn send(&self, _n: u32) -> &Self {
+                self
+            }
+        }
+
+        pub struct Server;
+
+        impl Server {
+            pub fn new() -> Self {
+                Server
+            }
+        }
+
+        impl Client {
+            pub fn new() -> Self {
+                Client
+            }
+        }
+
+        pub fn run(client: &Client) {
+            client.send(1).send(2);
+            client.send(3);
+        }
+        "#,
+        r#"
+        local new = { crate_name = "fixture", path = { "new" } }
+        local send = { location = { crate_name = "fixture", path = { "send" }, impl_block_number = 0 } }
+
+        rtk.emit(rtk.count_functions(new) .. "/" .. #rtk.query_functions(new) .. ",")
+        rtk.emit(rtk.count_method_calls(send) .. "/" .. #rtk.query_method_calls(send))
+        "#,
+    );
+
+    assert_eq!(emitted, "2/2,3/3");
+}
+
+#[test]
+fn derives_are_read_from_the_impls_they_generate() {
+    let emitted = run_script(
+        "derives",
+        r#"
+        #[derive(Debug, Clone, PartialEq)]
+        pub struct Point {
+            pub x: i32,
+        }
+
+        pub struct Plain;
+
+        pub trait Describe {}
+
+        impl Describe for Point {}
+
+        impl Describe for Plain {}
+        "#,
+        r#"
+        local describe = rtk.query_trait_impls({ crate_name = "fixture", path = { "Describe" } })
+
+        for _, describe_impl in ipairs(describe) do
+            local ty = describe_impl.for_type.variant_data
+            local derive = rtk.find_attribute(ty.attributes, "derive")
+            local derived_traits = derive and #derive.derived_traits
+
+            rtk.emit(table.concat(ty.derives, ",") .. ":" .. tostring(derived_traits) .. ";")
+        end
+        "#,
+    );
+
+    assert_eq!(emitted, "Debug,Clone,PartialEq:3;:nil;");
+}
+
+#[test]
+fn functions_are_found_in_workspace_dependencies() {
+    let emitted = run_script_over(
+        "dependency_functions",
+        &[
+            (
+                "Cargo.toml",
+                r#"
+                [package]
+                name = "fixture"
+                version = "0.1.0"
+                edition = "2024"
+
+                [dependencies]
+                shared = { path = "shared" }
+
+                [workspace]
+                members = ["shared"]
+                "#,
+            ),
+            (
+                "src/lib.rs",
+                r#"
+                pub fn greet_everyone() -> String {
+                    shared::greet(String::from("everyone"))
+                }
+                "#,
+            ),
+            (
+                "shared/Cargo.toml",
+                r#"
+                [package]
+                name = "shared"
+                version = "0.1.0"
+                edition = "2024"
+                "#,
+            ),
+            (
+                "shared/src/lib.rs",
+                r#"
+                pub fn greet(name: String) -> String {
+                    name
+                }
+
+                pub mod nested {
+                    pub fn depth(levels: u32) -> u32 {
+                        levels
+                    }
+                }
+                "#,
+            ),
+        ],
+        r#"
+        local greet = rtk.query_functions({ crate_name = "shared", path = { "greet" } })[1]
+        local depth = rtk.query_functions({ crate_name = "shared", path = { "nested", "depth" } })[1]
+
+        rtk.emit(rtk.fmt_location(greet.location) .. "->" .. greet.return_type.variant_name .. ";")
+        rtk.emit(rtk.fmt_location(depth.location) .. "(" .. depth.args_struct.fields[1].value.variant_name .. ")")
+        "#,
+        &[],
+    );
+
+    assert_eq!(emitted, "shared::greet->String;shared::nested::depth(U32)");
+}
+
+#[test]
+fn boxed_futures_return_their_output() {
+    let emitted = run_script(
+        "boxed_futures",
+        r#"
+        use std::{future::Future, pin::Pin};
+
+        pub fn pinned() -> Pin<Box<dyn Future<Output = u32>>> {
+            Box::pin(async { 1 })
+        }
+
+        pub fn boxed() -> Box<dyn Future<Output = bool> + Send> {
+            Box::new(async { true })
+        }
+
+        pub async fn automatic() -> String {
+            String::new()
+        }
+        "#,
+        r#"
+        for _, name in ipairs({ "pinned", "boxed", "automatic" }) do
+            local f = rtk.query_functions({ crate_name = "fixture", path = { name } })[1]
+            rtk.emit(name .. ":" .. f.return_type.variant_name .. ";")
+        end
+        "#,
+    );
+
+    assert_eq!(emitted, "pinned:U32;boxed:Bool;automatic:String;");
+}
+
+#[test]
+fn async_functions_with_unknown_future_shapes_are_skipped() {
+    // an async trait method's signature returns a projection to the future rather than the future
+    // itself, which isn't a shape `peel_future_output` can read
+    let emitted = run_script(
+        "unknown_future_shape",
+        r#"
+        pub trait Handler {
+            async fn handle(&self) -> u32;
+        }
+
+        pub struct Service;
+
+        impl Handler for Service {
+            async fn handle(&self) -> u32 {
+                1
+            }
+        }
+
+        pub async fn plain() -> u32 {
+            1
+        }
+
+        pub fn register<F>(_handler: F) {}
+
+        pub fn routes() {
+            register(<Service as Handler>::handle);
+            register(plain);
+        }
+        "#,
+        r#"
+        for _, call in ipairs(rtk.query_function_calls({ crate_name = "fixture", path = { "register" } })) do
+            rtk.emit(#call.args .. ";")
+        end
+        "#,
+    );
+
+    assert_eq!(emitted, "0;1;");
+}
+
+#[test]
+fn test_functions_are_flagged_when_checking_tests() {
+    let emitted = run_script_over(
+        "test_functions",
+        &[
+            ("Cargo.toml", FIXTURE_MANIFEST),
+            (
+                "src/lib.rs",
+                r#"
+                pub fn add(a: u32, b: u32) -> u32 {
+                    a + b
+                }
+
+                #[cfg(test)]
+                mod tests {
+                    #[test]
+                    fn adds() {
+                        assert_eq!(super::add(1, 2), 3);
+                    }
+                }
+                "#,
+            ),
+        ],
+        r#"
+        -- the test module is private, so it's hidden unless asked for
+        rtk.set_include_hidden(true)
+
+        for _, path in ipairs({ { "add" }, { "tests", "adds" } }) do
+            local f = rtk.query_functions({ crate_name = "fixture", path = path })[1]
+            rtk.emit(path[#path] .. ":" .. tostring(f.is_test) .. "," .. tostring(f.is_cfg_test) .. ";")
+        end
+        "#,
+        &["--tests"],
+    );
+
+    assert_eq!(emitted, "add:false,false;adds:true,true;");
+}
+
+#[test]
+fn generic_impl_self_types_keep_their_parameters() {
+    let emitted = run_script(
+        "generic_impl",
+        r#"
+        pub struct Wrapper<T> {
+            pub inner: T,
+            pub count: u32,
+        }
+
+        pub trait Describe {}
+
+        impl<T> Describe for Wrapper<T> {}
+        "#,
+        r#"
+        local describe = rtk.query_trait_impls({ crate_name = "fixture", path = { "Describe" } })
+        local wrapper = describe[1].for_type
+
+        rtk.emit(wrapper.variant_name .. ";")
+        for _, field in ipairs(wrapper.variant_data.fields) do
+            local value = field.value
+            local ty = value.variant_name
+            if ty == "Generic" then
+                ty = ty .. "(" .. value.variant_data .. ")"
+            end
+
+            rtk.emit(field.name.variant_data .. ":" .. ty .. ";")
+        end
+        "#,
+    );
+
+    assert_eq!(emitted, "Struct;inner:Generic(T);count:U32;");
+}
+
+#[test]
+fn only_declared_type_generics_are_warned_about() {
+    let (emitted, stderr) = run_script_over_with_stderr(
+        "generic_warnings",
+        &[
+            ("Cargo.toml", FIXTURE_MANIFEST),
+            (
+                "src/lib.rs",
+                r#"
+                pub fn f(x: impl Into<String>) -> String {
+                    x.into()
+                }
+
+                pub fn g<'a>(x: &'a str) -> &'a str {
+                    x
+                }
+
+                pub fn h<T>(x: T) -> T {
+                    x
+                }
+                "#,
+            ),
+        ],
+        r#"
+        rtk.emit(#rtk.query_functions({ crate_name = "fixture", path = { "h" } }))
+        "#,
+        &[],
+    );
+
+    assert_eq!(emitted, "1");
+    // every function is looked at by the query, but only `h` declares a type parameter
+    let warning = "function type and const generic parameters will be ignored";
+    assert_eq!(stderr.matches(warning).count(), 1, "{stderr}");
+    assert!(stderr.contains("pub fn h<T>(x: T) -> T"), "{stderr}");
+}
+
+#[test]
+fn struct_literals_are_found_through_self_and_aliases() {
+    let emitted = run_script(
+        "struct_literal_paths",
+        r#"
+        pub struct Config {
+            pub port: u16,
+        }
+
+        pub type Alias = Config;
+
+        impl Config {
+            pub fn new() -> Self {
+                Self { port: 1 }
+            }
+        }
+
+        pub fn aliased() -> Alias {
+            Alias { port: 2 }
+        }
+
+        pub fn direct() -> Config {
+            Config { port: 3 }
+        }
+
+        pub enum Event {
+            Moved { x: i32 },
+        }
+
+        impl Event {
+            pub fn moved() -> Self {
+                Self::Moved { x: 1 }
+            }
+        }
+        "#,
+        r#"
+        local config = rtk.query_struct_literals({ crate_name = "fixture", path = { "Config" } })
+        local moved = rtk.query_struct_literals({ crate_name = "fixture", path = { "Event", "Moved" } })
+
+        rtk.emit(#config .. ";" .. #moved)
+        "#,
+    );
+
+    assert_eq!(emitted, "3;1");
+}
+
+#[test]
+fn enum_variants_leave_derives_to_their_enum() {
+    let emitted = run_script(
+        "variant_derives",
+        r#"
+        #[derive(Debug, Clone)]
+        pub enum Event {
+            Moved { x: i32 },
+            Stopped,
+        }
+
+        pub trait Describe {}
+
+        impl Describe for Event {}
+        "#,
+        r#"
+        local describe = rtk.query_trait_impls({ crate_name = "fixture", path = { "Describe" } })
+        local event = describe[1].for_type.variant_data
+
+        rtk.emit(table.concat(event.derives, ",") .. ";")
+        for _, variant in ipairs(event.variants) do
+            rtk.emit(variant.name .. ":" .. #variant.value.variant_data.derives .. ";")
+        end
+        "#,
+    );
+
+    assert_eq!(emitted, "Debug,Clone;Moved:0;Stopped:0;");
+}
+
+#[test]
+fn lifetimes_are_counted_whether_early_or_late_bound() {
+    let emitted = run_script(
+        "lifetime_counts",
+        r#"
+        pub fn borrowed<'a>(x: &'a str) -> &'a str {
+            x
+        }
+
+        pub fn elided(x: &str, _y: &str) -> usize {
+            x.len()
+        }
+
+        // a lifetime that doesn't show up in the args is early bound
+        pub fn early<'a>() -> &'a str {
+            ""
+        }
+
+        pub fn owned(x: String) -> String {
+            x
+        }
+        "#,
+        r#"
+        for _, name in ipairs({ "borrowed", "elided", "early", "owned" }) do
+            local f = rtk.query_functions({ crate_name = "fixture", path = { name } })[1]
+            rtk.emit(name .. ":" .. f.lifetime_count .. ";")
+        end
+        "#,
+    );
+
+    assert_eq!(emitted, "borrowed:1;elided:2;early:1;owned:0;");
+}
+
+#[test]
+fn constructor_and_associated_function_calls_are_found() {
+    let emitted = run_script(
+        "constructor_calls",
+        r#"
+        pub struct Point(pub i32, pub i32);
+
+        pub enum Shape {
+            Circle(u32),
+        }
+
+        pub struct Router;
+
+        impl Router {
+            pub fn new() -> Self {
+                Router
+            }
+        }
+
+        pub fn build() {
+            let _ = Point(1, 2);
+            let _ = Shape::Circle(3);
+            let _ = Router::new();
+        }
+        "#,
+        r#"
+        local queries = {
+            { path = { "Point" } },
+            { path = { "Shape", "Circle" } },
+            { path = { "new" }, impl_block_number = 0 },
+        }
+
+        for _, query in ipairs(queries) do
+            query.crate_name = "fixture"
+            for _, call in ipairs(rtk.query_function_calls(query)) do
+                local location = call.location
+                rtk.emit(table.concat(location.path, "::") .. "@" .. tostring(location.impl_block_number))
+                rtk.emit("(" .. #call.args .. ");")
+            end
+        end
+        "#,
+    );
+
+    assert_eq!(emitted, "Point@nil(2);Shape::Circle@nil(1);new@0(0);");
+}
+
+#[test]
+fn trait_impls_carry_the_attributes_of_the_type() {
+    let emitted = run_script(
+        "for_type_attributes",
+        r#"
+        #[must_use = "configs do nothing unless applied"]
+        pub struct Config {
+            pub port: u16,
+        }
+
+        pub trait Describe {}
+
+        impl Describe for Config {}
+
+        impl Describe for u32 {}
+        "#,
+        r#"
+        local describe = rtk.query_trait_impls({ crate_name = "fixture", path = { "Describe" } })
+
+        for _, describe_impl in ipairs(describe) do
+            rtk.emit(describe_impl.for_type.variant_name .. ":")
+            for _, attr in ipairs(describe_impl.for_type_attributes) do
+                rtk.emit(attr.name .. "=" .. attr.value_str)
+            end
+            rtk.emit(";")
+        end
+        "#,
+    );
+
+    assert_eq!(
+        emitted,
+        "Struct:must_use=configs do nothing unless applied;U32:;"
+    );
+}
+
+#[test]
+fn functions_report_their_visibility() {
+    let emitted = run_script(
+        "visibility",
+        r#"
+        pub fn public_api() {}
+
+        fn private_helper() {}
+
+        pub(crate) fn internal() {}
+
+        pub mod a {
+            pub mod b {
+                pub(super) fn parent_only() {}
+
+                pub mod c {
+                    pub(in crate::a) fn within_a() {}
+                }
+            }
+        }
+        "#,
+        r#"
+        -- only public functions are queried by default
+        rtk.set_include_hidden(true)
+
+        local paths = {
+            { "public_api" },
+            { "private_helper" },
+            { "internal" },
+            { "a", "b", "parent_only" },
+            { "a", "b", "c", "within_a" },
+        }
+
+        for _, path in ipairs(paths) do
+            local f = rtk.query_functions({ crate_name = "fixture", path = path })[1]
+            rtk.emit(path[#path] .. ":" .. tostring(f.is_public) .. ":" .. f.visibility .. ";")
+        end
+        "#,
+    );
+
+    assert_eq!(
+        emitted,
+        "public_api:true:pub;private_helper:false:;internal:false:pub(crate);\
+         parent_only:false:pub(super);within_a:false:pub(in crate::a);"
+    );
+}
+
+#[test]
+fn non_exhaustive_types_and_variants_are_flagged() {
+    let emitted = run_script(
+        "non_exhaustive",
+        r#"
+        #[non_exhaustive]
+        pub enum Status {
+            Active,
+        }
+
+        pub enum Event {
+            #[non_exhaustive]
+            Moved { x: i32 },
+            Stopped,
+        }
+
+        #[non_exhaustive]
+        pub struct Options {
+            pub verbose: bool,
+        }
+
+        pub struct Point {
+            pub x: i32,
+        }
+
+        pub trait Describe {}
+
+        impl Describe for Status {}
+
+        impl Describe for Event {}
+
+        impl Describe for Options {}
+
+        impl Describe for Point {}
+        "#,
+        r#"
+        local describe = rtk.query_trait_impls({ crate_name = "fixture", path = { "Describe" } })
+
+        for _, describe_impl in ipairs(describe) do
+            local ty = describe_impl.for_type.variant_data
+            rtk.emit(ty.location.path[1] .. ":" .. tostring(ty.is_non_exhaustive))
+
+            for _, variant in ipairs(ty.variants or {}) do
+                rtk.emit("," .. variant.name .. ":" .. tostring(variant.value.variant_data.is_non_exhaustive))
+            end
+            rtk.emit(";")
+        end
+        "#,
+    );
+
+    assert_eq!(
+        emitted,
+        "Status:true,Active:false;Event:false,Moved:true,Stopped:false;Options:true;Point:false;"
+    );
+}
+
+#[test]
+fn impl_trait_args_report_their_bound() {
+    let emitted = run_script(
+        "impl_trait_args",
+        r#"
+        pub fn f(x: impl Into<String>) -> String {
+            x.into()
+        }
+        "#,
+        r#"
+        local f = rtk.query_functions({ crate_name = "fixture", path = { "f" } })[1]
+        local x = f.args_struct.fields[1].value
+
+        rtk.emit(x.variant_name .. ":" .. rtk.fmt_location(x.variant_data.bound))
+        "#,
+    );
+
+    assert_eq!(emitted, "ImplTrait:core::convert::Into");
+}
+
+#[test]
+fn only_functions_without_inputs_take_no_args() {
+    let emitted = run_script(
+        "takes_no_args",
+        r#"
+        pub fn ping() -> bool {
+            true
+        }
+
+        pub fn echo(x: u32) -> u32 {
+            x
+        }
+
+        // the arg can't be elevated, which mustn't be mistaken for there being none
+        pub fn callback(f: fn()) {
+            f()
+        }
+        "#,
+        r#"
+        for _, name in ipairs({ "ping", "echo", "callback" }) do
+            local f = rtk.query_functions({ crate_name = "fixture", path = { name } })[1]
+            rtk.emit(name .. ":" .. tostring(f.takes_no_args) .. ";")
+        end
+        "#,
+    );
+
+    assert_eq!(emitted, "ping:true;echo:false;callback:false;");
+}
+
+#[test]
+fn struct_fields_of_unsupported_types_are_kept() {
+    let (emitted, stderr) = run_script_over_with_stderr(
+        "unsupported_fields",
+        &[
+            ("Cargo.toml", FIXTURE_MANIFEST),
+            (
+                "src/lib.rs",
+                r#"
+                pub struct Buffer {
+                    pub ptr: *const u8,
+                    pub on_drop: fn(u32),
+                    pub len: usize,
+                }
+
+                pub trait Describe {}
+
+                impl Describe for Buffer {}
+                "#,
+            ),
+        ],
+        r#"
+        local describe = rtk.query_trait_impls({ crate_name = "fixture", path = { "Describe" } })
+        for _, field in ipairs(describe[1].for_type.variant_data.fields) do
+            local value = field.value
+            local ty = value.variant_name
+            if ty == "RawPointer" then
+                ty = ty .. "(" .. value.variant_data.inner.variant_name .. ")"
+            elseif ty == "Unsupported" then
+                ty = ty .. "(" .. value.variant_data.rust_type .. ")"
+            end
+
+            rtk.emit(field.name.variant_data .. ":" .. ty .. ";")
+        end
+        "#,
+        &[],
+    );
+
+    assert_eq!(
+        emitted,
+        "ptr:RawPointer(U8);on_drop:Unsupported(fn(u32));len:Usize;"
+    );
+    assert!(
+        stderr.contains("encountered an unsupported field type `fn(u32)`"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn trait_impl_functions_point_at_the_method_they_implement() {
+    let emitted = run_script(
+        "overrides_trait_method",
+        r#"
+        pub trait Handler {
+            fn handle(&self);
+
+            fn name(&self) -> u32 {
+                0
+            }
+        }
+
+        pub struct GetUser;
+
+        impl Handler for GetUser {
+            fn handle(&self) {}
+        }
+
+        pub fn standalone() {}
+        "#,
+        r#"
+        local handler = { crate_name = "fixture", path = { "Handler" } }
+        for _, impl in ipairs(rtk.query_trait_impls(handler)) do
+            for _, f in ipairs(impl.functions) do
+                rtk.emit(rtk.fmt_location(f.overrides_trait_method) .. ";")
+            end
+        end
+
+        local standalone = rtk.query_functions({ crate_name = "fixture", path = { "standalone" } })
+        rtk.emit(tostring(standalone[1].overrides_trait_method))
+        "#,
+    );
+
+    assert_eq!(emitted, "fixture::Handler::handle;nil");
+}
+
+#[test]
+fn chain_depth_is_read_from_the_call_rather_than_the_query() {
+    let emitted = run_script(
+        "chain_depth",
+        r#"
+        pub struct Builder;
+
+        impl Builder {
+            pub fn a(&self) -> &Self {
+                self
+            }
+
+            pub fn b(&self) -> &Self {
+                self
+            }
+
+            pub fn c(&self) -> &Self {
+                self
+            }
+        }
+
+        pub fn build(builder: &Builder) {
+            builder.a().b().c();
+            builder.c();
+        }
+        "#,
+        r#"
+        local function method(name, parent)
+            return {
+                parent = parent,
+                location = { crate_name = "fixture", path = { name }, impl_block_number = 0 },
+            }
+        end
+
+        for _, query in ipairs({ method("c"), method("c", method("b")) }) do
+            for _, call in ipairs(rtk.query_method_calls(query)) do
+                rtk.emit(call.chain_depth .. ";")
+            end
+            rtk.emit("|")
+        end
+        "#,
+    );
+
+    assert_eq!(emitted, "2;0;|2;|");
+}
+
+#[test]
+fn hand_written_serde_impls_are_detected_in_serde_and_serde_core() {
+    let manifest = |name: &str| {
+        format!(
+            r#"
+            [package]
+            name = "{name}"
+            version = "0.1.0"
+            edition = "2024"
+            "#
+        )
+    };
+    let (serde_manifest, serde_core_manifest) = (manifest("serde"), manifest("serde_core"));
+    let serde_traits = r#"
+        pub mod ser {
+            pub trait Serialize {}
+        }
+
+        pub mod de {
+            pub trait Deserialize {}
+        }
+    "#;
+
+    let emitted = run_script_over(
+        "custom_serde",
+        &[
+            (
+                "Cargo.toml",
+                r#"
+                [package]
+                name = "fixture"
+                version = "0.1.0"
+                edition = "2024"
+
+                [dependencies]
+                serde = { path = "serde" }
+                serde_core = { path = "serde_core" }
+
+                [workspace]
+                members = ["serde", "serde_core"]
+                "#,
+            ),
+            (
+                "src/lib.rs",
+                r#"
+                pub struct FromSerde;
+                pub struct FromSerdeCore;
+                pub struct Plain;
+
+                impl serde::ser::Serialize for FromSerde {}
+                impl serde_core::de::Deserialize for FromSerdeCore {}
+
+                pub trait Describe {}
+
+                impl Describe for FromSerde {}
+                impl Describe for FromSerdeCore {}
+                impl Describe for Plain {}
+                "#,
+            ),
+            ("serde/Cargo.toml", serde_manifest.as_str()),
+            ("serde/src/lib.rs", serde_traits),
+            ("serde_core/Cargo.toml", serde_core_manifest.as_str()),
+            ("serde_core/src/lib.rs", serde_traits),
+        ],
+        r#"
+        local found = {}
+        local describe = rtk.query_trait_impls({ crate_name = "fixture", path = { "Describe" } })
+        for _, impl in ipairs(describe) do
+            local ty = impl.for_type.variant_data
+            found[ty.location.path[1]] =
+                tostring(ty.has_custom_serialize) .. "," .. tostring(ty.has_custom_deserialize)
+        end
+
+        for _, name in ipairs({ "FromSerde", "FromSerdeCore", "Plain" }) do
+            rtk.emit(name .. ":" .. found[name] .. ";")
+        end
+        "#,
+        &[],
+    );
+
+    assert_eq!(
+        emitted,
+        "FromSerde:true,false;FromSerdeCore:false,true;Plain:false,false;"
+    );
+}
+
+#[test]
+fn method_calls_on_one_line_are_in_source_order() {
+    let emitted = run_script(
+        "method_call_order",
+        r#"
+        pub struct Client;
+
+        impl Client {
+            pub fn send(&self, _s: &str) -> &Self {
+                self
+            }
+        }
+
+        macro_rules! backwards {
+            ($first:expr, $second:expr) => {{
+                $second;
+                $first;
+            }};
+        }
+
+        pub fn run(client: &Client) {
+            backwards!(client.send("first"), client.send("second"));
+        }
+        "#,
+        r#"
+        local send = { location = { crate_name = "fixture", path = { "send" }, impl_block_number = 0 } }
+        for _, call in ipairs(rtk.query_method_calls(send)) do
+            rtk.emit(call.args[1].variant_data .. ";")
+        end
+        "#,
+    );
+
+    assert_eq!(emitted, "first;second;");
+}
+
+#[test]
+fn hidden_items_are_left_out_without_hiding_their_children() {
+    let emitted = run_script(
+        "include_hidden",
+        r#"
+        #[doc(hidden)]
+        pub fn secret() {}
+
+        mod private {
+            pub fn exposed() {}
+        }
+        "#,
+        r#"
+        local secret = { crate_name = "fixture", path = { "secret" } }
+        local exposed = { crate_name = "fixture", path = { "private", "exposed" } }
+
+        rtk.emit(#rtk.query_functions(secret) .. "," .. #rtk.query_functions(exposed) .. ";")
+        rtk.set_include_hidden(true)
+        rtk.emit(#rtk.query_functions(secret) .. "," .. #rtk.query_functions(exposed) .. ";")
+        "#,
+    );
+
+    assert_eq!(emitted, "0,1;1,1;");
+}
+
+#[test]
+fn missing_function_bodies_are_denied_warnings() {
+    let stderr = run_failing_script(
+        "missing_body_warnings",
+        r#"
+        pub fn foo() {}
+        "#,
+        r#"
+        local missing = { crate_name = "fixture", path = { "missing" } }
+        rtk.query_function_calls_in_function(missing, { crate_name = "fixture", path = { "foo" } })
+        rtk.query_closures_with_capture(missing, "x")
+        "#,
+        &[("RTK_DENY_WARNINGS", "1")],
+    );
+
+    assert!(
+        stderr.contains("no function body found at `fixture::missing` to query calls within"),
+        "{stderr}"
+    );
+    assert!(
+        stderr.contains("the script logged 2 warning(s)"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn function_calls_in_function_only_look_in_that_body() {
+    let emitted = run_script(
+        "calls_in_function",
+        r#"
+        pub fn foo(_from: &str) {}
+
+        pub fn a() {
+            foo("a");
+        }
+
+        pub fn b() {
+            foo("b");
+        }
+        "#,
+        r#"
+        local calls = rtk.query_function_calls_in_function(
+            { crate_name = "fixture", path = { "a" } },
+            { crate_name = "fixture", path = { "foo" } }
+        )
+
+        rtk.emit(#calls .. ";")
+        for _, call in ipairs(calls) do
+            rtk.emit(call.args[1].variant_data .. ";")
+        end
+        "#,
+    );
+
+    assert_eq!(emitted, "1;a;");
+}
+
+#[test]
+fn struct_layouts_are_read_once_instantiated() {
+    let emitted = run_script(
+        "struct_layout",
+        r#"
+        #[repr(C)]
+        pub struct S {
+            pub a: u32,
+            pub b: u8,
+        }
+
+        pub struct Wrapper<T> {
+            pub inner: T,
+        }
+
+        pub trait Describe {}
+
+        impl Describe for S {}
+        impl<T> Describe for Wrapper<T> {}
+        "#,
+        r#"
+        local describe = rtk.query_trait_impls({ crate_name = "fixture", path = { "Describe" } })
+        for _, impl in ipairs(describe) do
+            local ty = impl.for_type.variant_data
+            rtk.emit(ty.location.path[1] .. ":" .. tostring(ty.size) .. "," .. tostring(ty.align) .. ";")
+        end
+        "#,
+    );
+
+    assert_eq!(emitted, "S:8,4;Wrapper:nil,nil;");
+}
+
+#[test]
+fn half_and_quad_float_fields_are_elevated() {
+    let emitted = run_script(
+        "f16_f128",
+        r#"
+        #![feature(f16, f128)]
+
+        pub struct Floats {
+            pub half: f16,
+            pub quad: f128,
+        }
+
+        pub trait Describe {}
+
+        impl Describe for Floats {}
+        "#,
+        r#"
+        local describe = rtk.query_trait_impls({ crate_name = "fixture", path = { "Describe" } })
+        for _, field in ipairs(describe[1].for_type.variant_data.fields) do
+            rtk.emit(field.name.variant_data .. ":" .. field.value.variant_name .. ";")
+        end
+        "#,
+    );
+
+    assert_eq!(emitted, "half:F16;quad:F128;");
+}
+
+#[test]
+fn type_hashes_are_stable_across_queries_and_differ_between_types() {
+    let emitted = run_script(
+        "type_hash",
+        r#"
+        pub struct A;
+        pub struct B;
+
+        pub trait Describe {}
+
+        impl Describe for A {}
+        impl Describe for B {}
+
+        pub fn make() -> A {
+            A
+        }
+        "#,
+        r#"
+        local describe = rtk.query_trait_impls({ crate_name = "fixture", path = { "Describe" } })
+        local a = describe[1].for_type.variant_data
+        local b = describe[2].for_type.variant_data
+        local made = rtk.query_functions({ crate_name = "fixture", path = { "make" } })[1].return_type
+
+        rtk.emit(a.location.path[1] .. "," .. b.location.path[1] .. ";")
+        rtk.emit(tostring(a.type_hash == made.variant_data.type_hash) .. ";")
+        rtk.emit(tostring(a.type_hash ~= b.type_hash))
+        "#,
+    );
+
+    assert_eq!(emitted, "A,B;true;true");
+}
+
+#[test]
+fn result_return_types_expose_their_ok_and_err_types() {
+    let emitted = run_script(
+        "result_return",
+        r#"
+        pub struct MyError;
+
+        pub fn load_user() -> Result<String, MyError> {
+            Ok(String::new())
+        }
+        "#,
+        r#"
+        local f = rtk.query_functions({ crate_name = "fixture", path = { "load_user" } })[1]
+        local result = f.return_type.variant_data
+
+        rtk.emit(f.return_type.variant_name .. ";" .. result.ok.variant_name .. ";")
+        rtk.emit(rtk.fmt_location(result.err.variant_data.location))
+        "#,
+    );
+
+    assert_eq!(emitted, "Result;String;fixture::MyError");
+}
+
+#[test]
+fn tuple_return_types_are_arrays_of_their_elements() {
+    let emitted = run_script(
+        "tuple_return",
+        r#"
+        pub fn pair() -> (String, u32, bool) {
+            (String::new(), 0, false)
+        }
+        "#,
+        r#"
+        local f = rtk.query_functions({ crate_name = "fixture", path = { "pair" } })[1]
+        local elements = f.return_type.variant_data
+
+        rtk.emit(f.return_type.variant_name .. ";" .. #elements .. ":")
+        for _, element in ipairs(elements) do
+            rtk.emit(element.variant_name .. ";")
+        end
+        "#,
+    );
+
+    assert_eq!(emitted, "Tuple;3:String;U32;Bool;");
+}
+
+#[test]
+fn each_invocation_of_a_macro_is_its_own_call() {
+    let emitted = run_script(
+        "macro_calls",
+        r#"
+        macro_rules! route {
+            ($path:expr) => {
+                let _ = ($path, 0);
+            };
+        }
+
+        pub fn a() {
+            route!("/a");
+        }
+
+        pub fn b() {
+            route!("/b");
+        }
+        "#,
+        r#"
+        local calls = rtk.query_macro_calls("route")
+
+        rtk.emit(#calls .. ";")
+        for _, call in ipairs(calls) do
+            rtk.emit(call.args_text .. ";")
+        end
+        rtk.emit(tostring(calls[1].in_item_id ~= calls[2].in_item_id))
+        "#,
+    );
+
+    assert_eq!(emitted, r#"2;"/a";"/b";true"#);
+}
+
+#[test]
+fn const_string_paths_are_elevated_to_their_literal() {
+    let emitted = run_script(
+        "const_route",
+        r#"
+        const USER_PATH: &str = "/user";
+
+        pub fn route(_path: &str) {}
+
+        pub fn register() {
+            route(USER_PATH);
+        }
+        "#,
+        r#"
+        for _, call in ipairs(rtk.query_function_calls({ crate_name = "fixture", path = { "route" } })) do
+            local path = call.args[1]
+            rtk.emit(path.variant_name .. ":" .. path.variant_data)
+        end
+        "#,
+    );
+
+    assert_eq!(emitted, "StringLiteral:/user");
+}
+
+#[test]
+fn enum_variant_arguments_are_elevated_as_literals() {
+    let emitted = run_script(
+        "enum_variant_args",
+        r#"
+        pub enum Status {
+            Active(&'static str),
+            Inactive,
+        }
+
+        pub fn report(_status: Status) {}
+
+        pub fn run() {
+            report(Status::Active("busy"));
+            report(Status::Inactive);
+        }
+        "#,
+        r#"
+        for _, call in ipairs(rtk.query_function_calls({ crate_name = "fixture", path = { "report" } })) do
+            local status = call.args[1]
+            local literal = status.variant_data
+            rtk.emit(status.variant_name .. ":" .. rtk.fmt_location(literal.location) .. "::" .. literal.variant)
+            if literal.inner then
+                rtk.emit("(" .. literal.inner.variant_data .. ")")
+            end
+            rtk.emit(";")
+        end
+        "#,
+    );
+
+    assert_eq!(
+        emitted,
+        "EnumVariantLiteral:fixture::Status::Active(busy);EnumVariantLiteral:fixture::Status::Inactive;"
+    );
+}
+
+#[test]
+fn array_arguments_are_elevated_element_by_element() {
+    let emitted = run_script(
+        "array_args",
+        r#"
+        pub fn tags(_tags: [&str; 2]) {}
+
+        pub fn run() {
+            tags(["users", "admin"]);
+        }
+        "#,
+        r#"
+        for _, call in ipairs(rtk.query_function_calls({ crate_name = "fixture", path = { "tags" } })) do
+            local array = call.args[1]
+            rtk.emit(array.variant_name .. ":")
+            for _, element in ipairs(array.variant_data) do
+                rtk.emit(element.variant_data .. ";")
+            end
+        end
+        "#,
+    );
+
+    assert_eq!(emitted, "ArrayLiteral:users;admin;");
+}
+
+#[test]
+fn struct_literal_arguments_keep_their_fields() {
+    let emitted = run_script(
+        "struct_literal_args",
+        r#"
+        pub struct Config {
+            pub name: &'static str,
+            pub timeout: u32,
+        }
+
+        pub fn configure(_config: Config) {}
+
+        pub fn run() {
+            configure(Config {
+                name: "api",
+                timeout: 30,
+            });
+        }
+        "#,
+        r#"
+        for _, call in ipairs(rtk.query_function_calls({ crate_name = "fixture", path = { "configure" } })) do
+            local config = call.args[1]
+            rtk.emit(config.variant_name .. ":" .. rtk.fmt_location(config.variant_data.location) .. ";")
+            for _, field in ipairs(config.variant_data.fields) do
+                rtk.emit(field.name .. "=" .. field.value.variant_name .. ";")
+            end
+            rtk.emit(config.variant_data.fields[1].value.variant_data)
+        end
+        "#,
+    );
+
+    assert_eq!(
+        emitted,
+        "StructLiteral:fixture::Config;name=StringLiteral;timeout=Type;api"
+    );
+}
+
+#[test]
+fn batched_method_call_queries_answer_under_their_own_keys() {
+    let emitted = run_script(
+        "method_calls_batch",
+        r#"
+        pub struct Client;
+
+        impl Client {
+            pub fn send(&self) {}
+
+            pub fn recv(&self) {}
+        }
+
+        pub fn run(client: &Client) {
+            client.send();
+            client.send();
+            client.recv();
+        }
+        "#,
+        r#"
+        local function method(name)
+            return { location = { crate_name = "fixture", path = { name }, impl_block_number = 0 } }
+        end
+
+        local results = rtk.query_method_calls_batch({ send = method("send"), recv = method("recv") })
+        rtk.emit(#results.send .. "," .. #results.recv)
+        "#,
+    );
+
+    assert_eq!(emitted, "2,1");
+}
+
+#[test]
+fn receiver_type_filter_picks_out_one_receiver_type() {
+    let emitted = run_script(
+        "receiver_type_filter",
+        r#"
+        pub trait Build {
+            fn build(&self);
+        }
+
+        pub struct RequestBuilder;
+        pub struct ResponseBuilder;
+
+        impl Build for RequestBuilder {
+            fn build(&self) {}
+        }
+
+        impl Build for ResponseBuilder {
+            fn build(&self) {}
+        }
+
+        pub fn run(request: &RequestBuilder, response: &ResponseBuilder) {
+            request.build();
+            response.build();
+            response.build();
+        }
+        "#,
+        r#"
+        -- both calls resolve to the trait's method, so only the receiver tells them apart
+        local build = { location = { crate_name = "fixture", path = { "Build", "build" } } }
+        rtk.emit(#rtk.query_method_calls(build) .. ";")
+
+        build.receiver_type_filter = { crate_name = "fixture", path = { "ResponseBuilder" } }
+        rtk.emit(#rtk.query_method_calls(build) .. ";")
+
+        build.receiver_type_filter = { crate_name = "fixture", path = { "RequestBuilder" } }
+        rtk.emit(#rtk.query_method_calls(build))
+        "#,
+    );
+
+    assert_eq!(emitted, "3;2;1");
+}
+
+#[test]
+fn non_zero_integers_wrap_their_primitive() {
+    let emitted = run_script(
+        "non_zero",
+        r#"
+        use std::num::{NonZeroI64, NonZeroU32};
+
+        pub struct Ids {
+            pub user: NonZeroU32,
+            pub offset: NonZeroI64,
+        }
+
+        pub trait Describe {}
+
+        impl Describe for Ids {}
+        "#,
+        r#"
+        local describe = rtk.query_trait_impls({ crate_name = "fixture", path = { "Describe" } })
+        for _, field in ipairs(describe[1].for_type.variant_data.fields) do
+            local value = field.value
+            rtk.emit(field.name.variant_data .. ":" .. value.variant_name .. "(" .. value.variant_data.variant_name .. ");")
+        end
+        "#,
+    );
+
+    assert_eq!(emitted, "user:NonZero(U32);offset:NonZero(I64);");
+}
+
+#[test]
+fn where_clauses_are_pretty_printed() {
+    let emitted = run_script(
+        "where_clause_text",
+        r#"
+        pub fn show<T>(x: T) -> String
+        where
+            T: Clone + std::fmt::Debug,
+        {
+            format!("{:?}", x.clone())
+        }
+
+        pub fn plain() {}
+        "#,
+        r#"
+        local function bounds(name)
+            local f = rtk.query_functions({ crate_name = "fixture", path = { name } })[1]
+            local found = {}
+            for _, predicate in ipairs(f.where_clause_text) do
+                if predicate:find("^T: .*Clone$") then
+                    found[#found + 1] = "Clone"
+                elseif predicate:find("^T: .*Debug$") then
+                    found[#found + 1] = "Debug"
+                end
+            end
+            table.sort(found)
+            return #f.where_clause_text > 0, table.concat(found, ",")
+        end
+
+        for _, name in ipairs({ "show", "plain" }) do
+            local any, found = bounds(name)
+            rtk.emit(name .. ":" .. tostring(any) .. ":" .. found .. ";")
+        end
+        "#,
+    );
+
+    assert_eq!(emitted, "show:true:Clone,Debug;plain:false:;");
+}
+
+#[test]
+fn nested_attribute_args_keep_their_delimiters() {
+    let emitted = run_script(
+        "nested_attribute_args",
+        r#"
+        #[doc(alias("first", "second"))]
+        pub fn aliased() {}
+        "#,
+        r#"
+        local f = rtk.query_functions({ crate_name = "fixture", path = { "aliased" } })[1]
+        for _, attribute in ipairs(f.attributes) do
+            rtk.emit(attribute.name .. ":" .. attribute.value_str .. ";")
+        end
+        "#,
+    );
+
+    assert_eq!(emitted, r#"doc:alias("first","second");"#);
+}
+
+#[test]
+fn grouped_calls_are_those_made_from_the_module() {
+    let emitted = run_script(
+        "grouped_function_calls",
+        r#"
+        pub fn helper(_s: &str) {}
+
+        pub fn other(_s: &str) {}
+
+        pub mod handlers {
+            pub fn run() {
+                crate::helper("first");
+                crate::other("only");
+                crate::helper("second");
+            }
+
+            pub mod nested {
+                pub fn run() {
+                    crate::helper("third");
+                }
+            }
+        }
+
+        pub fn outside() {
+            helper("outside");
+        }
+        "#,
+        r#"
+        local grouped = rtk.query_function_calls_grouped({ crate_name = "fixture", path = { "handlers" } })
+        for _, callee in ipairs({ "fixture::helper", "fixture::other", "fixture::outside" }) do
+            local args = {}
+            for _, call in ipairs(grouped[callee] or {}) do
+                args[#args + 1] = call.args[1].variant_data
+            end
+            rtk.emit(callee .. "=" .. table.concat(args, ",") .. ";")
+        end
+        "#,
+    );
+
+    assert_eq!(
+        emitted,
+        "fixture::helper=first,second,third;fixture::other=only;fixture::outside=;"
+    );
+}
+
+#[test]
+fn impls_for_a_type_list_each_trait_it_implements() {
+    let emitted = run_script(
+        "impls_for_type",
+        r#"
+        #[derive(Clone)]
+        pub struct User;
+
+        pub struct Other;
+
+        pub trait Describe {}
+
+        impl Describe for User {}
+
+        impl Describe for Other {}
+
+        impl std::fmt::Display for User {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                write!(f, "user")
+            }
+        }
+
+        impl User {
+            pub fn new() -> Self {
+                User
+            }
+        }
+        "#,
+        r#"
+        local traits = {}
+        for _, location in ipairs(rtk.query_impls_for_type({ crate_name = "fixture", path = { "User" } })) do
+            traits[#traits + 1] = rtk.fmt_location(location)
+        end
+        table.sort(traits)
+        rtk.emit(table.concat(traits, ";"))
+        "#,
+    );
+
+    assert_eq!(
+        emitted,
+        "core::clone::Clone;core::fmt::Display;fixture::Describe"
+    );
+}
+
+#[test]
+fn closures_are_matched_on_the_variables_they_capture() {
+    let emitted = run_script(
+        "closures_with_capture",
+        r#"
+        pub fn run() -> bool {
+            let limit = 10u32;
+            let check = |x: u32| x < limit;
+            let next = |x: i64| x + 1;
+            check(next(1) as u32)
+        }
+
+        pub fn elsewhere() -> bool {
+            let limit = 10u32;
+            let check = move |x: u32| x < limit;
+            check(1)
+        }
+        "#,
+        r#"
+        local run = { crate_name = "fixture", path = { "run" } }
+        for _, closure in ipairs(rtk.query_closures_with_capture(run, "limit")) do
+            local args = {}
+            for _, arg in ipairs(closure.args) do
+                args[#args + 1] = arg.variant_name
+            end
+            rtk.emit(table.concat(args, ",") .. "->" .. closure.return_type.variant_name .. ";")
+        end
+        "#,
+    );
+
+    assert_eq!(emitted, "U32->Bool;");
+}
+
+#[test]
+fn modules_are_listed_from_the_crate_root_down() {
+    let emitted = run_script(
+        "query_modules",
+        r#"
+        pub mod a {
+            pub mod b {}
+        }
+
+        mod c {}
+        "#,
+        r#"
+        for _, module in ipairs(rtk.query_modules()) do
+            rtk.emit(rtk.fmt_location(module) .. ";")
+        end
+        "#,
+    );
+
+    assert_eq!(emitted, "fixture;fixture::a;fixture::a::b;fixture::c;");
+}
+
+#[test]
+fn functions_in_a_module_include_its_submodules() {
+    let emitted = run_script(
+        "functions_in_module",
+        r#"
+        pub mod api {
+            pub fn list() {}
+
+            pub mod users {
+                pub fn get(_id: u32) {}
+            }
+        }
+
+        pub fn outside() {}
+        "#,
+        r#"
+        local api = { crate_name = "fixture", path = { "api" } }
+        for _, f in ipairs(rtk.query_functions_in_module(api)) do
+            rtk.emit(rtk.fmt_location(f.location) .. "(" .. #f.args_struct.fields .. ");")
+        end
+        "#,
+    );
+
+    assert_eq!(
+        emitted,
+        "fixture::api::list(0);fixture::api::users::get(1);"
+    );
+}
+
+#[test]
+fn functions_across_impl_blocks_come_back_as_overloads() {
+    let emitted = run_script(
+        "function_overloads",
+        r#"
+        pub struct Wrapper<T>(pub T);
+
+        impl Wrapper<u8> {
+            pub fn new() -> Self {
+                Wrapper(0)
+            }
+        }
+
+        impl Wrapper<u32> {
+            pub fn new(a: u32, b: u32) -> Self {
+                Wrapper(a + b)
+            }
+        }
+        "#,
+        r#"
+        local new = { crate_name = "fixture", path = { "new" } }
+        local all = rtk.query_functions(new)
+        rtk.emit(#all .. ":" .. #all[1].args_struct.fields .. ":" .. #all[1].overloads .. ":")
+        rtk.emit(#all[1].overloads[1].args_struct.fields .. ":" .. rtk.count_functions(new) .. "|")
+
+        local second = rtk.query_functions({ crate_name = "fixture", path = { "new" }, impl_block_number = 1 })
+        rtk.emit(#second .. ":" .. #second[1].args_struct.fields .. ":" .. #second[1].overloads)
+        "#,
+    );
+
+    assert_eq!(emitted, "1:0:1:2:1|1:2:0");
+}
+
+#[test]
+fn trait_defs_list_methods_and_associated_items() {
+    let emitted = run_script(
+        "trait_defs",
+        r#"
+        /// Somewhere to keep things
+        pub trait Store {
+            type Key;
+
+            const NAME: &'static str = "store";
+            const LIMIT: u32;
+
+            fn get(&self, key: u32) -> String;
+
+            fn len(&self) -> u32 {
+                0
+            }
+        }
+        "#,
+        r#"
+        local store = rtk.query_trait_defs({ crate_name = "fixture", path = { "Store" } })[1]
+        rtk.emit(store.doc_comment .. ";")
+
+        for _, f in ipairs(store.functions) do
+            rtk.emit(rtk.fmt_location(f.location) .. "->" .. f.return_type.variant_name .. ";")
+        end
+
+        rtk.emit(table.concat(store.associated_types, ",") .. ";")
+
+        for _, item in ipairs(store.associated_consts) do
+            local value = item.value and item.value.variant_data or "nil"
+            rtk.emit(rtk.fmt_location(item.location) .. "=" .. value .. ";")
+        end
+        "#,
+    );
+
+    assert_eq!(
+        emitted,
+        " Somewhere to keep things;fixture::Store::get->String;fixture::Store::len->U32;Key;\
+         fixture::Store::NAME=store;fixture::Store::LIMIT=nil;"
+    );
+}
+
+#[test]
+fn all_trait_impls_include_those_in_dependencies() {
+    let emitted = run_script_over(
+        "all_trait_impls",
+        &[
+            (
+                "Cargo.toml",
+                r#"
+                [package]
+                name = "fixture"
+                version = "0.1.0"
+                edition = "2024"
+
+                [dependencies]
+                shared = { path = "shared" }
+
+                [workspace]
+                members = ["shared"]
+                "#,
+            ),
+            (
+                "src/lib.rs",
+                r#"
+                pub struct Label;
+
+                impl shared::Named for Label {
+                    fn name(&self) -> String {
+                        String::from("label")
+                    }
+                }
+                "#,
+            ),
+            (
+                "shared/Cargo.toml",
+                r#"
+                [package]
+                name = "shared"
+                version = "0.1.0"
+                edition = "2024"
+                "#,
+            ),
+            (
+                "shared/src/lib.rs",
+                r#"
+                pub trait Named {
+                    fn name(&self) -> String;
+                }
+
+                impl Named for u32 {
+                    fn name(&self) -> String {
+                        self.to_string()
+                    }
+                }
+
+                impl Named for bool {
+                    fn name(&self) -> String {
+                        self.to_string()
+                    }
+                }
+                "#,
+            ),
+        ],
+        r#"
+        local named = { crate_name = "shared", path = { "Named" } }
+
+        local for_types = {}
+        for _, impl in ipairs(rtk.query_all_trait_impls(named)) do
+            for_types[#for_types + 1] = impl.for_type.variant_name .. "(" .. #impl.functions .. ")"
+        end
+        table.sort(for_types)
+        rtk.emit(table.concat(for_types, ",") .. ";")
+        rtk.emit(#rtk.query_trait_impls(named))
+        "#,
+        &[],
+    );
+
+    assert_eq!(emitted, "Bool(1),Struct(1),U32(1);1");
+}
+
+#[test]
+fn constants_are_matched_on_their_type() {
+    let emitted = run_script(
+        "constants_by_type",
+        r#"
+        pub struct ErrorCode(pub u16);
+
+        pub const NOT_FOUND: ErrorCode = ErrorCode(404);
+
+        /// Not allowed here
+        pub const FORBIDDEN: ErrorCode = ErrorCode(403);
+
+        pub const MAX_RETRIES: u32 = 3;
+
+        pub const NAME: &str = "api";
+        "#,
+        r#"
+        local error_code = {
+            variant_name = "Struct",
+            variant_data = {
+                location = { crate_name = "fixture", path = { "ErrorCode" } },
+                fields = {},
+                attributes = {},
+                kind = { variant_name = "Tuple" },
+                derives = {},
+                is_non_exhaustive = false,
+            },
+        }
+        for _, filter in ipairs({ error_code, { variant_name = "U32" }, { variant_name = "String" } }) do
+            for _, c in ipairs(rtk.query_constants_by_type(filter)) do
+                local value = c.value and c.value.variant_name == "StringLiteral" and c.value.variant_data or "-"
+                rtk.emit(c.location.path[1] .. ":" .. tostring(c.doc_comment) .. ":" .. value .. ";")
+            end
+        end
+        "#,
+    );
+
+    assert_eq!(
+        emitted,
+        "NOT_FOUND:nil:-;FORBIDDEN: Not allowed here:-;MAX_RETRIES:nil:-;NAME:nil:api;"
+    );
+}
+
+#[test]
+fn serde_fields_follow_renames_and_skips() {
+    // a stand-in for serde's derive, which only has to accept the `serde` helper attribute
+    let emitted = run_script_over(
+        "serde_fields",
+        &[
+            (
+                "Cargo.toml",
+                r#"
+                [package]
+                name = "fixture"
+                version = "0.1.0"
+                edition = "2024"
+
+                [dependencies]
+                serde = { path = "serde" }
+
+                [workspace]
+                members = ["serde"]
+                "#,
+            ),
+            (
+                "src/lib.rs",
+                r#"
+                use serde::Serialize;
+
+                #[derive(Serialize)]
+                #[serde(rename_all = "camelCase")]
+                pub struct User {
+                    pub user_id: u32,
+                    #[serde(rename = "mail")]
+                    pub email_address: String,
+                    #[serde(skip)]
+                    pub password_hash: String,
+                    #[serde(skip_serializing)]
+                    pub session: String,
+                    pub display_name: String,
+                }
+                "#,
+            ),
+            (
+                "serde/Cargo.toml",
+                r#"
+                [package]
+                name = "serde"
+                version = "0.1.0"
+                edition = "2024"
+
+                [lib]
+                proc-macro = true
+                "#,
+            ),
+            (
+                "serde/src/lib.rs",
+                r#"
+                use proc_macro::TokenStream;
+
+                #[proc_macro_derive(Serialize, attributes(serde))]
+                pub fn derive_serialize(_item: TokenStream) -> TokenStream {
+                    TokenStream::new()
+                }
+                "#,
+            ),
+        ],
+        r#"
+        for _, field in ipairs(rtk.query_serde_fields({ crate_name = "fixture", path = { "User" } })) do
+            rtk.emit(field.name .. "=" .. field.field.name.variant_data .. ";")
+        end
+        "#,
+        &[],
+    );
+
+    assert_eq!(
+        emitted,
+        "userId=user_id;mail=email_address;displayName=display_name;"
+    );
+}
+
+#[test]
+fn calls_are_flagged_when_made_from_test_modules() {
+    let emitted = run_script_over(
+        "calls_in_test_modules",
+        &[
+            ("Cargo.toml", FIXTURE_MANIFEST),
+            (
+                "src/lib.rs",
+                r#"
+                pub struct Client;
+
+                impl Client {
+                    pub fn send(&self) {}
+                }
+
+                pub fn connect() -> Client {
+                    Client
+                }
+
+                pub fn run() {
+                    connect().send();
+                }
+
+                #[cfg(test)]
+                mod tests {
+                    #[test]
+                    fn connects() {
+                        super::connect().send();
+                    }
+                }
+                "#,
+            ),
+        ],
+        r#"
+        for _, fc in ipairs(rtk.query_function_calls({ crate_name = "fixture", path = { "connect" } })) do
+            rtk.emit(tostring(fc.is_in_test_module) .. ";")
+        end
+
+        local send = { location = { crate_name = "fixture", path = { "send" }, impl_block_number = 0 } }
+        for _, mc in ipairs(rtk.query_method_calls(send)) do
+            rtk.emit(tostring(mc.is_in_test_module) .. ";")
+        end
+        "#,
+        &["--tests"],
+    );
+
+    assert_eq!(emitted, "false;true;false;true;");
+}
+
+#[test]
+fn trait_impls_are_filtered_by_their_generic_args() {
+    let emitted = run_script(
+        "trait_generic_args",
+        r#"
+        pub struct Id(pub u32);
+
+        pub struct Raw;
+
+        impl From<u32> for Id {
+            fn from(value: u32) -> Self {
+                Id(value)
+            }
+        }
+
+        impl From<String> for Id {
+            fn from(value: String) -> Self {
+                Id(value.len() as u32)
+            }
+        }
+
+        impl From<Raw> for Id {
+            fn from(_value: Raw) -> Self {
+                Id(0)
+            }
+        }
+        "#,
+        r#"
+        local from = { crate_name = "core", path = { "convert", "From" } }
+
+        local args = {}
+        for _, impl in ipairs(rtk.query_trait_impls(from)) do
+            args[#args + 1] = impl.trait_generic_args[1].variant_name
+        end
+        rtk.emit(table.concat(args, ",") .. ";")
+
+        local raw = {
+            variant_name = "Struct",
+            variant_data = {
+                location = { crate_name = "fixture", path = { "Raw" } },
+                fields = {},
+                attributes = {},
+                kind = { variant_name = "Unit" },
+                derives = {},
+                is_non_exhaustive = false,
+            },
+        }
+        rtk.emit(#rtk.query_trait_impls(from, { { variant_name = "String" } }) .. ";")
+        rtk.emit(#rtk.query_trait_impls(from, { raw }) .. ";")
+        rtk.emit(#rtk.query_trait_impls(from, { { variant_name = "Bool" } }))
+        "#,
+    );
+
+    assert_eq!(emitted, "U32,String,Struct;1;1;0");
+}
@@ -0,0 +1,273 @@
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, Output},
+    sync::mpsc,
+};
+
+use anyhow::Context;
+use serde::Deserialize;
+
+/// An `rtk.toml`, listing scripts to run in one go, e.g. one for each crate of a workspace:
+///
+/// ```toml
+/// [[script]]
+/// script = "api.lua"
+/// out_file = "api.ts"
+/// cargo_args = ["-p", "api"]
+/// ```
+#[derive(Debug, Deserialize, PartialEq)]
+pub struct Config {
+    #[serde(default, rename = "script")]
+    pub scripts: Vec<ScriptEntry>,
+}
+
+/// A `[[script]]` entry of an `rtk.toml`, run the same as the CLI's own `--script`, `--out-file`
+/// and trailing cargo args. Once read, `script` and `out_file` are relative to the directory of the
+/// `rtk.toml` rather than to wherever rtk was run from
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct ScriptEntry {
+    pub script: PathBuf,
+    pub out_file: PathBuf,
+    #[serde(default)]
+    pub cargo_args: Vec<String>,
+}
+
+impl ScriptEntry {
+    /// How the entry is referred to in progress updates and in front of its output
+    pub fn name(&self) -> String {
+        self.script.display().to_string()
+    }
+}
+
+impl Config {
+    pub fn read(path: &Path) -> anyhow::Result<Self> {
+        let src = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read config file '{}'", path.display()))?;
+
+        let mut config: Self = toml::from_str(&src)
+            .with_context(|| format!("failed to parse config file '{}'", path.display()))?;
+
+        // joining leaves absolute paths as they are
+        let config_dir = path.parent().unwrap_or(Path::new(""));
+        for entry in &mut config.scripts {
+            entry.script = config_dir.join(&entry.script);
+            entry.out_file = config_dir.join(&entry.out_file);
+        }
+
+        Ok(config)
+    }
+}
+
+/// Runs the prepared `cargo check` of every entry one after the other, with their output going
+/// straight to the terminal. Like [`run_parallel`], a failing entry doesn't stop the rest from
+/// running, and every failure is reported once they all have
+pub fn run_sequential(commands: Vec<(String, Command)>) -> anyhow::Result<()> {
+    let total = commands.len();
+
+    let mut failed = vec![];
+    for (name, mut cmd) in commands {
+        log::info!("running script '{name}'");
+
+        match cmd.status() {
+            Ok(status) if status.success() => {}
+            Ok(status) => {
+                eprintln!("[{name}] failed: {status}");
+                failed.push(name);
+            }
+            Err(e) => {
+                eprintln!("[{name}] failed: failed to execute cargo check: {e}");
+                failed.push(name);
+            }
+        }
+    }
+
+    bail_if_any_failed(&failed, total)
+}
+
+/// Runs the prepared `cargo check` of every entry at once, printing each line of their output
+/// behind the entry's name once it finishes. Every entry is waited on before any failure is
+/// reported, so one failing script doesn't hide the output of the rest
+pub fn run_parallel(commands: Vec<(String, Command)>) -> anyhow::Result<()> {
+    let total = commands.len();
+    let (tx, rx) = mpsc::channel();
+
+    for (name, mut cmd) in commands {
+        eprintln!("[{name}] running");
+
+        let tx = tx.clone();
+        std::thread::spawn(move || {
+            let output = cmd.output();
+            // the receiver outlives every sender, so this can't fail
+            let _ = tx.send((name, output));
+        });
+    }
+    drop(tx);
+
+    let mut failed = vec![];
+    for (completed, (name, output)) in rx.into_iter().enumerate() {
+        let status = match output {
+            Ok(Output {
+                status,
+                stdout,
+                stderr,
+            }) => {
+                let _ = std::io::stdout().write_all(prefix_lines(&name, &stdout).as_bytes());
+                let _ = std::io::stderr().write_all(prefix_lines(&name, &stderr).as_bytes());
+
+                if status.success() {
+                    Ok(())
+                } else {
+                    Err(status.to_string())
+                }
+            }
+            Err(e) => Err(format!("failed to execute cargo check: {e}")),
+        };
+
+        match status {
+            Ok(()) => eprintln!("[{name}] finished ({}/{total})", completed + 1),
+            Err(reason) => {
+                eprintln!("[{name}] failed: {reason} ({}/{total})", completed + 1);
+                failed.push(name);
+            }
+        }
+    }
+
+    bail_if_any_failed(&failed, total)
+}
+
+/// Fails with the names of the `failed` entries out of `total`, if there are any
+fn bail_if_any_failed(failed: &[String], total: usize) -> anyhow::Result<()> {
+    if !failed.is_empty() {
+        anyhow::bail!(
+            "{} of {total} script(s) failed: {}",
+            failed.len(),
+            failed.join(", ")
+        );
+    }
+
+    Ok(())
+}
+
+/// Puts `[name] ` in front of every line of `output`
+fn prefix_lines(name: &str, output: &[u8]) -> String {
+    String::from_utf8_lossy(output)
+        .lines()
+        .map(|line| format!("[{name}] {line}\n"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn config_lists_every_script_entry() {
+        let config: Config = toml::from_str(
+            r#"
+            [[script]]
+            script = "api.lua"
+            out_file = "api.ts"
+            cargo_args = ["-p", "api"]
+
+            [[script]]
+            script = "admin.lua"
+            out_file = "admin.ts"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.scripts,
+            [
+                ScriptEntry {
+                    script: "api.lua".into(),
+                    out_file: "api.ts".into(),
+                    cargo_args: vec!["-p".to_string(), "api".to_string()],
+                },
+                ScriptEntry {
+                    script: "admin.lua".into(),
+                    out_file: "admin.ts".into(),
+                    cargo_args: vec![],
+                },
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn read_resolves_paths_against_the_config_directory() {
+        let dir = std::env::temp_dir().join(format!("rtk-config-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let config_path = dir.join("rtk.toml");
+        std::fs::write(
+            &config_path,
+            r#"
+            [[script]]
+            script = "scripts/api.lua"
+            out_file = "/tmp/api.ts"
+            "#,
+        )
+        .unwrap();
+
+        let config = Config::read(&config_path);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let entry = &config.unwrap().scripts[0];
+        assert_eq!(entry.script, dir.join("scripts/api.lua"));
+        assert_eq!(entry.out_file, Path::new("/tmp/api.ts"));
+    }
+
+    #[test]
+    fn prefix_lines_marks_every_line() {
+        assert_eq!(
+            prefix_lines("api.lua", b"Checking api\nFinished\n"),
+            "[api.lua] Checking api\n[api.lua] Finished\n"
+        );
+        assert_eq!(prefix_lines("api.lua", b""), "");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn run_parallel_waits_for_every_command_before_failing() {
+        let command = |script: &str| {
+            let mut cmd = Command::new("sh");
+            cmd.args(["-c", script]);
+            cmd
+        };
+
+        let err = run_parallel(vec![
+            ("failing".to_string(), command("exit 1")),
+            ("passing".to_string(), command("echo ok")),
+        ])
+        .unwrap_err();
+
+        assert_eq!(err.to_string(), "1 of 2 script(s) failed: failing");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn run_sequential_runs_every_command_before_failing() {
+        let marker = std::env::temp_dir().join(format!("rtk-sequential-{}", std::process::id()));
+        let command = |script: &str| {
+            let mut cmd = Command::new("sh");
+            cmd.args(["-c", script]);
+            cmd
+        };
+
+        let err = run_sequential(vec![
+            ("failing".to_string(), command("exit 1")),
+            (
+                "passing".to_string(),
+                command(&format!("touch '{}'", marker.display())),
+            ),
+        ])
+        .unwrap_err();
+
+        let ran_after_failure = marker.exists();
+        let _ = std::fs::remove_file(&marker);
+
+        assert_eq!(err.to_string(), "1 of 2 script(s) failed: failing");
+        assert!(ran_after_failure);
+    }
+}
@@ -0,0 +1,563 @@
+//! The RTK pipeline behind the `rtk` CLI, for running it from your own code (such as a build
+//! script) rather than shelling out to the binary.
+
+mod config;
+mod luack;
+mod versioning;
+
+use anyhow::Context;
+use clap::{Parser, Subcommand};
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// The name of the driver binary `cargo install` provides
+pub const DRIVER_NAME: &str = "rtk-rustc-driver";
+
+/// RTK CLI. Query your Rust types, and emit bindings for anything with no macros!
+#[derive(Parser, Debug, Clone)]
+#[command(
+    version,
+    about,
+    long_about = None,
+    subcommand_negates_reqs = true,
+    args_conflicts_with_subcommands = true
+)]
+pub struct Args {
+    #[command(subcommand)]
+    command: Option<Commands>,
+
+    /// The input Lua script file to use for the RTK driver.
+    #[arg(short, long, required_unless_present = "config")]
+    script: Option<PathBuf>,
+
+    /// The output file for where calls to `rtk.emit` in the Lua script will write to.
+    #[arg(short, long, required_unless_present = "config")]
+    out_file: Option<PathBuf>,
+
+    /// An `rtk.toml` to run every `[[script]]` entry of, in place of a single `--script` and
+    /// `--out-file`. Each entry gives its own `script`, `out_file` and `cargo_args`, with the paths
+    /// relative to the `rtk.toml`.
+    #[arg(long, conflicts_with_all = ["script", "out_file"])]
+    config: Option<PathBuf>,
+
+    /// Run the `--config` entries concurrently rather than one after the other. Each entry's
+    /// output is printed once it finishes, with the name of its script in front of every line.
+    /// Entries sharing a target directory wait on each other's cargo build lock, so give each
+    /// its own `--target-dir` in its `cargo_args` for them to actually run at once.
+    #[arg(long, requires = "config")]
+    parallel: bool,
+
+    /// The rustup toolchain to run `cargo` with, e.g. `nightly-2025-02-25`. This is used both to
+    /// install the driver and to run the analysis, so the two are always compiled alike.
+    #[arg(long)]
+    toolchain: Option<String>,
+
+    /// Path to the `Cargo.toml` of the crate to analyze, forwarded to `cargo check`. Defaults to
+    /// the manifest cargo finds from the current directory.
+    #[arg(long)]
+    manifest_path: Option<PathBuf>,
+
+    /// Silence cargo's own progress output. Diagnostics from the script and the driver are still
+    /// printed to stderr, and the output file is written the same either way.
+    #[arg(short, long)]
+    quiet: bool,
+
+    /// Fail the run if the script logs any warnings with `rtk.warn`, e.g. to keep CI strict. The
+    /// run stops before anything is written to the output file.
+    #[arg(long)]
+    deny_warnings: bool,
+
+    /// Additional arguments to pass to `cargo`. RTK wraps `cargo check`, so you can forward any
+    /// additional arguments here such as `-p <your-crate>` to only target a specific crate.
+    #[arg(last = true)]
+    cargo_args: Vec<String>,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+enum Commands {
+    /// Check a Lua script for errors without installing the driver or running cargo, e.g. to
+    /// validate it quickly in CI. Syntax errors are reported as `<script>:<line>: <message>`.
+    Check {
+        /// The Lua script file to check.
+        #[arg(short, long)]
+        script: PathBuf,
+    },
+}
+
+/// Runs the Lua script at `script_path` over the crate cargo finds from the current directory,
+/// writing everything it emits to `out_path`. The driver the script asks for is installed first
+/// if it isn't already, and `cargo_args` are forwarded to `cargo check` as with the CLI.
+pub fn run_rtk(
+    script_path: impl AsRef<Path>,
+    out_path: impl AsRef<Path>,
+    cargo_args: &[String],
+) -> anyhow::Result<()> {
+    run(&Args {
+        command: None,
+        script: Some(script_path.as_ref().to_path_buf()),
+        out_file: Some(out_path.as_ref().to_path_buf()),
+        config: None,
+        parallel: false,
+        toolchain: None,
+        manifest_path: None,
+        quiet: false,
+        deny_warnings: false,
+        cargo_args: cargo_args.to_vec(),
+    })
+}
+
+/// Checks the Lua script at `script_path` for errors the way `rtk check` does, without
+/// installing the driver or running cargo. The script is run with every query coming back empty,
+/// so only syntax errors, fatal errors and setting the version more than once are reported
+pub fn check_script(script_path: impl AsRef<Path>) -> anyhow::Result<()> {
+    let script_path = script_path.as_ref();
+    let script_src =
+        std::fs::read_to_string(script_path).context("failed to read input Lua script")?;
+
+    luack::ck_lua(&script_src, &script_path.display().to_string())
+}
+
+/// Runs the pipeline with everything the CLI accepts
+pub fn run(args: &Args) -> anyhow::Result<()> {
+    run_with_env(args, &[])
+}
+
+/// Runs the pipeline with `env` set on every cargo command on top of the environment of this
+/// process. The driver is also looked for under `env`'s `CARGO_INSTALL_ROOT` or `CARGO_HOME`, if
+/// it gives either
+fn run_with_env(args: &Args, env: &[(&str, OsString)]) -> anyhow::Result<()> {
+    if let Some(Commands::Check { script }) = &args.command {
+        return check_script(script);
+    }
+
+    if let Some(config_path) = &args.config {
+        return run_config(args, config_path, env);
+    }
+
+    let status = cargo_check_for_script(args, env)?
+        .status()
+        .context("failed to execute cargo check")?;
+    if !status.success() {
+        anyhow::bail!("cargo check failed with status: {status}");
+    }
+
+    Ok(())
+}
+
+/// Runs every entry of the `rtk.toml` at `config_path`, one after the other unless `--parallel`
+/// was given. The drivers are all installed before any script runs, as installing the same driver
+/// twice at once would race. Either way every entry is run before any failure is reported
+fn run_config(args: &Args, config_path: &Path, env: &[(&str, OsString)]) -> anyhow::Result<()> {
+    let config = config::Config::read(config_path)?;
+
+    let commands = config
+        .scripts
+        .iter()
+        .map(|entry| {
+            let entry_args = Args {
+                script: Some(entry.script.clone()),
+                out_file: Some(entry.out_file.clone()),
+                config: None,
+                parallel: false,
+                cargo_args: entry.cargo_args.clone(),
+                ..args.clone()
+            };
+            let cmd = cargo_check_for_script(&entry_args, env)
+                .with_context(|| format!("failed to prepare script '{}'", entry.name()))?;
+
+            Ok((entry.name(), cmd))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    if args.parallel {
+        config::run_parallel(commands)
+    } else {
+        config::run_sequential(commands)
+    }
+}
+
+/// Installs the driver the script at `args.script` asks for, returning the `cargo check` that runs
+/// it
+fn cargo_check_for_script(args: &Args, env: &[(&str, OsString)]) -> anyhow::Result<Command> {
+    let script_path = args
+        .script
+        .as_ref()
+        .context("an input Lua script is required")?;
+    let script_src =
+        std::fs::read_to_string(script_path).context("failed to read input Lua script")?;
+
+    let (driver_release_version, driver_debug_version) =
+        versioning::desired_version_for_script(&script_src)
+            .context("failed to extract desired version from Lua script")?;
+
+    let driver_version = if is_release_profile(&args.cargo_args) {
+        driver_release_version
+    } else {
+        driver_debug_version.unwrap_or(driver_release_version)
+    };
+
+    versioning::install_rtk_rustc_driver(driver_version, args.toolchain.as_deref(), env)
+        .context("failed to install RTK Rustc driver")?;
+
+    log::info!("driver version provisioned / already installed, proceeding with cargo execution");
+
+    let driver_path = versioning::installed_rtk_rustc_driver_path(env)
+        .context("failed to locate the installed RTK Rustc driver")?;
+
+    Ok(cargo_check_command(args, &driver_path, env))
+}
+
+/// Whether the forwarded cargo args build the crate with a release-like profile, either through
+/// `--release` or a `--profile` other than the debug ones. Without either cargo uses `dev`
+fn is_release_profile(cargo_args: &[String]) -> bool {
+    let mut args = cargo_args
+        .iter()
+        .map(String::as_str)
+        // anything after `--` is for rustc rather than cargo
+        .take_while(|arg| *arg != "--");
+
+    let mut is_release = false;
+    while let Some(arg) = args.next() {
+        let profile = match arg {
+            "--release" | "-r" => Some("release"),
+            "--profile" => args.next(),
+            _ => arg.strip_prefix("--profile="),
+        };
+
+        if let Some(profile) = profile {
+            is_release = !matches!(profile, "dev" | "test");
+        }
+    }
+
+    is_release
+}
+
+/// The `cargo check` command that runs the driver over the crate
+fn cargo_check_command(args: &Args, driver_path: &Path, env: &[(&str, OsString)]) -> Command {
+    let mut cmd = cargo_command(args.toolchain.as_deref(), env);
+    cmd.env("RUSTC_WRAPPER", driver_path);
+
+    // clap requires both unless a subcommand is given, and subcommands never run cargo
+    if let Some(script) = &args.script {
+        cmd.env("RTK_LUA_SCRIPT", script);
+    }
+    if let Some(out_file) = &args.out_file {
+        cmd.env("RTK_OUT_FILE", out_file);
+    }
+
+    cmd.arg("check");
+
+    if args.deny_warnings {
+        cmd.env("RTK_DENY_WARNINGS", "1");
+    }
+
+    if args.quiet {
+        cmd.arg("--quiet");
+    }
+
+    if let Some(manifest_path) = &args.manifest_path {
+        cmd.arg("--manifest-path").arg(manifest_path);
+    }
+
+    cmd.args(&args.cargo_args);
+
+    cmd
+}
+
+/// A `cargo` command, run through the given rustup toolchain if there is one. `env` is set on top of
+/// the environment of this process, and a `PATH` in it is also where `cargo` itself is looked for
+fn cargo_command(toolchain: Option<&str>, env: &[(&str, OsString)]) -> Command {
+    let mut cmd = Command::new("cargo");
+    cmd.envs(env.iter().cloned());
+    if let Some(toolchain) = toolchain {
+        cmd.arg(format!("+{toolchain}"));
+    }
+
+    cmd
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cargo_command_uses_toolchain() {
+        let cmd = cargo_command(Some("nightly-2025-02-25"), &[]);
+        let args = cmd.get_args().collect::<Vec<_>>();
+
+        assert_eq!(args, ["+nightly-2025-02-25"]);
+    }
+
+    #[test]
+    fn cargo_command_without_toolchain() {
+        let cmd = cargo_command(None, &[]);
+        assert_eq!(cmd.get_args().count(), 0);
+    }
+
+    #[test]
+    fn release_profile_is_inferred_from_cargo_args() {
+        let is_release = |args: &[&str]| {
+            is_release_profile(&args.iter().map(|s| s.to_string()).collect::<Vec<_>>())
+        };
+
+        assert!(!is_release(&[]));
+        assert!(!is_release(&["-p", "api"]));
+        assert!(is_release(&["--release"]));
+        assert!(is_release(&["-p", "api", "-r"]));
+        assert!(is_release(&["--profile", "release"]));
+        assert!(is_release(&["--profile=production"]));
+        assert!(!is_release(&["--profile", "dev"]));
+        assert!(!is_release(&["--profile=test"]));
+        assert!(!is_release(&["--", "--release"]));
+    }
+
+    #[test]
+    fn cargo_check_command_forwards_manifest_path() {
+        let args = Args::parse_from([
+            "rtk",
+            "--script",
+            "bindings.lua",
+            "--out-file",
+            "bindings.ts",
+            "--manifest-path",
+            "server/Cargo.toml",
+            "--",
+            "-p",
+            "api",
+        ]);
+
+        let cmd = cargo_check_command(
+            &args,
+            Path::new("/home/jack/.cargo/bin/rtk-rustc-driver"),
+            &[],
+        );
+        let args = cmd.get_args().collect::<Vec<_>>();
+
+        assert_eq!(
+            args,
+            ["check", "--manifest-path", "server/Cargo.toml", "-p", "api"]
+        );
+    }
+
+    #[test]
+    fn cargo_check_command_forwards_quiet() {
+        let args = Args::parse_from([
+            "rtk",
+            "--script",
+            "bindings.lua",
+            "--out-file",
+            "bindings.ts",
+            "--quiet",
+            "--",
+            "-p",
+            "api",
+        ]);
+
+        let cmd = cargo_check_command(
+            &args,
+            Path::new("/home/jack/.cargo/bin/rtk-rustc-driver"),
+            &[],
+        );
+        let args = cmd.get_args().collect::<Vec<_>>();
+
+        assert_eq!(args, ["check", "--quiet", "-p", "api"]);
+    }
+
+    #[test]
+    fn cargo_check_command_wraps_rustc_with_the_driver_path() {
+        let args = Args::parse_from([
+            "rtk",
+            "--script",
+            "bindings.lua",
+            "--out-file",
+            "bindings.ts",
+        ]);
+        let driver_path = Path::new("/home/jack/.cargo/bin/rtk-rustc-driver");
+
+        let cmd = cargo_check_command(&args, driver_path, &[]);
+        let rustc_wrapper = cmd
+            .get_envs()
+            .find(|(key, _)| *key == "RUSTC_WRAPPER")
+            .and_then(|(_, value)| value);
+
+        assert_eq!(rustc_wrapper, Some(driver_path.as_os_str()));
+    }
+
+    /// Runs the whole pipeline with `cargo` shimmed out by a script that records how it was
+    /// invoked and then runs `shim_body`, so nothing is installed or compiled. The shim is put on
+    /// the `PATH` of the cargo commands alone, leaving the environment of the tests untouched.
+    /// Returns the result of the run along with each invocation
+    #[cfg(unix)]
+    fn run_with_cargo_shim(test_name: &str, shim_body: &str) -> (anyhow::Result<()>, Vec<String>) {
+        use std::{ffi::OsStr, os::unix::fs::PermissionsExt};
+
+        let dir =
+            std::env::temp_dir().join(format!("rtk-run-test-{}-{test_name}", std::process::id()));
+        let bin_dir = dir.join("bin");
+        std::fs::create_dir_all(&bin_dir).unwrap();
+
+        let invocations = dir.join("invocations");
+        let cargo_shim = dir.join("cargo");
+        std::fs::write(
+            &cargo_shim,
+            format!(
+                "#!/bin/sh\necho \"$@\" >> '{}'\n{shim_body}\nexit 0\n",
+                invocations.display()
+            ),
+        )
+        .unwrap();
+        std::fs::set_permissions(&cargo_shim, std::fs::Permissions::from_mode(0o755)).unwrap();
+        std::fs::write(bin_dir.join(DRIVER_NAME), "").unwrap();
+
+        let script = dir.join("bindings.lua");
+        std::fs::write(&script, r#"rtk.version("1.2.3")"#).unwrap();
+        let out_file = dir.join("bindings.ts");
+
+        let path = std::env::var_os("PATH").unwrap_or_default();
+        let mut paths = vec![dir.clone()];
+        paths.extend(std::env::split_paths(&path));
+        let env = [
+            ("PATH", std::env::join_paths(paths).unwrap()),
+            ("CARGO_INSTALL_ROOT", dir.clone().into_os_string()),
+        ];
+
+        let args = Args::parse_from([
+            OsStr::new("rtk"),
+            OsStr::new("--script"),
+            script.as_os_str(),
+            OsStr::new("--out-file"),
+            out_file.as_os_str(),
+            OsStr::new("--"),
+            OsStr::new("-p"),
+            OsStr::new("foo"),
+        ]);
+        let result = run_with_env(&args, &env);
+
+        let invocations = std::fs::read_to_string(&invocations).unwrap_or_default();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        (result, invocations.lines().map(str::to_string).collect())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn run_installs_the_driver_and_checks_the_crate() {
+        let (result, invocations) = run_with_cargo_shim("passing", "");
+
+        result.unwrap();
+        assert_eq!(invocations.len(), 2);
+        assert!(invocations[0].starts_with("install"));
+        assert_eq!(invocations[1], "check -p foo");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn run_fails_when_cargo_check_fails() {
+        let (result, invocations) =
+            run_with_cargo_shim("failing_check", r#"[ "$1" = check ] && exit 101"#);
+
+        let err = result.unwrap_err().to_string();
+        assert!(
+            err.starts_with("cargo check failed"),
+            "unexpected error: {err}"
+        );
+        assert_eq!(invocations.len(), 2);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn run_stops_when_the_driver_fails_to_install() {
+        let (result, invocations) =
+            run_with_cargo_shim("failing_install", r#"[ "$1" = install ] && exit 1"#);
+
+        assert!(result.is_err());
+        assert_eq!(invocations.len(), 1);
+    }
+
+    #[test]
+    fn check_subcommand_only_needs_a_script() {
+        let args = Args::try_parse_from(["rtk", "check", "--script", "bindings.lua"]).unwrap();
+        assert!(matches!(
+            args.command,
+            Some(Commands::Check { script }) if script == Path::new("bindings.lua")
+        ));
+
+        assert!(Args::try_parse_from(["rtk", "--script", "bindings.lua"]).is_err());
+    }
+
+    #[test]
+    fn config_stands_in_for_script_and_out_file() {
+        let args = Args::try_parse_from(["rtk", "--config", "rtk.toml", "--parallel"]).unwrap();
+        assert_eq!(args.config.as_deref(), Some(Path::new("rtk.toml")));
+        assert!(args.parallel);
+
+        assert!(
+            Args::try_parse_from(["rtk", "--config", "rtk.toml", "--script", "a.lua"]).is_err()
+        );
+        assert!(
+            Args::try_parse_from([
+                "rtk",
+                "--script",
+                "bindings.lua",
+                "--out-file",
+                "bindings.ts",
+                "--parallel",
+            ])
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn check_script_reports_syntax_errors_with_the_line() {
+        let dir = std::env::temp_dir().join(format!("rtk-check-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let valid = dir.join("valid.lua");
+        std::fs::write(&valid, "rtk.version(\"1.2.3\")\nrtk.emit(\"hi\")\n").unwrap();
+        let invalid = dir.join("invalid.lua");
+        std::fs::write(&invalid, "rtk.version(\"1.2.3\")\nlocal x = )\n").unwrap();
+
+        let valid_result = check_script(&valid);
+        let invalid_result = check_script(&invalid);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        valid_result.unwrap();
+        let err = invalid_result.unwrap_err().to_string();
+        assert!(
+            err.starts_with(&format!("{}:2:", invalid.display())),
+            "unexpected error: {err}"
+        );
+    }
+
+    #[test]
+    fn cargo_check_command_forwards_deny_warnings() {
+        let deny_warnings_env = |extra_args: &[&str]| {
+            let args = Args::parse_from(
+                [
+                    "rtk",
+                    "--script",
+                    "bindings.lua",
+                    "--out-file",
+                    "bindings.ts",
+                ]
+                .iter()
+                .chain(extra_args),
+            );
+            let cmd = cargo_check_command(
+                &args,
+                Path::new("/home/jack/.cargo/bin/rtk-rustc-driver"),
+                &[],
+            );
+            cmd.get_envs()
+                .find(|(key, _)| *key == "RTK_DENY_WARNINGS")
+                .and_then(|(_, value)| value)
+                .map(|value| value.to_os_string())
+        };
+
+        assert_eq!(deny_warnings_env(&["--deny-warnings"]), Some("1".into()));
+        assert_eq!(deny_warnings_env(&[]), None);
+    }
+}
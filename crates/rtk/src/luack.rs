@@ -1,22 +1,26 @@
-use std::sync::{
-    Arc, Mutex,
-    atomic::{AtomicBool, Ordering},
-};
-
 use anyhow::Context;
-use rtk_lua::{RtkLuaScriptExecutor, RtkRustcDriverVersion};
 
-pub fn ck_lua(script: &str) -> anyhow::Result<()> {
+use crate::versioning::{PreflightFatalError, PreflightRtkVersioner, execute_preflight};
+
+/// Check the script for errors without a crate to run it over, referring to it as `script_name` in
+/// errors. Syntax errors are always reported, as `<script_name>:<line>: <message>`
+pub fn ck_lua(script: &str, script_name: &str) -> anyhow::Result<()> {
     let v = PreflightRtkVersioner::default();
     let lua = rtk_lua::RtkLua::new(v.clone()).context("failed to create Lua instance")?;
 
+    lua.check_syntax_named(script, script_name)?;
+
     // we can deliberately ignore an error here, since its very possible the script execution will
     // fail if the user currently is on a different version of the cli where the `rtk_lua` api is
     // different. we don't actually care about errors, we just need to extract the version so as
-    // long as the error occured after the version was set we're fine
-    let _ = lua.execute(script);
+    // long as the error occured after the version was set we're fine. a deliberate fatal error
+    // from the script is reported though
+    match execute_preflight(|| lua.execute_named(script, script_name)) {
+        Err(e) if e.is::<PreflightFatalError>() => return Err(e),
+        _ => {}
+    }
 
-    if v.version_double_set_attempted.load(Ordering::Relaxed) {
+    if v.version_double_set_attempted() {
         return Err(anyhow::anyhow!(
             "Lua script attempted to set the desired version multiple times, the desired version should be specified first and once"
         ));
@@ -25,60 +29,17 @@ pub fn ck_lua(script: &str) -> anyhow::Result<()> {
     Ok(())
 }
 
-/// Before running the Lua script against the real rustc driver, we do a dry run of the lua script
-/// to check it for errors and to extract the required version of the Rustc driver
-#[derive(Clone, Default)]
-struct PreflightRtkVersioner {
-    version: Arc<Mutex<Option<RtkRustcDriverVersion>>>,
-    debug_version: Arc<Mutex<Option<RtkRustcDriverVersion>>>,
-    version_double_set_attempted: Arc<AtomicBool>,
-}
-
-impl RtkLuaScriptExecutor for PreflightRtkVersioner {
-    fn intake_version(&self, version: RtkRustcDriverVersion) {
-        let mut curr_version = self.version.lock().unwrap();
-        if curr_version.is_some() {
-            self.version_double_set_attempted
-                .store(true, Ordering::Relaxed);
-        } else {
-            curr_version.replace(version);
-        }
-    }
-
-    fn intake_debug_version(&self, version: RtkRustcDriverVersion) {
-        let mut curr_version = self.debug_version.lock().unwrap();
-        if curr_version.is_some() {
-            self.version_double_set_attempted
-                .store(true, Ordering::Relaxed);
-        }
-        curr_version.replace(version);
-    }
-
-    fn query_method_calls(&self, _query: rtk_lua::MethodCallQuery) -> Vec<rtk_lua::MethodCall> {
-        vec![]
-    }
-
-    fn query_functions(&self, _query: rtk_lua::Location) -> Vec<rtk_lua::FunctionTypeValue> {
-        vec![]
-    }
-
-    fn query_trait_impls(&self, _query: rtk_lua::Location) -> Vec<rtk_lua::TraitImpl> {
-        vec![]
-    }
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    fn query_function_calls(&self, _query: rtk_lua::Location) -> Vec<rtk_lua::FunctionCall> {
-        vec![]
-    }
-
-    fn log_note(&self, _msg: String) {}
-
-    fn log_warn(&self, _msg: String) {}
+    #[test]
+    fn fatal_errors_are_reported_but_other_errors_are_not() {
+        let err = ck_lua(r#"rtk.fatal_error("no routes were found")"#, "api.lua")
+            .unwrap_err()
+            .to_string();
+        assert_eq!(err, "Lua script raised a fatal error: no routes were found");
 
-    fn log_error(&self, _msg: String) {}
-
-    fn log_fatal_error(&self, msg: String) -> ! {
-        panic!("fatal error hit in preflight script check: {msg}")
+        assert!(ck_lua(r#"error("something went wrong")"#, "api.lua").is_ok());
     }
-
-    fn emit(&self, _text: String) {}
 }
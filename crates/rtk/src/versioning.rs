@@ -1,6 +1,7 @@
 use std::{
+    collections::HashMap,
+    ffi::OsString,
     path::PathBuf,
-    process::Command,
     sync::{
         Arc, Mutex,
         atomic::{AtomicBool, Ordering},
@@ -10,7 +11,7 @@ use std::{
 use anyhow::Context;
 use rtk_lua::{RtkLuaScriptExecutor, RtkRustcDriverVersion};
 
-use crate::DRIVER_NAME;
+use crate::{DRIVER_NAME, cargo_command};
 
 /// Extract the desired driver version from the script, returning the target release version and
 /// optionally the desired debug version
@@ -23,10 +24,11 @@ pub fn desired_version_for_script(
     // we can deliberately ignore an error here, since its very possible the script execution will
     // fail if the user currently is on a different version of the cli where the `rtk_lua` api is
     // different. we don't actually care about errors, we just need to extract the version so as
-    // long as the error occured after the version was set we're fine
-    let _ = lua.execute(script);
+    // long as the error occured after the version was set we're fine. we do hold onto it though,
+    // since if no version was set it's likely the reason why
+    let script_result = execute_preflight(|| lua.execute(script));
 
-    if v.version_double_set_attempted.load(Ordering::Relaxed) {
+    if v.version_double_set_attempted() {
         return Err(anyhow::anyhow!(
             "Lua script attempted to set the desired version multiple times, the desired version should be specified first and once"
         ));
@@ -37,22 +39,61 @@ pub fn desired_version_for_script(
         .lock()
         .unwrap()
         .take()
-        .ok_or_else(|| anyhow::anyhow!("No version was set in the Lua script"))?;
+        .ok_or_else(|| match &script_result {
+            Ok(()) => anyhow::anyhow!("No version was set in the Lua script"),
+            Err(e) => anyhow::anyhow!(
+                "No version was set in the Lua script (script execution error: {e})"
+            ),
+        })?;
 
     let debug_version = v.debug_version.lock().unwrap().take();
 
     Ok((release_version, debug_version))
 }
 
+/// Raised by the preflight `log_fatal_error` to unwind out of the script, carrying the script's
+/// message. It's raised with `resume_unwind` so no panic message or backtrace is printed
+#[derive(Debug)]
+pub(crate) struct PreflightFatalError(String);
+
+impl std::fmt::Display for PreflightFatalError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Lua script raised a fatal error: {}", self.0)
+    }
+}
+
+impl std::error::Error for PreflightFatalError {}
+
+/// Execute the script in the preflight executor through `execute`, turning a fatal error raised by
+/// the script into a [`PreflightFatalError`] rather than letting it unwind any further
+pub(crate) fn execute_preflight(
+    execute: impl FnOnce() -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    match std::panic::catch_unwind(std::panic::AssertUnwindSafe(execute)) {
+        Ok(result) => result,
+        Err(payload) => match payload.downcast::<PreflightFatalError>() {
+            Ok(fatal) => Err((*fatal).into()),
+            Err(payload) => std::panic::resume_unwind(payload),
+        },
+    }
+}
+
 /// Before running the Lua script against the real rustc driver, we do a dry run of the lua script
 /// to check it for errors and to extract the required version of the Rustc driver
 #[derive(Clone, Default)]
-struct PreflightRtkVersioner {
+pub(crate) struct PreflightRtkVersioner {
     version: Arc<Mutex<Option<RtkRustcDriverVersion>>>,
     debug_version: Arc<Mutex<Option<RtkRustcDriverVersion>>>,
     version_double_set_attempted: Arc<AtomicBool>,
 }
 
+impl PreflightRtkVersioner {
+    /// Whether the script tried to set either version more than once
+    pub(crate) fn version_double_set_attempted(&self) -> bool {
+        self.version_double_set_attempted.load(Ordering::Relaxed)
+    }
+}
+
 impl RtkLuaScriptExecutor for PreflightRtkVersioner {
     fn intake_version(&self, version: RtkRustcDriverVersion) {
         let mut curr_version = self.version.lock().unwrap();
@@ -73,22 +114,103 @@ impl RtkLuaScriptExecutor for PreflightRtkVersioner {
         curr_version.replace(version);
     }
 
+    fn primary_crate_name(&self) -> String {
+        // the crate is only known once cargo runs the driver over it
+        "unknown".to_string()
+    }
+
+    fn primary_crate_version(&self) -> Option<String> {
+        None
+    }
+
     fn query_method_calls(&self, _query: rtk_lua::MethodCallQuery) -> Vec<rtk_lua::MethodCall> {
         vec![]
     }
 
+    fn query_method_calls_batch(
+        &self,
+        queries: Vec<rtk_lua::MethodCallQuery>,
+    ) -> Vec<Vec<rtk_lua::MethodCall>> {
+        queries.iter().map(|_| vec![]).collect()
+    }
+
     fn query_functions(&self, _query: rtk_lua::Location) -> Vec<rtk_lua::FunctionTypeValue> {
         vec![]
     }
 
+    fn query_functions_in_module(
+        &self,
+        _module: rtk_lua::Location,
+    ) -> Vec<rtk_lua::FunctionTypeValue> {
+        vec![]
+    }
+
     fn query_trait_impls(&self, _query: rtk_lua::Location) -> Vec<rtk_lua::TraitImpl> {
         vec![]
     }
 
+    fn query_all_trait_impls(&self, _query: rtk_lua::Location) -> Vec<rtk_lua::TraitImpl> {
+        vec![]
+    }
+
+    fn query_impls_for_type(&self, _query: rtk_lua::Location) -> Vec<rtk_lua::Location> {
+        vec![]
+    }
+
+    fn query_trait_defs(&self, _query: rtk_lua::Location) -> Vec<rtk_lua::TraitDef> {
+        vec![]
+    }
+
     fn query_function_calls(&self, _query: rtk_lua::Location) -> Vec<rtk_lua::FunctionCall> {
         vec![]
     }
 
+    fn query_struct_literals(&self, _query: rtk_lua::Location) -> Vec<rtk_lua::StructLiteralSite> {
+        vec![]
+    }
+
+    fn query_macro_calls(&self, _name: String) -> Vec<rtk_lua::MacroCall> {
+        vec![]
+    }
+
+    fn query_serde_fields(&self, _query: rtk_lua::Location) -> Vec<rtk_lua::SerdeField> {
+        vec![]
+    }
+
+    fn query_constants_by_type(
+        &self,
+        _type_filter: rtk_lua::TypeValue,
+    ) -> Vec<rtk_lua::ConstantValue> {
+        vec![]
+    }
+
+    fn query_function_calls_in_function(
+        &self,
+        _outer_location: rtk_lua::Location,
+        _call_location: rtk_lua::Location,
+    ) -> Vec<rtk_lua::FunctionCall> {
+        vec![]
+    }
+
+    fn query_closures_with_capture(
+        &self,
+        _within_location: rtk_lua::Location,
+        _capture_name: String,
+    ) -> Vec<rtk_lua::ClosureTypeValue> {
+        vec![]
+    }
+
+    fn query_function_calls_grouped(
+        &self,
+        _module: rtk_lua::Location,
+    ) -> HashMap<rtk_lua::Location, Vec<rtk_lua::FunctionCall>> {
+        HashMap::new()
+    }
+
+    fn query_modules(&self) -> Vec<rtk_lua::Location> {
+        vec![]
+    }
+
     fn log_note(&self, _msg: String) {}
 
     fn log_warn(&self, _msg: String) {}
@@ -96,26 +218,44 @@ impl RtkLuaScriptExecutor for PreflightRtkVersioner {
     fn log_error(&self, _msg: String) {}
 
     fn log_fatal_error(&self, msg: String) -> ! {
-        panic!("fatal error hit in preflight script check: {msg}")
+        std::panic::resume_unwind(Box::new(PreflightFatalError(msg)))
     }
 
+    // errors are discarded during preflight, so there's never anything to abort on
+    fn abort_if_errors(&self) {}
+
     fn emit(&self, _text: String) {}
+
+    fn emit_with_priority(&self, _priority: i64, _text: String) {}
+
+    fn emit_unique(&self, _text: String) {}
 }
 
-pub fn install_rtk_rustc_driver(version: RtkRustcDriverVersion) -> anyhow::Result<()> {
+/// Installs the driver at `version` unless it's the one already installed, with `env` set on top of
+/// the environment of this process for every cargo command run
+pub fn install_rtk_rustc_driver(
+    version: RtkRustcDriverVersion,
+    toolchain: Option<&str>,
+    env: &[(&str, OsString)],
+) -> anyhow::Result<()> {
     let currently_installed_version = currently_installed_rtk_rustc_driver_version(
         #[cfg(test)]
         "",
+        #[cfg(not(test))]
+        env,
     )
     .context("failed to get installed version")?;
 
-    if currently_installed_version.as_ref() == Some(&version) {
+    if currently_installed_version
+        .as_ref()
+        .is_some_and(|installed| is_installed_version(installed, &version))
+    {
         return Ok(());
     }
 
     log::info!("missing desired version, installing rtk driver `{version}`");
 
-    let mut install_cmd_base = Command::new("cargo");
+    let mut install_cmd_base = cargo_command(toolchain, env);
     install_cmd_base.arg("install");
 
     match version {
@@ -132,6 +272,14 @@ pub fn install_rtk_rustc_driver(version: RtkRustcDriverVersion) -> anyhow::Resul
         RtkRustcDriverVersion::Local { path } => {
             install_cmd_base.arg("--path").arg(path);
         }
+        RtkRustcDriverVersion::Git { repo, rev } => {
+            install_cmd_base
+                .arg("--git")
+                .arg(repo)
+                .arg("--rev")
+                .arg(rev)
+                .arg(DRIVER_NAME);
+        }
     }
 
     install_cmd_base
@@ -160,16 +308,99 @@ pub fn install_rtk_rustc_driver(version: RtkRustcDriverVersion) -> anyhow::Resul
     Ok(())
 }
 
+/// Whether the `installed` driver is the `desired` one. Git revisions are compared by prefix, since
+/// a script may give a short commit hash where cargo lists the full one
+fn is_installed_version(
+    installed: &RtkRustcDriverVersion,
+    desired: &RtkRustcDriverVersion,
+) -> bool {
+    match (installed, desired) {
+        (
+            RtkRustcDriverVersion::Git {
+                repo: installed_repo,
+                rev: installed_rev,
+            },
+            RtkRustcDriverVersion::Git { repo, rev },
+        ) => {
+            installed_repo == repo
+                && !installed_rev.is_empty()
+                && !rev.is_empty()
+                && (installed_rev.starts_with(rev.as_str())
+                    || rev.starts_with(installed_rev.as_str()))
+        }
+        _ => installed == desired,
+    }
+}
+
+/// The absolute path of the installed driver binary, so it can be handed to cargo as the rustc
+/// wrapper without relying on cargo's bin directory being on `PATH`. Variables given in `env` are
+/// read ahead of the environment of this process, the same as cargo sees them
+pub fn installed_rtk_rustc_driver_path(env: &[(&str, OsString)]) -> anyhow::Result<PathBuf> {
+    let var_os = |key: &str| {
+        env.iter()
+            .rev()
+            .find(|(env_key, _)| *env_key == key)
+            .map(|(_, value)| value.clone())
+            .or_else(|| std::env::var_os(key))
+    };
+
+    let home = var_os(if cfg!(windows) { "USERPROFILE" } else { "HOME" });
+    let bin_dir = cargo_install_bin_dir(
+        var_os("CARGO_INSTALL_ROOT").map(PathBuf::from),
+        var_os("CARGO_HOME").map(PathBuf::from),
+        home.map(PathBuf::from),
+    )
+    .ok_or_else(|| {
+        anyhow::anyhow!(
+            "couldn't work out where cargo installs binaries, set `CARGO_HOME` to point at your cargo directory"
+        )
+    })?;
+
+    let driver_path = bin_dir.join(format!("{DRIVER_NAME}{}", std::env::consts::EXE_SUFFIX));
+    if !driver_path.is_file() {
+        return Err(anyhow::anyhow!(
+            "the RTK Rustc driver was expected at `{}` but isn't there. If cargo installs binaries \
+             somewhere else, set `CARGO_INSTALL_ROOT` to that directory's parent",
+            driver_path.display()
+        ));
+    }
+
+    Ok(driver_path)
+}
+
+/// The directory `cargo install` puts binaries in, with the same precedence cargo gives
+/// `CARGO_INSTALL_ROOT`, then `CARGO_HOME`, then `~/.cargo`
+fn cargo_install_bin_dir(
+    install_root: Option<PathBuf>,
+    cargo_home: Option<PathBuf>,
+    home: Option<PathBuf>,
+) -> Option<PathBuf> {
+    install_root
+        .or(cargo_home)
+        .or_else(|| home.map(|home| home.join(".cargo")))
+        .map(|root| root.join("bin"))
+}
+
 fn currently_installed_rtk_rustc_driver_version(
     #[cfg(test)] installed_crates: &str,
+    #[cfg(not(test))] env: &[(&str, OsString)],
 ) -> anyhow::Result<Option<RtkRustcDriverVersion>> {
     #[cfg(not(test))]
-    let installed_crates = Command::new("cargo")
-        .arg("install")
-        .arg("--list")
-        .output()
-        .context("failed to list installed cargo packages")?
-        .stdout;
+    let installed_crates = {
+        let output = cargo_command(None, env)
+            .arg("install")
+            .arg("--list")
+            .output()
+            .context("failed to list installed cargo packages")?;
+        if !output.status.success() {
+            return Err(anyhow::anyhow!(
+                "cargo install --list failed with status: {}",
+                output.status
+            ));
+        }
+
+        output.stdout
+    };
 
     #[cfg(not(test))]
     let installed_crates = String::from_utf8(installed_crates)
@@ -185,16 +416,42 @@ fn currently_installed_rtk_rustc_driver_version(
         }
     };
 
-    let maybe_local_path = rtk_rustc_driver_line
+    let maybe_source = rtk_rustc_driver_line
         .split_once("(")
         .and_then(|(_, path)| path.strip_suffix("):"));
 
-    if let Some(path) = maybe_local_path {
+    if let Some(path) = maybe_source {
         let path = path.trim();
         if path.is_empty() {
             return Ok(None);
         }
 
+        // git installs list the repo with the commit they were built from, e.g.
+        // `(https://github.com/org/rtk#abc1234)`
+        if path.starts_with("https://") || path.starts_with("git+") {
+            let (repo, rev) = path
+                .trim_start_matches("git+")
+                .split_once('#')
+                .ok_or_else(|| anyhow::anyhow!("failed to parse git revision from `{path}`"))?;
+
+            // the query holds the ref the driver was installed with, e.g. `?tag=v1.2.0`, while what
+            // follows the `#` is the commit it resolved to. The ref is what a script would ask for
+            let (repo, query) = repo
+                .split_once('?')
+                .map_or((repo, None), |(repo, query)| (repo, Some(query)));
+            let requested_ref = query.and_then(|query| {
+                query.split('&').find_map(|pair| {
+                    let (key, value) = pair.split_once('=')?;
+                    matches!(key, "rev" | "tag" | "branch").then_some(value)
+                })
+            });
+
+            return Ok(Some(RtkRustcDriverVersion::Git {
+                repo: repo.to_string(),
+                rev: requested_ref.unwrap_or(rev).to_string(),
+            }));
+        }
+
         return Ok(Some(RtkRustcDriverVersion::Local {
             path: PathBuf::from(path),
         }));
@@ -326,6 +583,50 @@ mod tests {
         );
     }
 
+    #[test]
+    fn missing_version_reports_script_error() {
+        let script = r#"
+            error("something went wrong")
+            rtk.version("1.2.3");
+        "#;
+
+        let result = desired_version_for_script(script);
+        assert!(result.is_err());
+
+        let err = result.unwrap_err().to_string();
+        assert!(err.starts_with("No version was set in the Lua script (script execution error: "));
+        assert!(err.contains("something went wrong"));
+    }
+
+    #[test]
+    fn fatal_error_before_version_is_a_readable_error() {
+        let script = r#"
+            rtk.fatal_error("no routes were found")
+            rtk.version("1.2.3");
+        "#;
+
+        let err = desired_version_for_script(script).unwrap_err().to_string();
+        assert!(err.contains("Lua script raised a fatal error: no routes were found"));
+    }
+
+    #[test]
+    fn fatal_error_after_version_keeps_version() {
+        let script = r#"
+            rtk.version("1.2.3");
+            rtk.fatal_error("no routes were found")
+        "#;
+
+        let (version, _) = desired_version_for_script(script).unwrap();
+        assert_eq!(
+            version,
+            RtkRustcDriverVersion::CratesIo {
+                major: 1,
+                minor: 2,
+                patch: 3
+            }
+        );
+    }
+
     #[test]
     fn test_parse_cargo_installed_version_local() {
         let version = currently_installed_rtk_rustc_driver_version(
@@ -344,6 +645,124 @@ rtk-rustc-driver v0.1.0 (/Developer/rtk/crates/rtk-rustc-driver):
         );
     }
 
+    #[test]
+    fn cargo_install_bin_dir_follows_cargo_precedence() {
+        let root = || Some(PathBuf::from("/opt/cargo-root"));
+        let cargo_home = || Some(PathBuf::from("/data/cargo"));
+        let home = || Some(PathBuf::from("/home/jack"));
+
+        assert_eq!(
+            cargo_install_bin_dir(root(), cargo_home(), home()),
+            Some(PathBuf::from("/opt/cargo-root/bin"))
+        );
+        assert_eq!(
+            cargo_install_bin_dir(None, cargo_home(), home()),
+            Some(PathBuf::from("/data/cargo/bin"))
+        );
+        assert_eq!(
+            cargo_install_bin_dir(None, None, home()),
+            Some(PathBuf::from("/home/jack/.cargo/bin"))
+        );
+        assert_eq!(cargo_install_bin_dir(None, None, None), None);
+    }
+
+    #[test]
+    fn test_parse_cargo_installed_version_git() {
+        let version = currently_installed_rtk_rustc_driver_version(
+            r#"
+rtk-rustc-driver v0.1.0 (https://github.com/org/rtk#abc1234):
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            version,
+            Some(RtkRustcDriverVersion::Git {
+                repo: "https://github.com/org/rtk".to_string(),
+                rev: "abc1234".to_string(),
+            })
+        );
+
+        let version = currently_installed_rtk_rustc_driver_version(
+            r#"
+rtk-rustc-driver v0.1.0 (git+https://github.com/org/rtk?rev=abc1234#abc1234def5678abc1234def5678abc1234def56):
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            version,
+            Some(RtkRustcDriverVersion::Git {
+                repo: "https://github.com/org/rtk".to_string(),
+                rev: "abc1234".to_string(),
+            })
+        );
+
+        let version = currently_installed_rtk_rustc_driver_version(
+            r#"
+rtk-rustc-driver v0.1.0 (git+https://github.com/org/rtk?tag=v1.2.0#abc1234def5678abc1234def5678abc1234def56):
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            version,
+            Some(RtkRustcDriverVersion::Git {
+                repo: "https://github.com/org/rtk".to_string(),
+                rev: "v1.2.0".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn installed_git_revisions_match_by_prefix() {
+        let git = |repo: &str, rev: &str| RtkRustcDriverVersion::Git {
+            repo: repo.to_string(),
+            rev: rev.to_string(),
+        };
+        let installed = git(
+            "https://github.com/org/rtk",
+            "abc1234def5678abc1234def5678abc1234def56",
+        );
+
+        assert!(is_installed_version(
+            &installed,
+            &git("https://github.com/org/rtk", "abc1234")
+        ));
+        assert!(!is_installed_version(
+            &installed,
+            &git("https://github.com/org/rtk", "def5678")
+        ));
+        assert!(!is_installed_version(
+            &installed,
+            &git("https://github.com/other/rtk", "abc1234")
+        ));
+        assert!(!is_installed_version(
+            &installed,
+            &git("https://github.com/org/rtk", "")
+        ));
+    }
+
+    #[test]
+    fn test_desired_version_for_script_git() {
+        let script = r#"
+            rtk.version("git:https://github.com/org/rtk#abc1234");
+        "#;
+
+        let (release, _debug) = desired_version_for_script(script).unwrap();
+        assert_eq!(
+            release.to_string(),
+            "git:https://github.com/org/rtk#abc1234"
+        );
+        assert_eq!(
+            release,
+            RtkRustcDriverVersion::Git {
+                repo: "https://github.com/org/rtk".to_string(),
+                rev: "abc1234".to_string(),
+            }
+        );
+    }
+
     #[test]
     fn test_parse_cargo_installed_version_crates_io() {
         let version = currently_installed_rtk_rustc_driver_version(
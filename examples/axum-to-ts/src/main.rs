@@ -5,10 +5,12 @@ use axum::{
 };
 use serde::{Deserialize, Serialize};
 
+const USER_PATH: &str = "/user";
+
 #[tokio::main]
 async fn main() {
     let app = Router::new()
-        .route("/user", post(add_user))
+        .route(USER_PATH, post(add_user))
         .route("/user/{id}", get(get_user));
 
     let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await.unwrap();
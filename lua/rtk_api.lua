@@ -9,8 +9,35 @@ rtk = {}
 ---@field location Location
 ---@field args Value[]
 ---@field in_item_id string
+---@field is_in_test_module boolean
 local FunctionCall = {}
 
+---@class StructLiteralSite
+---@field location Location
+---@field fields StructLiteralField[]
+---@field in_item_id string
+local StructLiteralSite = {}
+
+---@class MacroCall
+---@field name string
+---@field text string
+---@field args_text string
+---@field in_item_id string
+local MacroCall = {}
+
+---@class ConstantValue
+---@field location Location
+---@field type_value TypeValue
+---@field value Value|nil
+---@field doc_comment string|nil
+---@field attributes Attribute[]
+local ConstantValue = {}
+
+---@class SerdeField
+---@field name string
+---@field field StructTypeValueField
+local SerdeField = {}
+
 ---@class FunctionTypeValue
 ---@field location Location
 ---@field args_struct StructTypeValue
@@ -19,14 +46,34 @@ local FunctionCall = {}
 ---@field attributes Attribute[]
 ---@field doc_comment string|nil
 ---@field is_async boolean
+---@field where_clause_text string[]
+---@field is_test boolean
+---@field is_cfg_test boolean
+---@field lifetime_count number
+---@field is_public boolean
+---@field visibility string
+---@field takes_no_args boolean
+---@field overrides_trait_method Location|nil
+---@field overloads FunctionTypeValue[]
 local FunctionTypeValue = {}
 
 ---@class TraitImpl
 ---@field trait_location Location
+---@field trait_generic_args TypeValue[]
 ---@field for_type TypeValue
+---@field for_type_attributes Attribute[]
 ---@field functions FunctionTypeValue[]
 local TraitImpl = {}
 
+---@class TraitDef
+---@field location Location
+---@field doc_comment string|nil
+---@field attributes Attribute[]
+---@field functions FunctionTypeValue[]
+---@field associated_types string[]
+---@field associated_consts ConstantValue[]
+local TraitDef = {}
+
 ---@alias RtkRustcDriverVersionCratesIoLatest string
 
 ---@alias RtkRustcDriverVersionCratesIo string
@@ -35,16 +82,27 @@ local TraitImpl = {}
 
 ---@alias RtkRustcDriverVersion RtkRustcDriverVersionCratesIoLatest|RtkRustcDriverVersionCratesIo|RtkRustcDriverVersionLocal
 
+---@alias QueryEmptyBehaviorIgnore string
+
+---@alias QueryEmptyBehaviorWarnOnEmpty string
+
+---@alias QueryEmptyBehaviorErrorOnEmpty string
+
+---@alias QueryEmptyBehavior QueryEmptyBehaviorIgnore|QueryEmptyBehaviorWarnOnEmpty|QueryEmptyBehaviorErrorOnEmpty
+
 ---@class MethodCall
 ---@field origin MethodCallQuery
 ---@field args Value[]
 ---@field in_item_id string
+---@field is_in_test_module boolean
+---@field chain_depth number
 local MethodCall = {}
 
 ---@class Location
 ---@field crate_name string
 ---@field path string[]
 ---@field impl_block_number number|nil
+---@field is_local boolean
 local Location = {}
 
 ---@class ValueStringLiteral
@@ -72,16 +130,48 @@ local ValueFunctionCall = {}
 ---@field variant_data MethodCall
 local ValueMethodCall = {}
 
+---@class ValueStructLiteral
+---@field variant_name "StructLiteral"
+---@field variant_data StructLiteralData
+local ValueStructLiteral = {}
+
+---@class StructLiteralData
+---@field location Location
+---@field fields StructLiteralField[]
+local StructLiteralData = {}
+
+---@class StructLiteralField
+---@field name string
+---@field value Value
+local StructLiteralField = {}
+
+---@class ValueArrayLiteral
+---@field variant_name "ArrayLiteral"
+---@field variant_data Value[]
+local ValueArrayLiteral = {}
+
+---@class ValueEnumVariantLiteral
+---@field variant_name "EnumVariantLiteral"
+---@field variant_data EnumVariantLiteralData
+local ValueEnumVariantLiteral = {}
+
+---@class EnumVariantLiteralData
+---@field location Location
+---@field variant string
+---@field inner Value|nil
+local EnumVariantLiteralData = {}
+
 ---@class ValueType
 ---@field variant_name "Type"
 ---@field variant_data TypeValue
 local ValueType = {}
 
----@alias Value ValueStringLiteral|ValueIntegerLiteral|ValueFloatLiteral|ValueFunctionCall|ValueMethodCall|ValueType
+---@alias Value ValueStringLiteral|ValueIntegerLiteral|ValueFloatLiteral|ValueFunctionCall|ValueMethodCall|ValueStructLiteral|ValueArrayLiteral|ValueEnumVariantLiteral|ValueType
 
 ---@class MethodCallQuery
 ---@field parent MethodCallQuery|nil
 ---@field location Location
+---@field receiver_type_filter Location|nil
 local MethodCallQuery = {}
 
 ---@class TypeValueString
@@ -149,6 +239,11 @@ local TypeValueI128 = {}
 ---@field variant_data nil
 local TypeValueIsize = {}
 
+---@class TypeValueF16
+---@field variant_name "F16"
+---@field variant_data nil
+local TypeValueF16 = {}
+
 ---@class TypeValueF32
 ---@field variant_name "F32"
 ---@field variant_data nil
@@ -159,15 +254,26 @@ local TypeValueF32 = {}
 ---@field variant_data nil
 local TypeValueF64 = {}
 
+---@class TypeValueF128
+---@field variant_name "F128"
+---@field variant_data nil
+local TypeValueF128 = {}
+
 ---@class TypeValueBool
 ---@field variant_name "Bool"
 ---@field variant_data nil
 local TypeValueBool = {}
 
----@class TypeValueHashMap
----@field variant_name "HashMap"
----@field variant_data TypeValue
-local TypeValueHashMap = {}
+---@class TypeValueMap
+---@field variant_name "Map"
+---@field variant_data MapData
+local TypeValueMap = {}
+
+---@class MapData
+---@field key TypeValue
+---@field value TypeValue
+---@field is_ordered boolean
+local MapData = {}
 
 ---@class TypeValueVec
 ---@field variant_name "Vec"
@@ -176,9 +282,14 @@ local TypeValueVec = {}
 
 ---@class TypeValueResult
 ---@field variant_name "Result"
----@field variant_data TypeValue
+---@field variant_data ResultData
 local TypeValueResult = {}
 
+---@class ResultData
+---@field ok TypeValue
+---@field err TypeValue
+local ResultData = {}
+
 ---@class TypeValueStruct
 ---@field variant_name "Struct"
 ---@field variant_data StructTypeValue
@@ -204,30 +315,97 @@ local TypeValueFunction = {}
 ---@field variant_data TypeValue
 local TypeValueOption = {}
 
+---@class TypeValueNonZero
+---@field variant_name "NonZero"
+---@field variant_data TypeValue
+local TypeValueNonZero = {}
+
 ---@class TypeValueTuple
 ---@field variant_name "Tuple"
 ---@field variant_data TypeValue[]
 local TypeValueTuple = {}
 
+---@class TypeValueRawPointer
+---@field variant_name "RawPointer"
+---@field variant_data RawPointerData
+local TypeValueRawPointer = {}
+
+---@class RawPointerData
+---@field mutable boolean
+---@field inner TypeValue
+local RawPointerData = {}
+
 ---@class TypeValueRecursiveRef
 ---@field variant_name "RecursiveRef"
 ---@field variant_data Location
 local TypeValueRecursiveRef = {}
 
----@alias TypeValue TypeValueString|TypeValueU8|TypeValueU16|TypeValueU32|TypeValueU64|TypeValueU128|TypeValueUsize|TypeValueI8|TypeValueI16|TypeValueI32|TypeValueI64|TypeValueI128|TypeValueIsize|TypeValueF32|TypeValueF64|TypeValueBool|TypeValueHashMap|TypeValueVec|TypeValueResult|TypeValueStruct|TypeValueEnum|TypeValueClosure|TypeValueFunction|TypeValueOption|TypeValueTuple|TypeValueRecursiveRef
+---@class TypeValueGeneric
+---@field variant_name "Generic"
+---@field variant_data string
+local TypeValueGeneric = {}
+
+---@class TypeValueImplTrait
+---@field variant_name "ImplTrait"
+---@field variant_data ImplTraitData
+local TypeValueImplTrait = {}
+
+---@class ImplTraitData
+---@field bound Location
+local ImplTraitData = {}
+
+---@class TypeValueUnsupported
+---@field variant_name "Unsupported"
+---@field variant_data UnsupportedData
+local TypeValueUnsupported = {}
+
+---@class UnsupportedData
+---@field rust_type string
+local UnsupportedData = {}
+
+---@alias TypeValue TypeValueString|TypeValueU8|TypeValueU16|TypeValueU32|TypeValueU64|TypeValueU128|TypeValueUsize|TypeValueI8|TypeValueI16|TypeValueI32|TypeValueI64|TypeValueI128|TypeValueIsize|TypeValueF16|TypeValueF32|TypeValueF64|TypeValueF128|TypeValueBool|TypeValueMap|TypeValueVec|TypeValueResult|TypeValueStruct|TypeValueEnum|TypeValueClosure|TypeValueFunction|TypeValueOption|TypeValueNonZero|TypeValueTuple|TypeValueRawPointer|TypeValueRecursiveRef|TypeValueGeneric|TypeValueImplTrait|TypeValueUnsupported
 
 ---@class StructTypeValue
 ---@field location Location
 ---@field fields StructTypeValueField[]
 ---@field doc_comment string|nil
 ---@field attributes Attribute[]
+---@field kind StructKind
+---@field derives string[]
+---@field is_non_exhaustive boolean
+---@field newtype_inner TypeValue|nil
+---@field has_custom_serialize boolean
+---@field has_custom_deserialize boolean
+---@field type_hash integer
+---@field size integer|nil
+---@field align integer|nil
 local StructTypeValue = {}
 
+---@class StructKindNamed
+---@field variant_name "Named"
+---@field variant_data nil
+local StructKindNamed = {}
+
+---@class StructKindTuple
+---@field variant_name "Tuple"
+---@field variant_data nil
+local StructKindTuple = {}
+
+---@class StructKindUnit
+---@field variant_name "Unit"
+---@field variant_data nil
+local StructKindUnit = {}
+
+---@alias StructKind StructKindNamed|StructKindTuple|StructKindUnit
+
 ---@class StructTypeValueField
 ---@field name Either
 ---@field doc_comment string|nil
 ---@field attributes Attribute[]
 ---@field value TypeValue
+---@field is_optional boolean
+---@field has_serde_skip boolean
+---@field serde_rename string|nil
 local StructTypeValueField = {}
 
 ---@class ClosureTypeValue
@@ -240,11 +418,18 @@ local ClosureTypeValue = {}
 ---@field variants EnumTypeValueVariant[]
 ---@field doc_comment string|nil
 ---@field attributes Attribute[]
+---@field is_c_like boolean
+---@field derives string[]
+---@field serde_tag string|nil
+---@field is_non_exhaustive boolean
+---@field type_hash integer
 local EnumTypeValue = {}
 
 ---@class Attribute
 ---@field name string
 ---@field value_str string|nil
+---@field is_derive boolean
+---@field derived_traits string[]
 local Attribute = {}
 
 ---@class EitherLeft
@@ -264,6 +449,7 @@ local EitherRight = {}
 ---@field value TypeValue|nil
 ---@field doc_comment string|nil
 ---@field attributes Attribute[]
+---@field serde_rename string|nil
 local EnumTypeValueVariant = {}
 
 ---@param arg_1 RtkRustcDriverVersion
@@ -290,22 +476,177 @@ function rtk.error(arg_1) end
 ---@return nil
 function rtk.fatal_error(arg_1) end
 
+---@param arg_1 QueryEmptyBehavior
+---@return nil
+function rtk.set_empty_query_behavior(arg_1) end
+
+---@return nil
+function rtk.trace_enable() end
+
+---@return nil
+function rtk.trace_disable() end
+
+---@param arg_1 boolean
+---@return nil
+function rtk.set_include_hidden(arg_1) end
+
+---@return nil
+function rtk.abort_if_errors() end
+
+---@return string
+function rtk.crate_name() end
+
+---@return string|nil
+function rtk.crate_version() end
+
 ---@param arg_1 MethodCallQuery
 ---@return MethodCall[]
 function rtk.query_method_calls(arg_1) end
 
 ---@param arg_1 Location
+---@param arg_2 TypeValue[]|nil
 ---@return TraitImpl[]
-function rtk.query_trait_impls(arg_1) end
+function rtk.query_trait_impls(arg_1, arg_2) end
+
+---@param arg_1 Location
+---@return TraitImpl[]
+function rtk.query_all_trait_impls(arg_1) end
+
+---@param arg_1 Location
+---@return Location[]
+function rtk.query_impls_for_type(arg_1) end
+
+---@param arg_1 Location
+---@return TraitDef[]
+function rtk.query_trait_defs(arg_1) end
 
 ---@param arg_1 Location
 ---@return FunctionTypeValue[]
 function rtk.query_functions(arg_1) end
 
+---@param arg_1 Location
+---@return FunctionTypeValue[]
+function rtk.query_functions_in_module(arg_1) end
+
 ---@param arg_1 Location
 ---@return FunctionCall[]
 function rtk.query_function_calls(arg_1) end
 
+---@param arg_1 Location
+---@return StructLiteralSite[]
+function rtk.query_struct_literals(arg_1) end
+
+---@param arg_1 string
+---@return MacroCall[]
+function rtk.query_macro_calls(arg_1) end
+
+---@param arg_1 Location
+---@return SerdeField[]
+function rtk.query_serde_fields(arg_1) end
+
+---@param arg_1 TypeValue
+---@return ConstantValue[]
+function rtk.query_constants_by_type(arg_1) end
+
+---@param arg_1 Location
+---@param arg_2 Location
+---@return FunctionCall[]
+function rtk.query_function_calls_in_function(arg_1, arg_2) end
+
+---@param arg_1 Location
+---@param arg_2 string
+---@return ClosureTypeValue[]
+function rtk.query_closures_with_capture(arg_1, arg_2) end
+
+---@param arg_1 Location
+---@return table<string, FunctionCall[]>
+function rtk.query_function_calls_grouped(arg_1) end
+
+---@return Location[]
+function rtk.query_modules() end
+
+---@param arg_1 Location
+---@return number
+function rtk.count_functions(arg_1) end
+
+---@param arg_1 MethodCallQuery
+---@return number
+function rtk.count_method_calls(arg_1) end
+
 ---@param arg_1 string
 ---@return nil
 function rtk.emit(arg_1) end
+
+---@param arg_1 number
+---@param arg_2 string
+---@return nil
+function rtk.emit_with_priority(arg_1, arg_2) end
+
+---@param arg_1 string
+---@return nil
+function rtk.emit_unique(arg_1) end
+
+---@param arg_1 Location
+---@return string
+function rtk.fmt_location(arg_1) end
+
+---@param arg_1 string
+---@param arg_2 string[]
+---@return string
+function rtk.join(arg_1, arg_2) end
+
+---@param arg_1 number
+---@param arg_2 string
+---@param arg_3 string|nil
+---@return string
+function rtk.indent(arg_1, arg_2, arg_3) end
+
+---@param arg_1 string
+---@return string
+function rtk.dedent(arg_1) end
+
+---@param arg_1 TypeValue
+---@return boolean
+function rtk.is_primitive(arg_1) end
+
+---@param arg_1 TypeValue
+---@return TypeValue
+function rtk.unwrap_inner(arg_1) end
+
+---@param arg_1 Attribute[]
+---@param arg_2 string
+---@return Attribute|nil
+function rtk.find_attribute(arg_1, arg_2) end
+
+---@param arg_1 StructTypeValue
+---@return string
+function rtk.generate_typescript_interface(arg_1) end
+
+---@param arg_1 EnumTypeValue
+---@param arg_2 { tag_field: string|nil, content_field: string|nil }|nil
+---@return string
+function rtk.generate_typescript_type(arg_1, arg_2) end
+
+---@param arg_1 string
+---@param arg_2 table
+---@return any
+function rtk.render(arg_1, arg_2) end
+
+---@param arg_1 table
+---@param arg_2 function
+---@return any
+function rtk.map(arg_1, arg_2) end
+
+---@param arg_1 table
+---@param arg_2 function
+---@return any
+function rtk.filter(arg_1, arg_2) end
+
+---@param arg_1 table
+---@param arg_2 function
+---@return any
+function rtk.flat_map(arg_1, arg_2) end
+
+---@param arg_1 table
+---@return any
+function rtk.query_method_calls_batch(arg_1) end
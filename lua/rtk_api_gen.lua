@@ -67,6 +67,13 @@ local function rust_type_value_into_lua_docstring_type(rt)
 
 	if rt.variant_name == "Struct" then
 		if rt.variant_data.location.crate_name == "mlua" then
+			local mlua_type_name = rt.variant_data.location.path[#rt.variant_data.location.path]
+			if mlua_type_name == "Table" then
+				return "table"
+			end
+			if mlua_type_name == "Function" then
+				return "function"
+			end
 			return ""
 		end
 
@@ -80,6 +87,10 @@ local function rust_type_value_into_lua_docstring_type(rt)
 		return rust_type_value_into_lua_docstring_type(rt.variant_data) .. "|nil"
 	end
 
+	if rt.variant_name == "NonZero" then
+		return rust_type_value_into_lua_docstring_type(rt.variant_data)
+	end
+
 	if rt.variant_name == "RecursiveRef" then
 		local type_name = rt.variant_data.path[#rt.variant_data.path]
 		return type_name
@@ -119,6 +130,32 @@ local api_fns = rtk.query_method_calls({
 	},
 })
 
+-- fallible functions are registered the same way, their closures just return a `Result`
+local fallible_api_fns = rtk.query_method_calls({
+	location = {
+		crate_name = "rtk_lua",
+		path = { "ext", "TableSetFnExt", "set_rtk_api_fallible_fn" },
+	},
+})
+
+for _, fn in ipairs(fallible_api_fns) do
+	table.insert(api_fns, fn)
+end
+
+-- these closures also take the lua instance as their first argument, which isn't exposed to scripts
+local with_lua_api_fns = rtk.query_method_calls({
+	location = {
+		crate_name = "rtk_lua",
+		path = { "ext", "TableSetFnExt", "set_rtk_api_fn_with_lua" },
+	},
+})
+
+for _, fn in ipairs(with_lua_api_fns) do
+	local closure = fn.args[3].variant_data.variant_data
+	table.remove(closure.args, 1)
+	table.insert(api_fns, fn)
+end
+
 for _, fn in ipairs(api_fns) do
 	-- first one is the second index since the first index is the lua object passed in which we don't care about
 	assert(fn.args[2].variant_name == "StringLiteral", "second argument to set_fn must be the global fn name")
@@ -139,8 +176,15 @@ for _, fn in ipairs(api_fns) do
 		"closure must have args"
 	)
 
+	-- functions taking multiple arguments do so through a single tuple argument on the rust side,
+	-- but from lua they are called with each element as its own argument
+	local closure_args = closure.args
+	if #closure_args == 1 and closure_args[1].variant_name == "Tuple" then
+		closure_args = closure_args[1].variant_data
+	end
+
 	local args_str = ""
-	for i, arg in ipairs(closure.args) do
+	for i, arg in ipairs(closure_args) do
 		deferred_function_defs_str = deferred_function_defs_str
 			.. string.format(
 				[[
@@ -151,7 +195,7 @@ for _, fn in ipairs(api_fns) do
 			)
 
 		args_str = args_str .. "arg_" .. i
-		if i < #closure.args then
+		if i < #closure_args then
 			args_str = args_str .. ", "
 		end
 	end